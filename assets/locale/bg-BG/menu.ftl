@@ -45,3 +45,12 @@ menu-tooltip-lan-connect = Свържи се към зададения сърв
 menu-title-lan-setup = Създай сървър
 menu-button-lan-join = Влез в друг
 menu-tooltip-lan-join = Свържи се към съществуващ сървър.
+
+results-title = Край на играта
+results-rank = Място
+results-player = Играч
+results-tiles = Разкрити
+results-mines = Мини
+results-lives = Животи
+results-eliminated = Елиминиран
+results-button-close = Главно меню
@@ -47,3 +47,12 @@ menu-tooltip-lan-connect = Connect to the specified server and start playing!
 menu-title-lan-setup = Create New Server
 menu-button-lan-join = Join Existing
 menu-tooltip-lan-join = Connect to an existing server instead.
+
+results-title = Game Over
+results-rank = Rank
+results-player = Player
+results-tiles = Explored
+results-mines = Mines Hit
+results-lives = Lives
+results-eliminated = Eliminated
+results-button-close = Main Menu
@@ -0,0 +1,87 @@
+use mw_common::prelude::*;
+
+use clap::{Args as ClapArgs, Parser, Subcommand};
+
+/// Tool for generating the TLS certificates used by MineWars (DER format)
+///
+/// Certificates and keys are written as `<PREFIX>.cert.der` and `<PREFIX>.key.der`.
+#[derive(Debug, Parser)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Generate a new self-signed CA certificate
+    Ca(CaArgs),
+    /// Generate a server certificate signed by a CA
+    Server(ServerArgs),
+    /// Generate a client certificate (for RPC, hostauth, players) signed by a CA
+    Client(SignArgs),
+    /// Generate all the certificates needed for a local development setup
+    DevSetup(DevSetupArgs),
+    /// Print information about a certificate
+    Inspect(InspectArgs),
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct CaArgs {
+    /// Where to write the new certificate and key
+    #[arg(value_name = "PREFIX")]
+    pub output: PathBuf,
+    /// Common Name of the CA
+    #[arg(short, long, default_value = "MineWars CA")]
+    pub name: String,
+    /// How long the certificate should be valid for
+    #[arg(short, long, default_value_t = 3650)]
+    pub days: u32,
+    /// Overwrite existing files
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct SignArgs {
+    /// Where to write the new certificate and key
+    #[arg(value_name = "PREFIX")]
+    pub output: PathBuf,
+    /// The CA to sign with
+    #[arg(short, long, value_name = "PREFIX")]
+    pub ca: PathBuf,
+    /// Common Name of the certificate
+    #[arg(short, long)]
+    pub name: String,
+    /// How long the certificate should be valid for
+    #[arg(short, long, default_value_t = 365)]
+    pub days: u32,
+    /// Overwrite existing files
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ServerArgs {
+    #[command(flatten)]
+    pub sign: SignArgs,
+    /// DNS name or IP address the server is reachable at (can be repeated)
+    #[arg(short, long, value_name = "NAME", required = true)]
+    pub san: Vec<String>,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct DevSetupArgs {
+    /// Directory to create the certificates in
+    #[arg(value_name = "DIR")]
+    pub dir: PathBuf,
+    /// Overwrite existing files
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct InspectArgs {
+    /// Certificate file (DER format)
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+}
@@ -0,0 +1,229 @@
+use mw_common::prelude::*;
+
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, SanType,
+};
+use time::OffsetDateTime;
+
+use crate::cli::{CaArgs, DevSetupArgs, ServerArgs, SignArgs};
+
+/// A certificate and its private key (both DER)
+pub struct CertKey {
+    pub cert: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+/// What a leaf certificate is going to be used for
+pub enum LeafKind {
+    /// TLS server, reachable at the given DNS names / IP addresses
+    Server(Vec<String>),
+    /// TLS client authentication
+    Client,
+}
+
+fn cert_path(prefix: &Path) -> PathBuf {
+    let mut s = prefix.as_os_str().to_owned();
+    s.push(".cert.der");
+    s.into()
+}
+
+fn key_path(prefix: &Path) -> PathBuf {
+    let mut s = prefix.as_os_str().to_owned();
+    s.push(".key.der");
+    s.into()
+}
+
+impl CertKey {
+    pub fn load(prefix: &Path) -> AnyResult<Self> {
+        let cert_path = cert_path(prefix);
+        let key_path = key_path(prefix);
+        let cert = std::fs::read(&cert_path)
+            .with_context(|| format!("Cannot read {:?}", cert_path))?;
+        let key = std::fs::read(&key_path)
+            .with_context(|| format!("Cannot read {:?}", key_path))?;
+        Ok(CertKey { cert, key })
+    }
+
+    pub fn save(&self, prefix: &Path, force: bool) -> AnyResult<()> {
+        let cert_path = cert_path(prefix);
+        let key_path = key_path(prefix);
+        if !force {
+            ensure_free(prefix)?;
+        }
+        std::fs::write(&cert_path, &self.cert)
+            .with_context(|| format!("Cannot write {:?}", cert_path))?;
+        std::fs::write(&key_path, &self.key)
+            .with_context(|| format!("Cannot write {:?}", key_path))?;
+        println!("Wrote {:?}, {:?}", cert_path, key_path);
+        Ok(())
+    }
+}
+
+/// Fail if saving to `prefix` would overwrite any files
+fn ensure_free(prefix: &Path) -> AnyResult<()> {
+    for path in [cert_path(prefix), key_path(prefix)] {
+        ensure!(!path.exists(), "{:?} already exists (use --force to overwrite)", path);
+    }
+    Ok(())
+}
+
+fn params(name: &str, days: u32) -> CertificateParams {
+    let mut params = CertificateParams::default();
+    let mut dn = DistinguishedName::new();
+    dn.push(DnType::CommonName, name);
+    params.distinguished_name = dn;
+    // a little slack for clocks that are behind
+    params.not_before = OffsetDateTime::now_utc() - time::Duration::hours(1);
+    params.not_after = OffsetDateTime::now_utc() + time::Duration::days(days.into());
+    params
+}
+
+/// Generate a new self-signed CA
+pub fn gen_ca(name: &str, days: u32) -> AnyResult<CertKey> {
+    let mut params = params(name, days);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    let cert = Certificate::from_params(params)?;
+    Ok(CertKey {
+        cert: cert.serialize_der()?,
+        key: cert.serialize_private_key_der(),
+    })
+}
+
+/// Generate a new certificate signed by `ca`
+pub fn gen_leaf(ca: &CertKey, name: &str, days: u32, kind: &LeafKind) -> AnyResult<CertKey> {
+    let ca_key = KeyPair::from_der(&ca.key)
+        .context("Cannot load the CA key")?;
+    let ca_params = CertificateParams::from_ca_cert_der(&ca.cert, ca_key)
+        .context("Cannot load the CA certificate")?;
+    let ca = Certificate::from_params(ca_params)?;
+
+    let mut params = params(name, days);
+    params.use_authority_key_identifier_extension = true;
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    match kind {
+        LeafKind::Server(sans) => {
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+            params.subject_alt_names = sans.iter().map(|san| {
+                match san.parse() {
+                    Ok(ip) => SanType::IpAddress(ip),
+                    Err(_) => SanType::DnsName(san.clone()),
+                }
+            }).collect();
+        }
+        LeafKind::Client => {
+            params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        }
+    }
+    let cert = Certificate::from_params(params)?;
+    Ok(CertKey {
+        cert: cert.serialize_der_with_signer(&ca)?,
+        key: cert.serialize_private_key_der(),
+    })
+}
+
+pub fn ca(args: &CaArgs) -> AnyResult<()> {
+    gen_ca(&args.name, args.days)?.save(&args.output, args.force)
+}
+
+pub fn server(args: &ServerArgs) -> AnyResult<()> {
+    let ca = CertKey::load(&args.sign.ca)?;
+    let kind = LeafKind::Server(args.san.clone());
+    gen_leaf(&ca, &args.sign.name, args.sign.days, &kind)?
+        .save(&args.sign.output, args.sign.force)
+}
+
+pub fn client(args: &SignArgs) -> AnyResult<()> {
+    let ca = CertKey::load(&args.ca)?;
+    gen_leaf(&ca, &args.name, args.days, &LeafKind::Client)?
+        .save(&args.output, args.force)
+}
+
+pub fn dev_setup(args: &DevSetupArgs) -> AnyResult<()> {
+    std::fs::create_dir_all(&args.dir)
+        .with_context(|| format!("Cannot create {:?}", args.dir))?;
+
+    // the clients currently always connect using this server name
+    let sans = vec![
+        "localhost".to_owned(),
+        "127.0.0.1".to_owned(),
+        "::1".to_owned(),
+        "auth.iyes.games".to_owned(),
+    ];
+    // (file name, common name, kind)
+    let leaves = [
+        ("host", "MineWars Dev Host", LeafKind::Server(sans.clone())),
+        ("hostrpc", "MineWars Dev Host RPC", LeafKind::Server(sans)),
+        ("hostauth", "MineWars Dev Host Auth Client", LeafKind::Client),
+        ("hostclient", "MineWars Dev Player", LeafKind::Client),
+        ("authclient", "MineWars Dev Auth Client", LeafKind::Client),
+    ];
+
+    // check everything up front, so that we don't leave behind a new CA
+    // that does not match the existing leaf certificates
+    let prefixes: Vec<PathBuf> = std::iter::once("ca")
+        .chain(leaves.iter().map(|(file, _, _)| *file))
+        .map(|file| args.dir.join(file))
+        .collect();
+    if !args.force {
+        for prefix in prefixes.iter() {
+            ensure_free(prefix)?;
+        }
+    }
+
+    let ca = gen_ca("MineWars Dev CA", 3650)?;
+    let mut certs = vec![];
+    for (_, name, kind) in leaves.iter() {
+        certs.push(gen_leaf(&ca, name, 3650, kind)?);
+    }
+    ca.save(&prefixes[0], true)?;
+    for (cert, prefix) in certs.iter().zip(prefixes[1..].iter()) {
+        cert.save(prefix, true)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use x509_parser::extensions::GeneralName;
+    use x509_parser::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn leaf_signed_by_ca() {
+        let ca = gen_ca("Test CA", 1).unwrap();
+        let kind = LeafKind::Server(vec!["localhost".to_owned(), "127.0.0.1".to_owned()]);
+        let leaf = gen_leaf(&ca, "Test Server", 1, &kind).unwrap();
+
+        let (_, ca_cert) = parse_x509_certificate(&ca.cert).unwrap();
+        let (_, cert) = parse_x509_certificate(&leaf.cert).unwrap();
+        assert!(ca_cert.is_ca());
+        assert!(!cert.is_ca());
+        assert_eq!(cert.issuer(), ca_cert.subject());
+        assert_eq!(cert.subject().to_string(), "CN=Test Server");
+        cert.verify_signature(Some(ca_cert.public_key())).unwrap();
+
+        let san = cert.subject_alternative_name().unwrap().unwrap();
+        assert_eq!(san.value.general_names, vec![
+            GeneralName::DNSName("localhost"),
+            GeneralName::IPAddress(&[127, 0, 0, 1]),
+        ]);
+        let eku = cert.extended_key_usage().unwrap().unwrap();
+        assert!(eku.value.server_auth);
+        assert!(!eku.value.client_auth);
+
+        let leaf = gen_leaf(&ca, "Test Client", 1, &LeafKind::Client).unwrap();
+        let (_, cert) = parse_x509_certificate(&leaf.cert).unwrap();
+        assert_eq!(cert.issuer(), ca_cert.subject());
+        assert!(cert.subject_alternative_name().unwrap().is_none());
+        let eku = cert.extended_key_usage().unwrap().unwrap();
+        assert!(eku.value.client_auth);
+        assert!(!eku.value.server_auth);
+    }
+}
@@ -0,0 +1,57 @@
+use mw_common::prelude::*;
+
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::*;
+
+use crate::cli::InspectArgs;
+
+pub fn inspect(args: &InspectArgs) -> AnyResult<()> {
+    let data = std::fs::read(&args.input)
+        .with_context(|| format!("Cannot read {:?}", args.input))?;
+    let (_, cert) = parse_x509_certificate(&data)
+        .map_err(|e| anyhow!("Not a DER certificate: {}", e))?;
+
+    println!("Subject: {}", cert.subject());
+    println!("Issuer: {}", cert.issuer());
+    println!("Serial: {}", cert.raw_serial_as_string());
+    println!("CA: {}", if cert.is_ca() { "yes" } else { "no" });
+
+    if let Some(san) = cert.subject_alternative_name()? {
+        let names: Vec<String> = san.value.general_names.iter().map(|name| {
+            match name {
+                GeneralName::DNSName(s) => s.to_string(),
+                GeneralName::IPAddress(b) if b.len() == 4 => {
+                    IpAddr::from(<[u8; 4]>::try_from(*b).unwrap()).to_string()
+                }
+                GeneralName::IPAddress(b) if b.len() == 16 => {
+                    IpAddr::from(<[u8; 16]>::try_from(*b).unwrap()).to_string()
+                }
+                other => format!("{:?}", other),
+            }
+        }).collect();
+        println!("Subject Alt Names: {}", names.join(", "));
+    }
+    if let Some(eku) = cert.extended_key_usage()? {
+        let mut usages = vec![];
+        if eku.value.server_auth {
+            usages.push("server");
+        }
+        if eku.value.client_auth {
+            usages.push("client");
+        }
+        println!("Usage: {}", usages.join(", "));
+    }
+
+    let validity = cert.validity();
+    println!("Not Before: {}", validity.not_before);
+    println!("Not After: {}", validity.not_after);
+    match validity.time_to_expiration() {
+        Some(left) => println!("Expires in {} days", left.whole_days()),
+        None if validity.not_before.timestamp() > ASN1Time::now().timestamp() => {
+            println!("Not yet valid")
+        }
+        None => println!("EXPIRED"),
+    }
+
+    Ok(())
+}
@@ -1,3 +1,22 @@
+use clap::Parser;
+
+mod cli;
+mod gen;
+mod inspect;
+
 fn main() {
-    println!("Hello, world!");
+    let args = cli::Args::parse();
+
+    let r = match args.command {
+        cli::Command::Ca(args) => gen::ca(&args),
+        cli::Command::Server(args) => gen::server(&args),
+        cli::Command::Client(args) => gen::client(&args),
+        cli::Command::DevSetup(args) => gen::dev_setup(&args),
+        cli::Command::Inspect(args) => inspect::inspect(&args),
+    };
+
+    if let Err(e) = r {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,161 @@
+use mw_common::prelude::*;
+
+use clap::{Args as ClapArgs, Parser, Subcommand};
+
+/// Tool for working with MineWars data files (gameplay streams, replays)
+#[derive(Debug, Parser)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Assemble a text file into a binary gameplay stream
+    Asm(AsmArgs),
+    /// Disassemble a binary gameplay stream (or replay file) into text
+    Disasm(DisasmArgs),
+    /// Print information about a replay file
+    Info(ReplayArgs),
+    /// Validate the checksums and frames of a replay file
+    Verify(ReplayArgs),
+    /// Render the map of a replay file as text
+    Map(MapArgs),
+    /// Render the map of a replay file (or a player's view of it) as an image
+    #[cfg(feature = "render")]
+    Render(RenderArgs),
+    /// Cut a replay file to a time window
+    Trim(TrimArgs),
+    /// Remove the player names from a replay file
+    Anonymize(RewriteArgs),
+    /// Extract the stream of a single player from a replay file
+    Extract(ExtractArgs),
+    /// Rewrite a replay file, with or without compression
+    Recompress(RewriteArgs),
+    /// Compare two replay files (or gameplay streams)
+    Diff(DiffArgs),
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct AsmArgs {
+    /// Assembly source file
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+    /// Where to write the binary output (default: stdout)
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct DisasmArgs {
+    /// Binary file to disassemble
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+    /// The input is a replay file; disassemble all of its frames
+    #[arg(short, long)]
+    pub replay: bool,
+    /// Where to write the text output (default: stdout)
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ReplayArgs {
+    /// Replay file
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct MapArgs {
+    /// Replay file
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+    /// Also show the locations of items (mines, decoys, ...)
+    #[arg(short, long)]
+    pub items: bool,
+}
+
+#[cfg(feature = "render")]
+#[derive(Debug, ClapArgs)]
+pub struct RenderArgs {
+    /// Replay file
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+    /// Where to write the image (SVG if the name ends in `.svg`, PNG otherwise)
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+    /// Render what the given player knows (0 for the spectator view),
+    /// instead of the initial map
+    #[arg(short, long, value_name = "PLID")]
+    pub view: Option<u8>,
+    /// Render the view as it was at this time, in seconds (default: at the end)
+    #[arg(short, long, value_name = "SECS")]
+    pub time: Option<f64>,
+    /// Also show the locations of items (mines, decoys, ...)
+    #[arg(short, long)]
+    pub items: bool,
+    /// Draw the borders between regions
+    #[arg(short, long)]
+    pub regions: bool,
+    /// Width of a tile, in pixels
+    #[arg(short = 's', long, value_name = "PX", default_value_t = 16)]
+    pub tile_size: u32,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct RewriteArgs {
+    /// Replay file
+    #[arg(value_name = "FILE")]
+    pub input: PathBuf,
+    /// Where to write the new replay file (default: stdout)
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    /// Do not LZ4-compress the output
+    #[arg(short, long)]
+    pub uncompressed: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct TrimArgs {
+    #[command(flatten)]
+    pub rewrite: RewriteArgs,
+    /// Start of the time window, in seconds
+    ///
+    /// The state of the game at this point will be recreated at the start of the new replay.
+    #[arg(short, long, value_name = "SECS", default_value_t = 0.0)]
+    pub start: f64,
+    /// End of the time window, in seconds (default: until the end)
+    #[arg(short, long, value_name = "SECS")]
+    pub end: Option<f64>,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ExtractArgs {
+    #[command(flatten)]
+    pub rewrite: RewriteArgs,
+    /// The player to extract
+    #[arg(short, long, value_name = "PLID")]
+    pub plid: u8,
+    /// Write a raw player stream (Initialization Sequence + messages, no timing)
+    /// instead of a replay file
+    #[arg(short, long)]
+    pub raw: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct DiffArgs {
+    /// The old file
+    #[arg(value_name = "OLD")]
+    pub old: PathBuf,
+    /// The new file
+    #[arg(value_name = "NEW")]
+    pub new: PathBuf,
+    /// Compare the resulting map state instead of the raw messages
+    #[arg(short, long)]
+    pub semantic: bool,
+    /// The inputs are gameplay streams (as produced by `asm`) of the given player,
+    /// instead of replay files
+    #[arg(long, value_name = "PLID")]
+    pub stream: Option<u8>,
+}
@@ -0,0 +1,63 @@
+use mw_common::plid::{PlayerId, Plids};
+use mw_common::prelude::*;
+use mw_dataformat::codec::decode_msgs;
+use mw_dataformat::diff::{diff_frames, Change, DiffMode};
+use mw_dataformat::frame::Frame;
+
+use crate::cli::DiffArgs;
+use crate::replay::load;
+use crate::stream::Disasm;
+
+fn load_frames(path: &Path, stream: Option<u8>) -> AnyResult<Vec<Frame>> {
+    if let Some(plid) = stream {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Cannot read {:?}", path))?;
+        let msgs = decode_msgs(&data).collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Cannot decode {:?}", path))?;
+        // the stream has no timing; pretend it all happened at once
+        let plid = PlayerId::from(plid);
+        return Ok(vec![Frame {
+            time: Duration::ZERO,
+            plids: Plids::from(plid),
+            views: vec![(plid, msgs)],
+        }]);
+    }
+    let (_, replay) = load(path)?;
+    let frames = replay.frames().collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Cannot decode the frames of {:?}", path))?;
+    Ok(frames)
+}
+
+pub fn diff(args: &DiffArgs) -> AnyResult<()> {
+    let old = load_frames(&args.old, args.stream)?;
+    let new = load_frames(&args.new, args.stream)?;
+    let mode = if args.semantic {
+        DiffMode::Semantic
+    } else {
+        DiffMode::Raw
+    };
+
+    let diffs = diff_frames(old, new, mode);
+    for d in diffs.iter() {
+        let prefix = format!("[{:?}] view {}:", d.time, u8::from(d.plid));
+        match &d.change {
+            Change::Removed(msg) => {
+                println!("{} - {}", prefix, Disasm(std::slice::from_ref(msg)).to_string().trim());
+            }
+            Change::Added(msg) => {
+                println!("{} + {}", prefix, Disasm(std::slice::from_ref(msg)).to_string().trim());
+            }
+            Change::Changed(old, new) => {
+                println!("{} ~ {} => {}", prefix,
+                    Disasm(std::slice::from_ref(old)).to_string().trim(),
+                    Disasm(std::slice::from_ref(new)).to_string().trim());
+            }
+        }
+    }
+    if diffs.is_empty() {
+        println!("No differences");
+    } else {
+        println!("{} differences", diffs.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use mw_common::game::{ItemKind, TileKind};
+use mw_common::grid::map::ring_order;
+use mw_common::grid::{Coord, Hex, Sq, Topology};
+use mw_common::plid::{PlayerId, Plids};
+use mw_common::prelude::*;
+use mw_dataformat::codec::encode_msgs;
+use mw_dataformat::frame::FrameWriter;
+use mw_dataformat::init::InitSequence;
+use mw_dataformat::replay::ReplayFile;
+use mw_dataformat::state::{StreamState, ViewState};
+
+use crate::cli::{ExtractArgs, RewriteArgs, TrimArgs};
+use crate::replay::load;
+use crate::write_output;
+
+fn save(args: &RewriteArgs, replay: &ReplayFile) -> AnyResult<()> {
+    let mut out = vec![];
+    replay.encode(&mut out, !args.uncompressed)?;
+    write_output(args.output.as_deref(), &out)
+}
+
+pub fn trim(args: &TrimArgs) -> AnyResult<()> {
+    let start = Duration::try_from_secs_f64(args.start)
+        .with_context(|| format!("Invalid start time: {}", args.start))?;
+    let end = match args.end {
+        Some(end) => Duration::try_from_secs_f64(end)
+            .with_context(|| format!("Invalid end time: {}", end))?,
+        None => Duration::MAX,
+    };
+    ensure!(start <= end, "The start time must not be after the end time");
+    let (_, mut replay) = load(&args.rewrite.input)?;
+    trim_replay(&mut replay, start, end)?;
+    save(&args.rewrite, &replay)
+}
+
+/// Cut the replay down to the frames between `start` and `end`
+///
+/// The map is updated to how it was at `start`, and the rest of the state
+/// at that point is recreated with a snapshot at the start of the frames.
+fn trim_replay(replay: &mut ReplayFile, start: Duration, end: Duration) -> AnyResult<()> {
+    let mut state = StreamState::default();
+    let mut w = FrameWriter::new(replay.init.max_plid());
+    let mut frames = replay.frames();
+    let mut first = None;
+    for frame in frames.by_ref() {
+        let frame = frame?;
+        if frame.time >= start {
+            first = Some(frame);
+            break;
+        }
+        state.apply(&frame);
+    }
+    for (plids, msgs) in state.snapshot() {
+        w.push(Duration::ZERO, plids, msgs)?;
+    }
+    for frame in first.into_iter().map(Ok).chain(frames) {
+        let mut frame = frame?;
+        if frame.time > end {
+            break;
+        }
+        frame.time -= start;
+        w.push_frame(frame)?;
+    }
+    if let Some(view) = state.view(PlayerId::Neutral) {
+        match replay.init.topology {
+            Topology::Hex => trim_init::<Hex>(&mut replay.init, view),
+            Topology::Sq => trim_init::<Sq>(&mut replay.init, view),
+        }
+    }
+    replay.frames = w.finish()?;
+    Ok(())
+}
+
+/// Apply the changes to the map that the spectator has seen
+fn trim_init<C: Coord>(init: &mut InitSequence, view: &ViewState) {
+    let order = ring_order::<C>(init.size);
+    for (c, tile) in order.into_iter().zip(init.tiles_mut()) {
+        let Some(kind) = view.kind(c.into()) else {
+            continue;
+        };
+        tile.kind = kind.into();
+        // explosions destroy the tile and any item on it
+        if tile.kind == TileKind::Destroyed {
+            tile.item = ItemKind::Safe;
+        }
+    }
+}
+
+pub fn anonymize(args: &RewriteArgs) -> AnyResult<()> {
+    let (_, mut replay) = load(&args.input)?;
+    replay.init.names.clear();
+    save(args, &replay)
+}
+
+pub fn extract(args: &ExtractArgs) -> AnyResult<()> {
+    let (_, mut replay) = load(&args.rewrite.input)?;
+    ensure!(
+        args.plid >= 1 && args.plid <= replay.init.n_players,
+        "The replay has players 1-{}", replay.init.n_players
+    );
+    let plid = PlayerId::from(args.plid);
+
+    // players don't get to know where the items are
+    for tile in replay.init.tiles_mut() {
+        tile.item = ItemKind::Safe;
+    }
+
+    let mut w = FrameWriter::new(replay.init.max_plid());
+    let mut all_msgs = vec![];
+    for frame in replay.frames() {
+        let frame = frame?;
+        for (view, msgs) in frame.views {
+            if view != plid {
+                continue;
+            }
+            if args.raw {
+                all_msgs.extend(msgs);
+            } else {
+                w.push(frame.time, Plids::from(plid), msgs)?;
+            }
+        }
+    }
+
+    if args.raw {
+        let mut out = vec![];
+        replay.init.encode(&mut out, !args.rewrite.uncompressed)?;
+        encode_msgs(&all_msgs, &mut out)?;
+        write_output(args.rewrite.output.as_deref(), &out)
+    } else {
+        replay.frames = w.finish()?;
+        save(&args.rewrite, &replay)
+    }
+}
+
+pub fn recompress(args: &RewriteArgs) -> AnyResult<()> {
+    let (_, replay) = load(&args.input)?;
+    save(args, &replay)
+}
+
+#[cfg(test)]
+mod test {
+    use mw_common::grid::{MapData, Pos};
+    use mw_dataformat::frame::Frame;
+    use mw_dataformat::init::InitTile;
+    use mw_dataformat::msg::{Msg, MsgItem, MsgTileKind};
+
+    use super::*;
+
+    const MINE: Pos = Pos(1, 0);
+
+    fn test_replay() -> ReplayFile {
+        let map = MapData::<Hex, _>::new_with(3, |c: Hex| Pos::from(c) == MINE);
+        let init = InitSequence::new(&map, &[Hex(0, 0)], 2, vec![], |mine| InitTile {
+            kind: TileKind::Regular,
+            item: if *mine { ItemKind::Mine } else { ItemKind::Safe },
+            region: 0,
+        });
+        let p1 = PlayerId::from(1);
+        let mut w = FrameWriter::new(init.max_plid());
+        w.push(Duration::from_secs(1), Plids::with_spect(p1), vec![
+            Msg::Capture { pos: Pos(0, 0), digit: 1 },
+        ]).unwrap();
+        w.push(Duration::from_secs(2), Plids(0b111), vec![
+            Msg::RevealItem { pos: MINE, item: MsgItem::Mine },
+            Msg::Explode { pos: MINE },
+        ]).unwrap();
+        w.push(Duration::from_secs(4), Plids(0b111), vec![Msg::Tremor]).unwrap();
+        ReplayFile {
+            init,
+            frames: w.finish().unwrap(),
+        }
+    }
+
+    fn frames(replay: &ReplayFile) -> Vec<Frame> {
+        replay.frames().map(|frame| frame.unwrap()).collect()
+    }
+
+    /// Everything sent to `plid` in the given frames
+    fn view_msgs(frames: &[Frame], plid: PlayerId) -> Vec<Msg> {
+        frames.iter()
+            .flat_map(|frame| frame.views.iter())
+            .filter(|(view, _)| *view == plid)
+            .flat_map(|(_, msgs)| msgs.iter().cloned())
+            .collect()
+    }
+
+    fn mine_tile(replay: &ReplayFile) -> InitTile {
+        replay.init.mapdata::<Hex>().unwrap()[Hex::from(MINE)]
+    }
+
+    #[test]
+    fn trim_window() {
+        let mut replay = test_replay();
+        trim_replay(&mut replay, Duration::from_millis(1500), Duration::from_secs(3)).unwrap();
+        let frames = frames(&replay);
+        let n_snapshot = frames.iter().take_while(|frame| frame.time == Duration::ZERO).count();
+        let (snapshot, rest) = frames.split_at(n_snapshot);
+        assert!(view_msgs(snapshot, PlayerId::from(1)).contains(&Msg::Capture { pos: Pos(0, 0), digit: 1 }));
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].time, Duration::from_millis(500));
+        // the explosion happens after the start, so the map is unchanged
+        assert_eq!(mine_tile(&replay).item, ItemKind::Mine);
+        assert_eq!(mine_tile(&replay).kind, TileKind::Regular);
+    }
+
+    #[test]
+    fn trim_after_end() {
+        let mut replay = test_replay();
+        trim_replay(&mut replay, Duration::from_secs(5), Duration::MAX).unwrap();
+        // nothing left but the snapshot
+        let frames = frames(&replay);
+        assert!(frames.iter().all(|frame| frame.time == Duration::ZERO));
+        let spect = view_msgs(&frames, PlayerId::Neutral);
+        assert!(spect.contains(&Msg::TileKind { pos: MINE, kind: MsgTileKind::Destroyed }));
+        // the map is as it was at the trim point
+        assert_eq!(mine_tile(&replay).item, ItemKind::Safe);
+        assert_eq!(mine_tile(&replay).kind, TileKind::Destroyed);
+    }
+}
@@ -1,3 +1,48 @@
+use mw_common::prelude::*;
+
+use clap::Parser;
+
+mod cli;
+mod diff;
+mod edit;
+mod replay;
+#[cfg(feature = "render")]
+mod render;
+mod stream;
+
 fn main() {
-    println!("Hello, world!");
+    let args = cli::Args::parse();
+
+    let r = match args.command {
+        cli::Command::Asm(args) => stream::asm(&args),
+        cli::Command::Disasm(args) => stream::disasm(&args),
+        cli::Command::Info(args) => replay::info(&args),
+        cli::Command::Verify(args) => replay::verify(&args),
+        cli::Command::Map(args) => replay::map(&args),
+        #[cfg(feature = "render")]
+        cli::Command::Render(args) => render::render(&args),
+        cli::Command::Trim(args) => edit::trim(&args),
+        cli::Command::Anonymize(args) => edit::anonymize(&args),
+        cli::Command::Extract(args) => edit::extract(&args),
+        cli::Command::Recompress(args) => edit::recompress(&args),
+        cli::Command::Diff(args) => diff::diff(&args),
+    };
+
+    if let Err(e) = r {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Write to the given file, or stdout if no file was specified
+fn write_output(path: Option<&Path>, data: &[u8]) -> AnyResult<()> {
+    use std::io::Write;
+
+    if let Some(path) = path {
+        std::fs::write(path, data)
+            .with_context(|| format!("Cannot write {:?}", path))?;
+    } else {
+        std::io::stdout().write_all(data)?;
+    }
+    Ok(())
 }
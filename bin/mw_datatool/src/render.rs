@@ -0,0 +1,121 @@
+use mw_common::game::{ItemKind, TileKind};
+use mw_common::grid::{Coord, Hex, Sq, Topology};
+use mw_common::plid::PlayerId;
+use mw_common::prelude::*;
+use mw_common::render::{render_png, render_svg, tile_color, RenderOptions, Rgb, TileStyle};
+use mw_dataformat::init::{InitSequence, InitTile};
+use mw_dataformat::state::{StreamState, ViewState};
+
+use crate::cli::RenderArgs;
+use crate::replay::load;
+use crate::write_output;
+
+const CIT_COLOR: Rgb = [0xe0, 0xe0, 0xe0];
+const FLAG_COLOR: Rgb = [0xff, 0x40, 0xc0];
+
+const PLAYER_COLORS: [Rgb; 8] = [
+    [0xe0, 0x30, 0x30],
+    [0x30, 0x60, 0xe0],
+    [0xf0, 0xd0, 0x20],
+    [0x20, 0xc0, 0xc0],
+    [0xa0, 0x40, 0xe0],
+    [0xf0, 0x80, 0x20],
+    [0x80, 0x20, 0x20],
+    [0x10, 0x10, 0x10],
+];
+
+fn player_color(plid: PlayerId) -> Option<Rgb> {
+    match plid {
+        PlayerId::Neutral => None,
+        plid => Some(PLAYER_COLORS[(plid.i() - 1) % PLAYER_COLORS.len()]),
+    }
+}
+
+fn item_color(item: ItemKind) -> Option<Rgb> {
+    match item {
+        ItemKind::Safe => None,
+        ItemKind::Mine => Some([0x90, 0x00, 0x00]),
+        ItemKind::Decoy => Some([0xc0, 0x90, 0x60]),
+        ItemKind::Flashbang => Some([0xff, 0xff, 0xa0]),
+    }
+}
+
+pub fn render(args: &RenderArgs) -> AnyResult<()> {
+    let (_, replay) = load(&args.input)?;
+    let view = if let Some(plid) = args.view {
+        ensure!(
+            plid <= replay.init.n_players,
+            "The replay has players 1-{}", replay.init.n_players
+        );
+        let end = match args.time {
+            Some(time) => Duration::try_from_secs_f64(time)
+                .with_context(|| format!("Invalid time: {}", time))?,
+            None => Duration::MAX,
+        };
+        let mut state = StreamState::default();
+        for frame in replay.frames() {
+            let frame = frame?;
+            if frame.time > end {
+                break;
+            }
+            state.apply(&frame);
+        }
+        Some(state.view(PlayerId::from(plid)).cloned().unwrap_or_default())
+    } else {
+        ensure!(args.time.is_none(), "A time can only be given together with a view");
+        None
+    };
+
+    let svg = args.output.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("svg"))
+        .unwrap_or(false);
+    let out = match replay.init.topology {
+        Topology::Hex => render_map::<Hex>(&replay.init, view.as_ref(), args, svg)?,
+        Topology::Sq => render_map::<Sq>(&replay.init, view.as_ref(), args, svg)?,
+    };
+    write_output(Some(&args.output), &out)
+}
+
+fn render_map<C: Coord>(
+    init: &InitSequence,
+    view: Option<&ViewState>,
+    args: &RenderArgs,
+    svg: bool,
+) -> AnyResult<Vec<u8>> {
+    let map = init.mapdata::<C>()
+        .context("Map data does not match the map size")?;
+    let regions = map.convert(|_, tile| tile.region);
+    let opts = RenderOptions {
+        tile_size: args.tile_size,
+        regions: args.regions.then_some(&regions),
+        ..Default::default()
+    };
+    let style = |c: C, tile: &InitTile| {
+        let pos = c.into();
+        let kind = view.and_then(|v| v.kind(pos)).map(TileKind::from).unwrap_or(tile.kind);
+        let mut style = TileStyle::fill(tile_color(kind));
+        if init.cits.contains(&pos) {
+            style.fill = CIT_COLOR;
+        }
+        let mut item = if args.items { tile.item } else { ItemKind::Safe };
+        if let Some(v) = view {
+            if let Some(revealed) = v.item(pos) {
+                item = revealed.into();
+            }
+            style.owner = player_color(v.owner(pos));
+            style.digit = v.digit(pos).filter(|d| *d > 0);
+            if v.flag(pos).is_some() {
+                style.fill = FLAG_COLOR;
+            }
+        }
+        if let Some(color) = item_color(item) {
+            style.fill = color;
+        }
+        style
+    };
+    if svg {
+        Ok(render_svg(&map, &opts, style).into_bytes())
+    } else {
+        Ok(render_png(&map, &opts, style)?)
+    }
+}
@@ -0,0 +1,103 @@
+use mw_common::game::{ItemKind, TileKind};
+use mw_common::grid::{Coord, Hex, Sq, Topology};
+use mw_common::prelude::*;
+use mw_dataformat::init::{InitSequence, InitTile};
+use mw_dataformat::replay::{ReplayFile, ReplayHeader};
+
+use crate::cli::{MapArgs, ReplayArgs};
+use crate::write_output;
+
+pub fn load(path: &Path) -> AnyResult<(Vec<u8>, ReplayFile)> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("Cannot read {:?}", path))?;
+    let replay = ReplayFile::decode(&data)?;
+    Ok((data, replay))
+}
+
+pub fn info(args: &ReplayArgs) -> AnyResult<()> {
+    let (data, replay) = load(&args.input)?;
+    let header = ReplayHeader::decode(&data)?;
+    let init = &replay.init;
+
+    println!("File size: {} bytes", data.len());
+    println!("Checksums: {:016x} {:016x} {:016x}",
+        header.checksum_header, header.checksum_init, header.checksum_frames);
+    println!("Frames data: {} bytes ({} bytes stored)", header.len_raw, header.len_compressed);
+    println!("Topology: {:?}", init.topology);
+    let land = init.tiles().iter().filter(|t| t.kind.is_land()).count();
+    println!("Map size: {} ({} tiles, {} land)", init.size, init.tiles().len(), land);
+    println!("Players: {}", init.n_players);
+    for (i, name) in init.names.iter().enumerate() {
+        println!("  {}: {}", i + 1, name);
+    }
+    println!("Cities: {}", init.cits.len());
+    for (i, pos) in init.cits.iter().enumerate() {
+        println!("  {}: {},{}", i, pos.0, pos.1);
+    }
+
+    let mut n_frames = 0;
+    let mut duration = Duration::ZERO;
+    for frame in replay.frames() {
+        let frame = frame?;
+        n_frames += 1;
+        duration = frame.time;
+    }
+    println!("Frames: {}", n_frames);
+    println!("Duration: {}:{:02}.{:03}",
+        duration.as_secs() / 60, duration.as_secs() % 60, duration.subsec_millis());
+
+    Ok(())
+}
+
+pub fn verify(args: &ReplayArgs) -> AnyResult<()> {
+    // decoding validates all the checksums
+    let (_, replay) = load(&args.input)?;
+    let mut frames = replay.frames();
+    let mut n_frames = 0;
+    while let Some(r) = frames.next() {
+        r.with_context(|| {
+            format!("Frame at frames data byte offset {}", frames.offset())
+        })?;
+        n_frames += 1;
+    }
+    println!("OK: {} frames", n_frames);
+    Ok(())
+}
+
+pub fn map(args: &MapArgs) -> AnyResult<()> {
+    let (_, replay) = load(&args.input)?;
+    let mut out = vec![];
+    match replay.init.topology {
+        Topology::Hex => map_ascii::<Hex>(&replay.init, args.items, &mut out)?,
+        Topology::Sq => map_ascii::<Sq>(&replay.init, args.items, &mut out)?,
+    }
+    write_output(None, &out)
+}
+
+fn map_ascii<C: Coord>(init: &InitSequence, items: bool, out: &mut Vec<u8>) -> AnyResult<()> {
+    let map = init.mapdata::<C>()
+        .context("Map data does not match the map size")?;
+    map.ascii_art(out, |c, tile: &InitTile| {
+        if init.cits.contains(&c.into()) {
+            return b'C';
+        }
+        if items && tile.kind.is_land() {
+            match tile.item {
+                ItemKind::Safe => {}
+                ItemKind::Mine => return b'*',
+                ItemKind::Decoy => return b'd',
+                ItemKind::Flashbang => return b'f',
+            }
+        }
+        match tile.kind {
+            TileKind::Water => b'~',
+            TileKind::Foundation => b'_',
+            TileKind::Regular => b'.',
+            TileKind::Fertile => b',',
+            TileKind::Forest => b'T',
+            TileKind::Mountain => b'M',
+            TileKind::Destroyed => b'x',
+        }
+    })?;
+    Ok(())
+}
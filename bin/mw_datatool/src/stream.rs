@@ -0,0 +1,96 @@
+use std::fmt::{Formatter, Write};
+
+use mw_common::prelude::*;
+use mw_dataformat::asm::Assembly;
+use mw_dataformat::codec::{decode_msgs, encode_msgs};
+use mw_dataformat::msg::Msg;
+use mw_dataformat::replay::ReplayFile;
+
+use crate::cli::{AsmArgs, DisasmArgs};
+use crate::write_output;
+
+/// Helper to get at a `Formatter` for `Assembly::disasm_all`
+pub struct Disasm<'a>(pub &'a [Msg]);
+
+impl Display for Disasm<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (_, r) = Msg::disasm_all(self.0, f);
+        r.map_err(|_| std::fmt::Error)
+    }
+}
+
+pub fn asm(args: &AsmArgs) -> AnyResult<()> {
+    let source = std::fs::read_to_string(&args.input)
+        .with_context(|| format!("Cannot read {:?}", args.input))?;
+
+    // every message takes up at least one word of source text
+    let mut buffer = vec![Msg::Nop; source.split_ascii_whitespace().count()];
+    let len = match Msg::asm_all(&source, &mut buffer) {
+        Ok(len) => len,
+        Err(e) => {
+            // `asm_all` does not tell us where; find the offending line
+            let lineno = source.lines().position(|line| {
+                let mut buffer = vec![Msg::Nop; line.split_ascii_whitespace().count()];
+                Msg::asm_one(line, &mut buffer).is_err()
+            });
+            if let Some(lineno) = lineno {
+                bail!("Line {}: {}", lineno + 1, e);
+            }
+            bail!("{}", e);
+        }
+    };
+
+    let mut out = vec![];
+    encode_msgs(&buffer[..len], &mut out)?;
+    write_output(args.output.as_deref(), &out)
+}
+
+pub fn disasm(args: &DisasmArgs) -> AnyResult<()> {
+    let data = std::fs::read(&args.input)
+        .with_context(|| format!("Cannot read {:?}", args.input))?;
+
+    let mut text = String::new();
+    let r = if args.replay {
+        disasm_replay(&data, &mut text)
+    } else {
+        disasm_stream(&data, &mut text)
+    };
+    write_output(args.output.as_deref(), text.as_bytes())?;
+    r
+}
+
+fn disasm_stream(data: &[u8], text: &mut String) -> AnyResult<()> {
+    let mut msgs = vec![];
+    let mut decoder = decode_msgs(data);
+    let mut error = None;
+    for r in decoder.by_ref() {
+        match r {
+            Ok(msg) => msgs.push(msg),
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+    write!(text, "{}", Disasm(&msgs))?;
+    if let Some(e) = error {
+        bail!("At byte offset {}: {}", decoder.offset(), e);
+    }
+    Ok(())
+}
+
+fn disasm_replay(data: &[u8], text: &mut String) -> AnyResult<()> {
+    let replay = ReplayFile::decode(data)?;
+    let mut frames = replay.frames();
+    while let Some(r) = frames.next() {
+        let frame = r.with_context(|| {
+            format!("Frame at frames data byte offset {}", frames.offset())
+        })?;
+        writeln!(text, "; time {:?}, plids {:#06x}", frame.time, frame.plids.0)?;
+        for (plid, msgs) in frame.views.iter() {
+            writeln!(text, "; view {}", u8::from(*plid))?;
+            write!(text, "{}", Disasm(msgs))?;
+        }
+    }
+    Ok(())
+}
@@ -79,7 +79,7 @@ pub struct BevyHost<G: Game> {
 }
 
 struct BevyHostState<G: Game> {
-    events: Vec<(Plids, G::OutEvent)>,
+    events: Vec<(PlidsBig, G::OutEvent)>,
     scheds: BTreeMap<Instant, G::SchedEvent>,
     cancel: HashSet<G::SchedEvent>,
     init_data: Option<Box<G::InitData>>,
@@ -99,11 +99,16 @@ impl<G: Game> BevyHost<G> {
             },
         }
     }
+
+    /// Access the game, to query its state
+    pub fn game(&self) -> &G {
+        &self.game
+    }
 }
 
 impl<G: Game> Host<G> for BevyHostState<G> {
-    fn msg(&mut self, plids: Plids, event: G::OutEvent) {
-        self.events.push((plids, event));
+    fn msg<P: PlidMask>(&mut self, plids: P, event: G::OutEvent) {
+        self.events.push((plids.into(), event));
     }
     fn sched(&mut self, time: Instant, event: G::SchedEvent) {
         self.scheds.insert(time, event);
@@ -46,6 +46,7 @@ pub enum InputAction {
     ZoomCamera,
     MinimapEnlarge,
     MinimapShrink,
+    RequestHint,
 }
 
 #[derive(Resource, Default)]
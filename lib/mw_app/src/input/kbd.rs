@@ -31,6 +31,7 @@ pub fn add_minewars_defaults(map: &mut InputMap<InputAction>) {
     map.insert(KeyCode::C, InputAction::SwitchTool(Tool::BuildBridge));
     map.insert(KeyCode::V, InputAction::SwitchTool(Tool::BuildWall));
     map.insert(KeyCode::B, InputAction::SwitchTool(Tool::BuildTower));
+    map.insert(KeyCode::H, InputAction::RequestHint);
     map.insert(VirtualAxis {
         negative: KeyCode::BracketRight.into(),
         positive: KeyCode::BracketLeft.into(),
@@ -13,12 +13,14 @@ use crate::prelude::*;
 use crate::view::VisibleInView;
 
 mod update;
+mod vis;
 
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(update::MapUpdatePlugin);
+        app.add_plugins(vis::MapVisPlugin);
         app.add_event::<RecomputeVisEvent>();
         app.init_resource::<GridCursorTileEntity>();
         for topo in enum_iterator::all::<Topology>() {
@@ -123,11 +125,13 @@ pub enum TileGent {
     Structure(StructureKind),
     /// Tile contains a Flag (placed by the given player)
     Flag(PlayerId),
+    /// Tile contains a question mark (placed by the given player)
+    QuestionMark(PlayerId),
 }
 
 /// Visibility level of the given tile
 /// (see MW game design docs)
-#[derive(Component)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TileVisLevel {
     Fog,
     Limited,
@@ -143,6 +147,12 @@ pub enum TileExplosionKind {
 #[derive(Component)]
 pub struct TileExplosion(pub Entity, pub TileExplosionKind);
 
+/// A tile that was pointed out by a hint
+///
+/// The `bool` is whether the tile has a mine (otherwise, it is safe).
+#[derive(Component)]
+pub struct TileHint(pub Entity, pub bool);
+
 /// Components common to all map tiles
 #[derive(Bundle)]
 pub struct MapTileBundle {
@@ -181,6 +191,13 @@ pub struct ExplosionBundle {
     pub view: VisibleInView,
 }
 
+#[derive(Bundle)]
+pub struct HintBundle {
+    pub pos: MwTilePos,
+    pub hint: TileHint,
+    pub view: VisibleInView,
+}
+
 /// Trigger a recompute of `TileVisLevel`.
 ///
 /// For a specific tile position, or for the whole map if None.
@@ -228,7 +245,7 @@ pub fn setup_map<C: Coord, D>(
     f_regid: impl Fn(&D) -> u8,
 ) {
     let mut tile_index = MapTileIndex(
-        MapData::<C, _>::new(mapdata.size(), Entity::PLACEHOLDER)
+        mapdata.convert(|_, _| Entity::PLACEHOLDER)
     );
 
     let mut cit_index = CitIndex {
@@ -16,6 +16,7 @@ impl Plugin for MapUpdatePlugin {
                 (
                     (event_gents::<Hex>, event_explosion::<Hex>).chain(),
                 ).in_set(MapUpdateSet::TileGent),
+                event_hint::<Hex>,
             ).in_set(MapTopologySet(Topology::Hex)),
             (
                 event_kind::<Sq>.in_set(MapUpdateSet::TileKind),
@@ -24,6 +25,7 @@ impl Plugin for MapUpdatePlugin {
                 (
                     (event_gents::<Sq>, event_explosion::<Sq>).chain(),
                 ).in_set(MapUpdateSet::TileGent),
+                event_hint::<Sq>,
             ).in_set(MapTopologySet(Topology::Sq)),
         ).in_set(NeedsMapSet).after(GameEventSet).after(ViewSwitchSet));
         app.add_systems(Update, (
@@ -37,17 +39,26 @@ fn event_kind<C: Coord>(
     viewing: Res<PlidViewing>,
     index: Res<MapTileIndex<C>>,
     mut q_tile: Query<&mut TileKind>,
+    mut evw_visrecompute: EventWriter<RecomputeVisEvent>,
 ) {
+    let mut recompute_vis = false;
     for ev in evr.iter() {
         if ev.plid != viewing.0 {
             continue;
         }
         if let MwEv::Map { pos, ev: MapEv::Tile { kind }} = ev.ev {
             if let Ok(mut tilekind) = q_tile.get_mut(index.0[pos.into()]) {
+                // mountains/forests block line of sight
+                if tilekind.is_rescluster() != kind.is_rescluster() {
+                    recompute_vis = true;
+                }
                 *tilekind = kind;
             }
         }
     }
+    if recompute_vis {
+        evw_visrecompute.send(RecomputeVisEvent(None));
+    }
 }
 
 fn event_owner<C: Coord>(
@@ -58,7 +69,9 @@ fn event_owner<C: Coord>(
     cits: Res<CitIndex>,
     mut q_tile: Query<&mut TileOwner>,
     mut q_cit: Query<&mut CitOwner>,
+    mut evw_visrecompute: EventWriter<RecomputeVisEvent>,
 ) {
+    let mut recompute_vis = false;
     for ev in evr.iter() {
         if ev.plid != viewing.0 {
             continue;
@@ -71,6 +84,9 @@ fn event_owner<C: Coord>(
                         TileAlert(Timer::new(Duration::from_millis(1000), TimerMode::Once))
                     );
                 }
+                if (owner.0 == viewing.0) != (plid == viewing.0) {
+                    recompute_vis = true;
+                }
                 owner.0 = plid;
                 if let Some(e_cit) = cits.by_pos.get(&pos) {
                     let mut citowner = q_cit.get_mut(*e_cit).unwrap();
@@ -79,6 +95,9 @@ fn event_owner<C: Coord>(
             }
         }
     }
+    if recompute_vis {
+        evw_visrecompute.send(RecomputeVisEvent(None));
+    }
 }
 
 fn event_digit<C: Coord>(
@@ -111,9 +130,12 @@ fn event_gents<C: Coord>(
             continue;
         }
         let (pos, gent) = match ev.ev {
-            MwEv::Map { pos, ev: MapEv::Flag { plid }} => {
+            MwEv::Map { pos, ev: MapEv::Flag { plid, question: false }} => {
                 (pos, TileGent::Flag(plid))
             }
+            MwEv::Map { pos, ev: MapEv::Flag { plid, question: true }} => {
+                (pos, TileGent::QuestionMark(plid))
+            }
             MwEv::Map { pos, ev: MapEv::Item { kind }} => {
                 (pos, TileGent::Item(kind))
             }
@@ -165,6 +187,32 @@ fn event_explosion<C: Coord>(
     }
 }
 
+fn event_hint<C: Coord>(
+    mut commands: Commands,
+    mut evr: EventReader<GameEvent>,
+    viewing: Res<PlidViewing>,
+    index: Res<MapTileIndex<C>>,
+    q_tile: Query<&MwTilePos>,
+) {
+    for ev in evr.iter() {
+        if ev.plid != viewing.0 {
+            continue;
+        }
+        if let MwEv::Player { ev: PlayerEv::Hint { pos, mine }, .. } = ev.ev {
+            let e = index.0[pos.into()];
+            if let Ok(tilepos) = q_tile.get(e) {
+                commands.spawn((
+                    HintBundle {
+                        pos: MwTilePos(tilepos.0),
+                        hint: TileHint(e, mine),
+                        view: VisibleInView(viewing.0),
+                    },
+                ));
+            }
+        }
+    }
+}
+
 fn alert_timer(
     time: Res<Time>,
     mut commands: Commands,
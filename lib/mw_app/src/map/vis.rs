@@ -0,0 +1,193 @@
+//! Computing `TileVisLevel` for the current view
+//!
+//! Players have full visibility of their own territory, and limited visibility
+//! (see ownership, but not items) of tiles within line of sight of their
+//! territory or watch towers. Everything else is fog of war.
+
+use mw_common::algo::{flood, FloodQ, FloodSelect};
+use mw_common::grid::fov::{fov, has_los};
+
+use crate::prelude::*;
+use crate::view::{PlidViewing, ViewSwitchSet};
+use super::*;
+
+/// Vision radius around owned tiles, if there is no `BalancingTable`
+const DEFAULT_RADIUS_VIS: u8 = 3;
+/// Vision radius around watch towers, if there is no `BalancingTable`
+const DEFAULT_RADIUS_VIS_WATCHTOWER: u8 = 5;
+
+pub struct MapVisPlugin;
+
+impl Plugin for MapVisPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            recompute_vis::<Hex>.in_set(MapTopologySet(Topology::Hex)),
+            recompute_vis::<Sq>.in_set(MapTopologySet(Topology::Sq)),
+        )
+            .in_set(NeedsMapSet)
+            .after(MapUpdateSet::TileKind)
+            .after(MapUpdateSet::TileOwner)
+            .after(MapUpdateSet::TileGent)
+            .after(ViewSwitchSet)
+        );
+    }
+}
+
+/// Vision is blocked by mountains and forests
+fn blocks_vis(kind: TileKind) -> bool {
+    kind.is_rescluster()
+}
+
+/// Snapshot of the map data relevant for vision
+struct VisMap<C: Coord> {
+    kinds: MapData<C, TileKind>,
+    /// (owned by the viewer, has a watch tower)
+    owned: MapData<C, (bool, bool)>,
+    radius: u8,
+    radius_tower: u8,
+}
+
+impl<C: Coord> VisMap<C> {
+    fn blocks(&self, c: C) -> bool {
+        self.kinds.get(c).map(|k| blocks_vis(*k)).unwrap_or(true)
+    }
+
+    /// Is there a source of vision that can see `c`?
+    fn sees(&self, c: C) -> bool {
+        let r = self.radius.max(self.radius_tower);
+        let in_range = |src: C| {
+            let Some(&(owned, tower)) = self.owned.get(src) else {
+                return false;
+            };
+            let d = src.distance(c);
+            (owned && d <= self.radius as u16) || (tower && d <= self.radius_tower as u16)
+        };
+        if in_range(c) {
+            return true;
+        }
+        (1..=r).any(|ring| {
+            c.iter_ring(ring).any(|src| in_range(src) && has_los(src, c, |c| self.blocks(c)))
+        })
+    }
+
+    /// All tiles that can be seen
+    fn visible_all(&self) -> MapData<C, bool> {
+        let mut vis = MapData::new(self.kinds.size(), false);
+        for (c, &(owned, tower)) in self.owned.iter() {
+            let radius = match (owned, tower) {
+                (_, true) => self.radius_tower.max(self.radius),
+                (true, false) => self.radius,
+                (false, false) => continue,
+            };
+            fov(&mut vis, c, radius, |c| self.blocks(c));
+        }
+        // Mountains are a cluster: seeing one tile means seeing all of it
+        let mut q = FloodQ::new();
+        let mut done = MapData::new(self.kinds.size(), false);
+        for c in vis.iter_coords(None) {
+            if !vis[c] || done[c] || self.kinds[c] != TileKind::Mountain {
+                continue;
+            }
+            done[c] = true;
+            q.push_back(c.into());
+            flood(&mut q, |c: C, _| {
+                if self.kinds.get(c) != Some(&TileKind::Mountain) || done[c] {
+                    return FloodSelect::No;
+                }
+                done[c] = true;
+                vis[c] = true;
+                FloodSelect::Yes
+            });
+        }
+        vis
+    }
+
+    /// Can a single tile be seen?
+    fn visible_one(&self, c: C) -> bool {
+        if self.kinds.get(c) != Some(&TileKind::Mountain) {
+            return self.sees(c);
+        }
+        let mut q = FloodQ::new();
+        let mut cluster = vec![c];
+        q.push_back(c.into());
+        flood(&mut q, |c: C, _| {
+            if self.kinds.get(c) != Some(&TileKind::Mountain) || cluster.contains(&c) {
+                return FloodSelect::No;
+            }
+            cluster.push(c);
+            FloodSelect::Yes
+        });
+        cluster.into_iter().any(|c| self.sees(c))
+    }
+}
+
+fn vis_level(visible: bool, owned: bool) -> TileVisLevel {
+    match (visible, owned) {
+        (_, true) => TileVisLevel::Full,
+        (true, false) => TileVisLevel::Limited,
+        (false, false) => TileVisLevel::Fog,
+    }
+}
+
+fn recompute_vis<C: Coord>(
+    mut evr: EventReader<RecomputeVisEvent>,
+    viewing: Res<PlidViewing>,
+    desc: Res<MapDescriptor>,
+    balancing: Option<Res<BalancingTable>>,
+    index: Res<MapTileIndex<C>>,
+    q_tile: Query<(&MwTilePos, &TileKind, Option<&TileOwner>, Option<&TileGent>)>,
+    mut q_vis: Query<&mut TileVisLevel>,
+) {
+    let mut all = false;
+    let mut tiles = vec![];
+    for ev in evr.iter() {
+        match ev.0 {
+            Some(pos) => tiles.push(pos),
+            None => all = true,
+        }
+    }
+    if !all && tiles.is_empty() {
+        return;
+    }
+
+    // spectators see everything
+    if viewing.0 == PlayerId::Neutral {
+        for mut vis in &mut q_vis {
+            vis.set_if_neq(TileVisLevel::Full);
+        }
+        return;
+    }
+
+    let mut map = VisMap {
+        kinds: MapData::new(desc.size, TileKind::Water),
+        owned: MapData::new(desc.size, (false, false)),
+        radius: balancing.as_ref().map(|b| b.radius_vis).unwrap_or(DEFAULT_RADIUS_VIS),
+        radius_tower: balancing.as_ref().map(|b| b.radius_vis_watchtower).unwrap_or(DEFAULT_RADIUS_VIS_WATCHTOWER),
+    };
+    for (pos, kind, owner, gent) in &q_tile {
+        let c: C = pos.0.into();
+        map.kinds[c] = *kind;
+        let owned = owner.map(|o| o.0 == viewing.0).unwrap_or(false);
+        let tower = owned && matches!(gent, Some(TileGent::Structure(StructureKind::WatchTower)));
+        map.owned[c] = (owned, tower);
+    }
+
+    if all {
+        let visible = map.visible_all();
+        for (c, e) in index.0.iter() {
+            if let Ok(mut vis) = q_vis.get_mut(*e) {
+                vis.set_if_neq(vis_level(visible[c], map.owned[c].0));
+            }
+        }
+    } else {
+        for pos in tiles {
+            let c: C = pos.into();
+            let Some(e) = index.0.get(c) else {
+                continue;
+            };
+            if let Ok(mut vis) = q_vis.get_mut(*e) {
+                vis.set_if_neq(vis_level(map.visible_one(c), map.owned[c].0));
+            }
+        }
+    }
+}
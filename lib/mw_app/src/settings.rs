@@ -130,6 +130,11 @@ pub struct MinewarsGameSettings {
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MapGenStyle {
     Flat,
+    /// Flat map with a classic Minesweeper-style rectangular board
+    FlatRect {
+        width: u8,
+        height: u8,
+    },
     MineWars,
 }
 
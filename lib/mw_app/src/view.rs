@@ -110,7 +110,8 @@ pub struct ViewTileData {
     pub has_structure: bool,
     pub structure: StructureKind,
     pub flag: B4,
-    #[skip] __: B4,
+    pub question: bool,
+    #[skip] __: B3,
 }
 
 /// The map data of a view
@@ -241,6 +242,8 @@ fn switch_view_update_map_gents<C: Coord>(
         let flag = PlayerId::from(tiledata.flag());
         *gent = if tiledata.has_structure() {
             TileGent::Structure(tiledata.structure())
+        } else if flag != PlayerId::Neutral && tiledata.question() {
+            TileGent::QuestionMark(flag)
         } else if flag != PlayerId::Neutral {
             TileGent::Flag(flag)
         } else if item != ItemKind::Safe {
@@ -46,8 +46,9 @@ fn event_map<C: Coord>(
                 MapEv::Item { kind } => {
                     tile.set_item(*kind);
                 },
-                MapEv::Flag { plid } => {
+                MapEv::Flag { plid, question } => {
                     tile.set_flag(u8::from(*plid));
+                    tile.set_question(*question);
                 },
                 MapEv::Explode => {
                     // clear any item from the tile
@@ -0,0 +1,50 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use mw_common::algo::*;
+use mw_common::grid::{Coord, Hex, MapData, Sq};
+use mw_common::prelude::*;
+
+const SIZE: u8 = 24;
+
+/// Random map where ~20% of the tiles cannot be walked on,
+/// with the start and goal on opposite sides
+fn setup<C: Coord>() -> (MapData<C, bool>, C, C) {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(1503);
+    let mut map = MapData::new_with(SIZE, |_| rng.gen_bool(0.8));
+    let start = C::from((-(SIZE as i8) + 1, 0).into());
+    let goal = C::from((SIZE as i8 - 1, 0).into());
+    map[start] = true;
+    map[goal] = true;
+    let path = find_path(&mut PathQ::default(), start, goal, Neighbors::N0, |c| {
+        map.get(c).copied().filter(|x| *x).map(|_| 1)
+    });
+    assert!(path.is_some());
+    (map, start, goal)
+}
+
+fn bench_topology<C: Coord>(c: &mut Criterion, name: &str) {
+    let (map, start, goal) = setup::<C>();
+    let walkable = |c: C| map.get(c).copied().filter(|x| *x);
+
+    let mut group = c.benchmark_group("pathfinding");
+    let mut rq = ReachQ::new();
+    group.bench_function(BenchmarkId::new("reach", name), |b| b.iter(|| {
+        reach(&mut rq, start, |c| walkable(c).map(|_| c.distance(goal)))
+    }));
+    let mut pq = PathQ::default();
+    group.bench_function(BenchmarkId::new("find_path", name), |b| b.iter(|| {
+        find_path(&mut pq, start, goal, Neighbors::N0, |c| walkable(c).map(|_| 1))
+    }));
+    group.bench_function(BenchmarkId::new("distance_map", name), |b| b.iter(|| {
+        distance_map(&mut pq, SIZE, [start], Neighbors::N0, |c| walkable(c).map(|_| 1))
+    }));
+    group.finish();
+}
+
+fn bench_pathfinding(c: &mut Criterion) {
+    bench_topology::<Hex>(c, "hex");
+    bench_topology::<Sq>(c, "sq");
+}
+
+criterion_group!(benches, bench_pathfinding);
+criterion_main!(benches);
@@ -1,6 +1,11 @@
 //! General helper algorithms
 
-use crate::grid::{Coord, Pos};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::game::TileKind;
+use crate::grid::{Coord, MapData, Pos};
+use crate::prelude::*;
 
 /// What to do with each tile considered by the `flood` algorithm?
 pub enum FloodSelect {
@@ -52,8 +57,24 @@ where
     }
 }
 
-/// Type of the queue data structure (re)used for `reach`
-pub type ReachQ = Vec<(Pos, u16)>;
+/// Memory (re)used for `reach`
+#[derive(Debug, Default)]
+pub struct ReachQ {
+    /// (distance to goal, tile)
+    heap: BinaryHeap<Reverse<(u16, Pos)>>,
+    seen: HashSet<Pos>,
+}
+
+impl ReachQ {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.seen.clear();
+    }
+}
 
 /// Reachability (pathfinding) algorithm
 ///
@@ -80,49 +101,446 @@ where
         }
         Some(cost) => {
             q.clear();
-            q.push((start.into(), cost));
+            q.heap.push(Reverse((cost, start.into())));
+            q.seen.insert(start.into());
         }
         None => {
             return false;
         }
     }
 
-    let mut cur = 0;
-    let mut best = 0;
-
-    loop {
-        let mut found = false;
-        let cc: C = q[cur].0.into();
+    while let Some(Reverse((_, pos))) = q.heap.pop() {
+        let cc: C = pos.into();
         for c in cc.iter_n0() {
-            if let Some(cost) = p(c) {
-                if cost == 0 {
-                    found = true;
-                } else if let Err(i) = q.binary_search_by(|c2| cost.cmp(&c2.1)) {
-                    if i > best {
-                        best = i;
-                    } else {
-                        best += 1;
-                    }
-                    if i <= cur {
-                        cur += 1;
-                    }
-                    q.insert(i, (c.into(), cost));
+            if !q.seen.insert(c.into()) {
+                continue;
+            }
+            match p(c) {
+                Some(0) => {
+                    q.clear();
+                    return true;
+                }
+                Some(cost) => {
+                    q.heap.push(Reverse((cost, c.into())));
                 }
+                None => {}
             }
         }
+    }
 
-        if found {
-            q.clear();
-            return true;
-        } else if best > cur {
-            cur = best;
-        } else if cur == 0 {
+    q.clear();
+    false
+}
+
+/// Which tiles count as adjacent, for `find_path` and `distance_map`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbors {
+    /// Tiles sharing an edge
+    N0,
+    /// All touching tiles (incl. diagonals on square maps)
+    N1,
+}
+
+impl Neighbors {
+    fn iter<C: Coord>(self, c: C) -> impl Iterator<Item = C> {
+        let (n0, n1) = match self {
+            Neighbors::N0 => (Some(c.iter_n0()), None),
+            Neighbors::N1 => (None, Some(c.iter_n1())),
+        };
+        n0.into_iter().flatten().chain(n1.into_iter().flatten())
+    }
+}
+
+/// Memory (re)used for `find_path` and `distance_map`
+#[derive(Debug, Default)]
+pub struct PathQ {
+    /// (estimated total cost, cost so far, tile)
+    heap: BinaryHeap<Reverse<(u32, u32, Pos)>>,
+    /// tile -> (where we came from, cost so far)
+    came_from: HashMap<Pos, (Pos, u32)>,
+}
+
+impl PathQ {
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.came_from.clear();
+    }
+}
+
+/// Shortest path algorithm (A*)
+///
+/// `P` returns the cost of stepping onto the given tile, or `None` if it
+/// cannot be stepped on (also use this for tiles outside of the map).
+/// Costs of 0 are treated as 1. The cost of `start` is never asked for.
+///
+/// Returns the cheapest path, including both `start` and `goal`.
+///
+/// Uses externally-provided queue to reuse memory allocations.
+pub fn find_path<C: Coord, P>(
+    q: &mut PathQ,
+    start: C,
+    goal: C,
+    neighbors: Neighbors,
+    p: P,
+) -> Option<Vec<C>>
+where
+    P: Fn(C) -> Option<u16>,
+{
+    q.clear();
+    q.heap.push(Reverse((start.distance(goal) as u32, 0, start.into())));
+    q.came_from.insert(start.into(), (start.into(), 0));
+
+    while let Some(Reverse((_, cost, pos))) = q.heap.pop() {
+        let cc: C = pos.into();
+        if cc == goal {
+            let mut path = vec![cc];
+            let mut pos = pos;
+            while pos != start.as_pos() {
+                pos = q.came_from[&pos].0;
+                path.push(pos.into());
+            }
+            path.reverse();
             q.clear();
-            return false;
-        } else {
-            cur -= 1;
-            best = cur;
+            return Some(path);
+        }
+        // we have since found a cheaper way here
+        if cost > q.came_from[&pos].1 {
+            continue;
+        }
+        for c in neighbors.iter(cc) {
+            let Some(step) = p(c) else {
+                continue;
+            };
+            let new_cost = cost + step.max(1) as u32;
+            if let Some(&(_, old_cost)) = q.came_from.get(&c.as_pos()) {
+                if old_cost <= new_cost {
+                    continue;
+                }
+            }
+            q.came_from.insert(c.into(), (pos, new_cost));
+            q.heap.push(Reverse((new_cost + c.distance(goal) as u32, new_cost, c.into())));
         }
     }
+
+    q.clear();
+    None
 }
 
+/// Distance field (multi-source Dijkstra)
+///
+/// Computes, for every tile of a map with the given radius, the cost of the
+/// cheapest path from any of `sources`. `P` is like for `find_path`.
+/// Unreachable tiles are `u16::MAX`.
+///
+/// Uses externally-provided queue to reuse memory allocations.
+pub fn distance_map<C: Coord, P>(
+    q: &mut PathQ,
+    size: u8,
+    sources: impl IntoIterator<Item = C>,
+    neighbors: Neighbors,
+    p: P,
+) -> MapData<C, u16>
+where
+    P: Fn(C) -> Option<u16>,
+{
+    let mut map = MapData::new(size, u16::MAX);
+    q.clear();
+    for c in sources {
+        if let Some(d) = map.get_mut(c) {
+            *d = 0;
+            q.heap.push(Reverse((0, 0, c.into())));
+        }
+    }
+
+    while let Some(Reverse((cost, _, pos))) = q.heap.pop() {
+        let cc: C = pos.into();
+        if cost > map[cc] as u32 {
+            continue;
+        }
+        for c in neighbors.iter(cc) {
+            let Some(&old) = map.get(c) else {
+                continue;
+            };
+            let Some(step) = p(c) else {
+                continue;
+            };
+            let new_cost = (cost + step.max(1) as u32).min(u16::MAX as u32 - 1);
+            if new_cost < old as u32 {
+                map[c] = new_cost as u16;
+                q.heap.push(Reverse((new_cost, 0, c.into())));
+            }
+        }
+    }
+
+    q.clear();
+    map
+}
+
+/// Region ID for tiles that do not belong to any city (water)
+pub const NO_REGION: u8 = u8::MAX;
+
+/// Partition the map into city regions
+///
+/// Every ownable tile is assigned to the city with the shortest travel
+/// distance over land tiles. Mountains and forests are assigned too, but
+/// cannot be travelled through. Ties go to the city listed first.
+///
+/// Islands that cannot be reached from any city are assigned as a whole,
+/// to the city closest in a straight line to any of their tiles.
+///
+/// Water is `NO_REGION`. Uses externally-provided queue to reuse memory
+/// allocations.
+pub fn regions<C: Coord>(
+    q: &mut PathQ,
+    kinds: &MapData<C, TileKind>,
+    cits: &[C],
+) -> MapData<C, u8> {
+    assert!(cits.len() < NO_REGION as usize);
+
+    // (travel cost, region)
+    let mut map = kinds.convert(|_, _| (u32::MAX, NO_REGION));
+    q.clear();
+    for (i, &c) in cits.iter().enumerate() {
+        if let Some(d) = map.get_mut(c) {
+            if d.0 != 0 {
+                *d = (0, i as u8);
+                q.heap.push(Reverse((0, i as u32, c.into())));
+            }
+        }
+    }
+
+    while let Some(Reverse((cost, region, pos))) = q.heap.pop() {
+        let cc: C = pos.into();
+        if (cost, region as u8) != map[cc] {
+            continue;
+        }
+        // cities can be on any tile, but only land can be travelled through
+        if cost != 0 && !kinds[cc].is_land() {
+            continue;
+        }
+        for c in cc.iter_n0_in(kinds) {
+            if !kinds[c].ownable() {
+                continue;
+            }
+            let new = (cost + 1, region as u8);
+            if new < map[c] {
+                map[c] = new;
+                q.heap.push(Reverse((new.0, region, c.into())));
+            }
+        }
+    }
+    q.clear();
+
+    let mut out = map.convert(|_, d| d.1);
+
+    // islands
+    let mut fq = FloodQ::new();
+    for c in kinds.iter_coords(None) {
+        if out[c] != NO_REGION || !kinds[c].ownable() || cits.is_empty() {
+            continue;
+        }
+        let mut island = vec![c];
+        out[c] = 0;
+        fq.push_back(c.into());
+        flood(&mut fq, |c2: C, _| {
+            if out.get(c2) != Some(&NO_REGION) || !kinds[c2].ownable() {
+                return FloodSelect::No;
+            }
+            island.push(c2);
+            out[c2] = 0;
+            FloodSelect::Yes
+        });
+        let region = island.iter()
+            .flat_map(|c| cits.iter().enumerate().map(|(i, cit)| (c.distance(*cit), i)))
+            .min()
+            .map(|(_, i)| i as u8)
+            .unwrap();
+        for c in island {
+            out[c] = region;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::grid::{Hex, Sq};
+
+    use super::*;
+
+    fn check_path<C: Coord>(path: &[C], start: C, goal: C, neighbors: Neighbors) {
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        for w in path.windows(2) {
+            assert!(neighbors.iter(w[0]).any(|c| c == w[1]), "{:?} -> {:?}", w[0], w[1]);
+        }
+    }
+
+    #[test]
+    fn path_open_hex() {
+        let mut q = PathQ::default();
+        let map = MapData::<Hex, ()>::new(5, ());
+        let (start, goal) = (Hex(-3, 0), Hex(2, 2));
+        let path = find_path(&mut q, start, goal, Neighbors::N0, |c| map.get(c).map(|_| 1)).unwrap();
+        check_path(&path, start, goal, Neighbors::N0);
+        assert_eq!(path.len(), start.distance(goal) as usize + 1);
+        let path = find_path(&mut q, start, start, Neighbors::N0, |c| map.get(c).map(|_| 1)).unwrap();
+        assert_eq!(path, vec![start]);
+    }
+
+    #[test]
+    fn path_sq_neighbors() {
+        let mut q = PathQ::default();
+        let map = MapData::<Sq, ()>::new(5, ());
+        let (start, goal) = (Sq(0, 0), Sq(3, 3));
+        let path = find_path(&mut q, start, goal, Neighbors::N1, |c| map.get(c).map(|_| 1)).unwrap();
+        check_path(&path, start, goal, Neighbors::N1);
+        assert_eq!(path.len(), 4);
+        let path = find_path(&mut q, start, goal, Neighbors::N0, |c| map.get(c).map(|_| 1)).unwrap();
+        check_path(&path, start, goal, Neighbors::N0);
+        assert_eq!(path.len(), 7);
+    }
+
+    #[test]
+    fn path_walls_sq() {
+        let mut q = PathQ::default();
+        // wall across the map, with a gap at the top
+        let mut map = MapData::<Sq, bool>::new(4, true);
+        for y in -4..4 {
+            map[Sq(y, 0)] = false;
+        }
+        let (start, goal) = (Sq(0, -2), Sq(0, 2));
+        let path = find_path(&mut q, start, goal, Neighbors::N0, |c| {
+            map.get(c).copied().filter(|x| *x).map(|_| 1)
+        }).unwrap();
+        check_path(&path, start, goal, Neighbors::N0);
+        assert!(path.contains(&Sq(4, 0)));
+        assert_eq!(path.len(), 13);
+
+        map[Sq(4, 0)] = false;
+        let path = find_path(&mut q, start, goal, Neighbors::N0, |c| {
+            map.get(c).copied().filter(|x| *x).map(|_| 1)
+        });
+        assert_eq!(path, None);
+    }
+
+    #[test]
+    fn path_costs_hex() {
+        let mut q = PathQ::default();
+        // expensive tiles on the direct route
+        let map = MapData::<Hex, u16>::new_with(4, |c| if c.0 == 0 && c.1.abs() < 3 { 10 } else { 1 });
+        let (start, goal) = (Hex(-3, 0), Hex(3, 0));
+        let path = find_path(&mut q, start, goal, Neighbors::N0, |c| map.get(c).copied()).unwrap();
+        check_path(&path, start, goal, Neighbors::N0);
+        let cost: u16 = path[1..].iter().map(|c| map[*c]).sum();
+        assert!(cost < 10);
+        let dist = distance_map(&mut q, 4, [start], Neighbors::N0, |c| map.get(c).copied());
+        assert_eq!(dist[goal], cost);
+    }
+
+    #[test]
+    fn distance_multi_source() {
+        let mut q = PathQ::default();
+        let sources = [Hex(-2, 1), Hex(3, -1), Hex(0, 0)];
+        let dist = distance_map(&mut q, 5, sources, Neighbors::N0, |_| Some(1));
+        for (c, d) in dist.iter() {
+            let expected = sources.iter().map(|s| s.distance(c)).min().unwrap();
+            assert_eq!(*d, expected, "{:?}", c);
+        }
+
+        // an island that cannot be reached
+        let walls: Vec<Sq> = Sq(3, 3).iter_n1().collect();
+        let dist = distance_map(&mut q, 5, [Sq(0, 0)], Neighbors::N1, |c| {
+            (!walls.contains(&c)).then_some(1)
+        });
+        assert_eq!(dist[Sq(3, 3)], u16::MAX);
+        assert_eq!(dist[Sq(-5, 5)], 5);
+        assert_eq!(dist[Sq(3, 2)], u16::MAX);
+    }
+
+    #[test]
+    fn agrees_with_distance_map() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let mut q = PathQ::default();
+        let mut rq = ReachQ::new();
+        for _ in 0..50 {
+            let map = MapData::<Hex, u16>::new_with(6, |_| {
+                if rng.gen_bool(0.4) { 0 } else { rng.gen_range(1..4) }
+            });
+            let cost = |c: Hex| map.get(c).copied().filter(|x| *x != 0);
+            let coords: Vec<Hex> = map.iter().filter(|(_, x)| **x != 0).map(|(c, _)| c).collect();
+            let start = coords[rng.gen_range(0..coords.len())];
+            let goal = coords[rng.gen_range(0..coords.len())];
+            let dist = distance_map(&mut q, 6, [start], Neighbors::N0, cost);
+            match find_path(&mut q, start, goal, Neighbors::N0, cost) {
+                Some(path) => {
+                    check_path(&path, start, goal, Neighbors::N0);
+                    assert_eq!(path[1..].iter().map(|c| map[*c]).sum::<u16>(), dist[goal]);
+                }
+                None => assert_eq!(dist[goal], u16::MAX),
+            }
+            let found = reach(&mut rq, start, |c| cost(c).map(|_| c.distance(goal)));
+            assert_eq!(found, dist[goal] != u16::MAX);
+        }
+    }
+
+    #[test]
+    fn regions_travel() {
+        let mut q = PathQ::default();
+        // a line of land, cities at both ends
+        let kinds = MapData::<Sq, _>::new_with(3, |c| if c.0 == 0 { TileKind::Regular } else { TileKind::Water });
+        let out = regions(&mut q, &kinds, &[Sq(0, -3), Sq(0, 3)]);
+        assert_eq!(out[Sq(0, -1)], 0);
+        assert_eq!(out[Sq(0, 1)], 1);
+        // tie goes to the first city
+        assert_eq!(out[Sq(0, 0)], 0);
+        assert_eq!(out[Sq(1, 0)], NO_REGION);
+
+        // a water wall with a gap at the bottom: tiles just behind the wall
+        // are closer to city 0 in a straight line, but belong to city 1
+        let kinds = MapData::<Sq, _>::new_with(4, |c| match c {
+            Sq(y, 0) if y < 4 => TileKind::Water,
+            Sq(-4, -4) => TileKind::Mountain,
+            _ => TileKind::Regular,
+        });
+        let out = regions(&mut q, &kinds, &[Sq(-3, -1), Sq(-3, 4)]);
+        assert_eq!(out[Sq(-3, 1)], 1);
+        assert_eq!(out[Sq(0, 1)], 1);
+        assert_eq!(out[Sq(3, -1)], 0);
+        assert_eq!(out[Sq(4, 0)], 0);
+        assert_eq!(out[Sq(-4, -4)], 0);
+        assert_eq!(out[Sq(0, 0)], NO_REGION);
+    }
+
+    #[test]
+    fn regions_blocked() {
+        let mut q = PathQ::default();
+        let mountains = [Hex(0, 1), Hex(1, 0), Hex(1, -1), Hex(0, -1), Hex(-1, 0), Hex(-1, 1)];
+        let island = [Hex(4, -1), Hex(4, 0), Hex(3, 0)];
+        let kinds = MapData::<Hex, _>::new_with(4, |c| {
+            if mountains.contains(&c) {
+                TileKind::Mountain
+            } else if island.contains(&c) {
+                TileKind::Fertile
+            } else if c.ring() == 4 || c.0 >= 2 {
+                TileKind::Water
+            } else {
+                TileKind::Regular
+            }
+        });
+        // city 1 is surrounded by mountains
+        let out = regions(&mut q, &kinds, &[Hex(-2, 1), Hex(0, 0)]);
+        assert_eq!(out[Hex(0, 0)], 1);
+        assert_eq!(out[Hex(0, 1)], 1);
+        // right behind the mountains, but city 1 cannot travel through them
+        assert_eq!(out[Hex(1, 1)], 0);
+        // the whole island goes to the closest city
+        for c in island {
+            assert_eq!(out[c], 1, "{:?}", c);
+        }
+        for (c, kind) in kinds.iter() {
+            assert_eq!(out[c] == NO_REGION, *kind == TileKind::Water, "{:?}", c);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{prelude::*, plid::{PlayerId, Plids}};
+use crate::{prelude::*, plid::{PlayerId, PlidMask}};
 
 /// Abstract interface through which the Game communicates with the Host
 ///
@@ -8,7 +8,10 @@ use crate::{prelude::*, plid::{PlayerId, Plids}};
 /// implement this trait using its respective timers, events, etc.
 pub trait Host<G: Game>: Sized {
     /// Notify the Host about something that happened in the game world
-    fn msg(&mut self, plids: Plids, event: G::OutEvent);
+    ///
+    /// `plids` can be a `Plids` or, for sessions with more than 15 plids,
+    /// a `PlidsBig`.
+    fn msg<P: PlidMask>(&mut self, plids: P, event: G::OutEvent);
     /// Request an action to occur at a specific future time
     fn sched(&mut self, time: Instant, event: G::SchedEvent);
     /// Cancel scheduled events equal to the value given
@@ -27,7 +30,7 @@ pub trait Game: Sized {
     ///
     /// When the Host calls either `Game::input_action` or `Game::unsched`
     /// to drive the Game, it can call `Host::msg` to send output events.
-    /// They will be broadcast to all player ids selected with `Plids`.
+    /// They will be broadcast to all player ids selected with `plids`.
     type OutEvent: Clone + Send + Sync + 'static;
 
     /// For things that need to be triggered on a timeout
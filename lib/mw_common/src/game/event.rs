@@ -63,6 +63,27 @@ pub enum PlayerEv {
     MatchTimeRemain {
         secs: u16,
     },
+    /// A tile that the player can figure out from what they know
+    ///
+    /// `mine` is true if the tile certainly has a mine, false if it is
+    /// certainly safe to explore.
+    Hint {
+        pos: Pos,
+        mine: bool,
+    },
+    /// How the player did, sent to everyone when the game is over
+    ///
+    /// `rank` starts from 1 (the winner). Players who explored the same number
+    /// of tiles share a rank.
+    /// `eliminated_secs` is when the player was eliminated, counted from
+    /// the start of the game, or `None` if they survived to the end.
+    GameResult {
+        rank: u8,
+        tiles: u16,
+        mines_hit: u8,
+        lives: u8,
+        eliminated_secs: Option<u16>,
+    },
     FriendlyChat(String),
     AllChat(String),
 }
@@ -82,8 +103,12 @@ pub enum MapEv {
     Item {
         kind: ItemKind,
     },
+    /// A flag was placed on (or removed from, if `plid` is neutral) a tile
+    ///
+    /// `question` is true if it is a question mark instead of a flag.
     Flag {
         plid: PlayerId,
+        question: bool,
     },
     Explode,
     Smoke {
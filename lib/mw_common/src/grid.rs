@@ -24,14 +24,18 @@ use enum_map::Enum;
 use thiserror::Error;
 use serde::{Serialize, Deserialize};
 
+pub mod fov;
 pub mod hex;
 pub mod map;
 pub mod pos;
+pub mod shape;
 pub mod sq;
+pub mod symmetry;
 
 pub use hex::Hex;
 pub use map::MapData;
 pub use pos::Pos;
+pub use shape::MapShape;
 pub use sq::Sq;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -75,6 +79,8 @@ pub trait Coord:
     const N1: usize;
     const N2: usize;
     const TOPOLOGY: Topology;
+    /// Number of rotation steps in a full turn
+    const ROTATIONS: u8;
 
     type IterN0: Iterator<Item = Self>;
     type IterN1: Iterator<Item = Self>;
@@ -88,10 +94,20 @@ pub trait Coord:
     fn distance(self, other: Self) -> u16;
     fn ring(self) -> u8;
     fn ring_len(r: u8) -> usize;
+    /// Is the coordinate within a map of radius `r`?
+    fn in_bounds(self, r: u8) -> bool;
     fn iter_n0(self) -> Self::IterN0;
     fn iter_n1(self) -> Self::IterN1;
     fn iter_n2(self) -> Self::IterN2;
     fn iter_ring(self, radius: u8) -> Self::IterRing;
+    /// `iter_n0`, skipping tiles outside of the map
+    fn iter_n0_in<D>(self, map: &MapData<Self, D>) -> InBounds<Self::IterN0> {
+        InBounds::new(self.iter_n0(), map.shape().clone())
+    }
+    /// `iter_n1`, skipping tiles outside of the map
+    fn iter_n1_in<D>(self, map: &MapData<Self, D>) -> InBounds<Self::IterN1> {
+        InBounds::new(self.iter_n1(), map.shape().clone())
+    }
     fn from_f32_clamped(xy: (f32, f32)) -> Self;
     fn translation(self) -> glam::Vec2;
     fn as_pos(self) -> Pos {
@@ -104,8 +120,43 @@ pub trait Coord:
     fn xmax(r: u8, y: i8) -> i8;
     fn index(r: u8, c: Self) -> usize;
     fn iter_coords(r: u8) -> Self::IterCoords;
+    /// Rotate around the origin, by `steps` (60° for hex, 90° for square)
+    fn rotated(self, steps: u8) -> Self;
+    /// Mirror across one of the `ROTATIONS` axes through the origin
+    ///
+    /// Axis `0` swaps the two coordinates. The others are axis `0` followed
+    /// by `axis` rotation steps.
+    fn mirrored(self, axis: u8) -> Self;
+    fn transformed(self, t: symmetry::Transform) -> Self {
+        match t {
+            symmetry::Transform::Identity => self,
+            symmetry::Transform::Rotate(steps) => self.rotated(steps),
+            symmetry::Transform::Mirror(axis) => self.mirrored(axis),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 #[error("Out of bounds")]
 pub struct OutOfBoundsError;
+
+/// Iterator adapter that skips coordinates outside of a map of a given shape
+pub struct InBounds<I> {
+    iter: I,
+    shape: MapShape,
+}
+
+impl<I> InBounds<I> {
+    pub fn new(iter: I, shape: MapShape) -> Self {
+        Self { iter, shape }
+    }
+}
+
+impl<C: Coord, I: Iterator<Item = C>> Iterator for InBounds<I> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<C> {
+        let shape = &self.shape;
+        self.iter.find(|c| shape.contains(*c))
+    }
+}
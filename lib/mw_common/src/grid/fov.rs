@@ -0,0 +1,176 @@
+//! Line of sight and field of view
+//!
+//! Lines are computed by linear interpolation and rounding to the nearest
+//! tile, which works the same for both topologies and is symmetric: the line
+//! from `a` to `b` has the same tiles as the line from `b` to `a`.
+
+use super::*;
+
+/// Interpolate between two tiles
+///
+/// `nudge` breaks ties for points exactly on the edge between two tiles.
+fn lerp<C: Coord>(a: C, b: C, i: u16, n: u16, nudge: f32) -> C {
+    let t = i as f32 / n as f32;
+    let a: Pos = a.into();
+    let b: Pos = b.into();
+    let y = a.0 as f32 + (b.0 as f32 - a.0 as f32) * t;
+    let x = a.1 as f32 + (b.1 as f32 - a.1 as f32) * t;
+    match C::TOPOLOGY {
+        Topology::Hex => {
+            // round in cube coordinates
+            let (fy, fx) = (y + nudge, x + nudge);
+            let fz = -y - x - 2.0 * nudge;
+            let (mut ry, mut rx, rz) = (fy.round(), fx.round(), fz.round());
+            let (dy, dx, dz) = ((ry - fy).abs(), (rx - fx).abs(), (rz - fz).abs());
+            if dy > dx && dy > dz {
+                ry = -rx - rz;
+            } else if dx > dz {
+                rx = -ry - rz;
+            }
+            Pos(ry as i8, rx as i8).into()
+        }
+        Topology::Sq => {
+            Pos((y + nudge).round() as i8, (x + nudge).round() as i8).into()
+        }
+    }
+}
+
+fn line_nudged<C: Coord>(a: C, b: C, nudge: f32) -> impl Iterator<Item = C> {
+    let n = a.distance(b);
+    (0..=n).map(move |i| if n == 0 { a } else { lerp(a, b, i, n, nudge) })
+}
+
+/// All the tiles on a straight line from `a` to `b` (inclusive)
+///
+/// Consecutive tiles are `n1` neighbors.
+pub fn line<C: Coord>(a: C, b: C) -> impl Iterator<Item = C> {
+    line_nudged(a, b, 1e-4)
+}
+
+/// Can `to` be seen from `from`?
+///
+/// `blocks` says if a tile obstructs vision. The end points themselves
+/// do not obstruct (you can see a mountain, but not what is behind it).
+/// If the line passes exactly between two tiles, one of them being clear
+/// is enough.
+pub fn has_los<C: Coord>(from: C, to: C, blocks: impl Fn(C) -> bool) -> bool {
+    let n = from.distance(to) as usize;
+    if n < 2 {
+        return true;
+    }
+    [1e-4, -1e-4].iter().any(|nudge| {
+        line_nudged(from, to, *nudge)
+            .skip(1)
+            .take(n - 1)
+            .all(|c| !blocks(c))
+    })
+}
+
+/// Mark all tiles visible from `origin` within `radius`
+///
+/// Tiles are only ever set to `true`, so this can be called repeatedly
+/// to accumulate the vision from many sources.
+pub fn fov<C: Coord>(
+    out: &mut MapData<C, bool>,
+    origin: C,
+    radius: u8,
+    blocks: impl Fn(C) -> bool,
+) {
+    let Some(vis) = out.get_mut(origin) else {
+        return;
+    };
+    *vis = true;
+    for r in 1..=radius {
+        for c in origin.iter_ring(r) {
+            let Some(&visible) = out.get(c) else {
+                continue;
+            };
+            if !visible && has_los(origin, c, &blocks) {
+                out[c] = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+
+    use super::*;
+
+    fn check_line<C: Coord>(a: C, b: C) {
+        let tiles: Vec<C> = line(a, b).collect();
+        assert_eq!(tiles.len(), a.distance(b) as usize + 1);
+        assert_eq!(tiles.first(), Some(&a));
+        assert_eq!(tiles.last(), Some(&b));
+        for w in tiles.windows(2) {
+            assert!(w[0].iter_n1().any(|c| c == w[1]), "{:?} -> {:?}", w[0], w[1]);
+        }
+        let mut back: Vec<C> = line(b, a).collect();
+        back.reverse();
+        assert_eq!(tiles, back);
+    }
+
+    #[test]
+    fn lines() {
+        for a in Hex::iter_coords(4) {
+            for b in Hex::iter_coords(4) {
+                check_line(a, b);
+            }
+        }
+        for a in Sq::iter_coords(4) {
+            for b in Sq::iter_coords(4) {
+                check_line(a, b);
+            }
+        }
+        let tiles: Vec<Sq> = line(Sq(0, 0), Sq(0, 3)).collect();
+        assert_eq!(tiles, vec![Sq(0, 0), Sq(0, 1), Sq(0, 2), Sq(0, 3)]);
+    }
+
+    #[test]
+    fn fov_open() {
+        let mut vis = MapData::<Hex, bool>::new(6, false);
+        fov(&mut vis, Hex(1, 1), 3, |_| false);
+        for (c, v) in vis.iter() {
+            assert_eq!(*v, c.distance(Hex(1, 1)) <= 3, "{:?}", c);
+        }
+        // at the edge of the map
+        let mut vis = MapData::<Sq, bool>::new(3, false);
+        fov(&mut vis, Sq(3, 3), 2, |_| false);
+        assert_eq!(vis.iter().filter(|(_, v)| **v).count(), 9);
+    }
+
+    #[test]
+    fn fov_blocked() {
+        // a wall right next to the origin
+        let wall = [Sq(-1, 1), Sq(0, 1), Sq(1, 1)];
+        let mut vis = MapData::<Sq, bool>::new(5, false);
+        fov(&mut vis, Sq(0, 0), 4, |c| wall.contains(&c));
+        for c in wall {
+            assert!(vis[c]);
+        }
+        assert!(!vis[Sq(0, 2)]);
+        assert!(!vis[Sq(1, 4)]);
+        assert!(vis[Sq(0, -4)]);
+        assert!(vis[Sq(4, 1)]);
+
+        let mut vis = MapData::<Hex, bool>::new(5, false);
+        fov(&mut vis, Hex(0, 0), 3, |c| c == Hex(0, 1));
+        assert!(vis[Hex(0, 1)]);
+        assert!(!vis[Hex(0, 2)]);
+        assert!(!vis[Hex(0, 3)]);
+        assert!(vis[Hex(1, 1)]);
+    }
+
+    #[test]
+    fn los_symmetric() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(7);
+        let blocked = MapData::<Hex, bool>::new_with(5, |_| rng.gen_bool(0.25));
+        let blocks = |c: Hex| blocked.get(c).copied().unwrap_or(true);
+        for a in Hex::iter_coords(5) {
+            for b in Hex::iter_coords(5) {
+                assert_eq!(has_los(a, b, blocks), has_los(b, a, blocks));
+            }
+        }
+    }
+}
@@ -33,6 +33,7 @@ impl Coord for Hex {
     const N2: usize = 6;
 
     const TOPOLOGY: super::Topology = super::Topology::Hex;
+    const ROTATIONS: u8 = 6;
 
     type IterN0 = IterNeigh;
     type IterN1 = IterNeigh;
@@ -78,6 +79,15 @@ impl Coord for Hex {
         r as usize * 6
     }
 
+    fn in_bounds(self, r: u8) -> bool {
+        // not using `ring`, which overflows for coords near the i8 limits
+        let v0 = self.0 as i16;
+        let v1 = self.1 as i16;
+        let v2 = 0 - v0 - v1;
+
+        (v0.abs() + v1.abs() + v2.abs()) / 2 <= r as i16
+    }
+
     fn translation(self) -> glam::Vec2 {
         let y = self.0 as f32;
         let x = self.1 as f32;
@@ -200,6 +210,17 @@ impl Coord for Hex {
             next: Some(Hex(-(r as i8), 0)),
         }
     }
+    fn rotated(self, steps: u8) -> Self {
+        // in cube coordinates: (a, b, c) -> (-b, -c, -a)
+        let mut c = self;
+        for _ in 0..(steps % Self::ROTATIONS) {
+            c = Hex(-c.1, c.0 + c.1);
+        }
+        c
+    }
+    fn mirrored(self, axis: u8) -> Self {
+        Hex(self.1, self.0).rotated(axis)
+    }
 }
 
 impl TryFrom<(f32, f32)> for Hex {
@@ -447,14 +468,13 @@ impl Iterator for IterCoords {
         if let Some(Hex(y, x)) = &mut self.next {
             let xmax = if *y < 0 { r } else { r - *y };
 
-            *x += 1;
-
-            if *x > xmax {
+            // careful not to overflow at the last tile of the biggest maps
+            if *x < xmax {
+                *x += 1;
+            } else if *y < r {
                 *y += 1;
                 *x = if *y < 0 { -r - *y } else { -r }
-            }
-
-            if *y > r {
+            } else {
                 self.next = None;
             }
         }
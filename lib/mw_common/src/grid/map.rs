@@ -2,15 +2,22 @@ use std::io::Write;
 use std::marker::PhantomData;
 use std::ops::{Index, IndexMut};
 
+use serde::{de, Deserializer, Serializer};
+
 use super::*;
+use super::shape::ShapeCoords;
+use super::symmetry::{wedge_tile, Transform};
 
-/// Map storage for a "radial" map, as a compact dense array.
+/// Map storage, as a compact dense array.
 ///
 /// `C` is the type of coordinate.
 /// `D` is the data to store for each map cell.
+///
+/// Maps are "radial" by default; see `MapShape` for other shapes.
 #[derive(Clone)]
 pub struct MapData<C: Coord, D> {
     size: u8,
+    shape: MapShape,
     data: Vec<D>,
     _c: PhantomData<C>,
 }
@@ -21,37 +28,83 @@ impl<C: Coord, D: Clone> MapData<C, D> {
     /// All cells initialized with clones of the provided value.
     pub fn new(size: u8, init: D) -> Self {
         assert!(size <= 127);
+        Self::new_shape(MapShape::Radial(size), init)
+    }
+
+    /// Construct map with given shape
+    ///
+    /// All cells initialized with clones of the provided value.
+    pub fn new_shape(shape: MapShape, init: D) -> Self {
+        assert!(shape.is_valid::<C>());
 
-        let len = C::map_area(size);
+        let len = shape.area::<C>();
 
         let mut data = Vec::with_capacity(len);
         data.resize(len, init);
 
         Self {
-            size,
+            size: shape.size::<C>(),
+            shape,
             data,
             _c: PhantomData,
         }
     }
 }
 
+impl<C: Coord, D: Clone> MapData<C, D> {
+    /// Copy of the map, with the data of each tile moved by a symmetry transform
+    ///
+    /// Only for radial maps.
+    pub fn transformed(&self, t: Transform) -> Self {
+        assert_eq!(self.shape, MapShape::Radial(self.size));
+        let inverse = t.inverse::<C>();
+        Self::new_with(self.size, |c| self[c.transformed(inverse)].clone())
+    }
+
+    /// Construct map with `n`-fold rotational symmetry
+    ///
+    /// `f` is only called for the tiles of one wedge (see `symmetry::wedge_tile`);
+    /// the rest of the map is copies of them. `n` must divide `C::ROTATIONS`.
+    pub fn new_symmetric<F: FnMut(C) -> D>(size: u8, n: u8, mut f: F) -> Self {
+        assert!(n > 0 && C::ROTATIONS % n == 0);
+
+        let mut wedge = MapData::<C, Option<D>>::new_with(size, |_| None);
+        for c in C::iter_coords(size) {
+            if wedge_tile(c, n) == c {
+                wedge[c] = Some(f(c));
+            }
+        }
+
+        Self::new_with(size, |c| wedge[wedge_tile(c, n)].as_ref().unwrap().clone())
+    }
+}
+
 impl<C: Coord, D> MapData<C, D> {
     /// Construct map with given radius
     ///
     /// All cells initialized with clones of the provided value.
-    pub fn new_with<F: FnMut(C) -> D>(size: u8, mut f: F) -> Self {
+    pub fn new_with<F: FnMut(C) -> D>(size: u8, f: F) -> Self {
         assert!(size <= 127);
+        Self::new_shape_with(MapShape::Radial(size), f)
+    }
 
-        let len = C::map_area(size);
+    /// Construct map with given shape
+    ///
+    /// All cells initialized with the values returned by the closure.
+    pub fn new_shape_with<F: FnMut(C) -> D>(shape: MapShape, mut f: F) -> Self {
+        assert!(shape.is_valid::<C>());
+
+        let len = shape.area::<C>();
 
         let mut data = Vec::with_capacity(len);
 
-        for c in C::iter_coords(size) {
+        for c in shape.iter_coords() {
             data.push(f(c));
         }
 
         Self {
-            size,
+            size: shape.size::<C>(),
+            shape,
             data,
             _c: PhantomData,
         }
@@ -62,28 +115,38 @@ impl<C: Coord, D> Index<C> for MapData<C, D> {
     type Output = D;
 
     fn index(&self, c: C) -> &D {
-        let i = C::index(self.size, c);
+        debug_assert!(self.contains(c), "{:?} is outside of map with shape {:?}", c, self.shape);
+        let i = self.shape.index(c);
         self.data.index(i)
     }
 }
 
 impl<C: Coord, D> IndexMut<C> for MapData<C, D> {
     fn index_mut(&mut self, c: C) -> &mut D {
-        let i = C::index(self.size, c);
+        debug_assert!(self.contains(c), "{:?} is outside of map with shape {:?}", c, self.shape);
+        let i = self.shape.index(c);
         self.data.index_mut(i)
     }
 }
 
 impl<C: Coord, D> MapData<C, D> {
     /// Radius of map (number of rings)
+    ///
+    /// For maps that are not radial, this is the smallest radius
+    /// that contains all the tiles.
     pub fn size(&self) -> u8 {
         self.size
     }
 
+    pub fn shape(&self) -> &MapShape {
+        &self.shape
+    }
+
     /// Construct new map based on data from another map
     pub fn convert<T, F: FnMut(C, &D) -> T>(&self, mut f: F) -> MapData<C, T> {
         MapData {
             size: self.size,
+            shape: self.shape.clone(),
             data: self.iter().map(|(c, d)| f(c, d)).collect(),
             _c: PhantomData,
         }
@@ -101,9 +164,13 @@ impl<C: Coord, D> MapData<C, D> {
         if x.abs() as u8 + new_size > self.size || y.abs() as u8 + new_size > self.size {
             return Err(OutOfBoundsError);
         }
+        if C::iter_coords(new_size).any(|c| !self.contains(c + offset)) {
+            return Err(OutOfBoundsError);
+        }
 
         Ok(MapData {
             size: new_size,
+            shape: MapShape::Radial(new_size),
             data: self
                 .iter_at(offset, new_size)
                 .map(|(c0, c1, d)| f(c0, c1, d))
@@ -115,50 +182,59 @@ impl<C: Coord, D> MapData<C, D> {
     /// Print map as ascii art
     ///
     /// Given closure provides byte to output for each cell
+    /// Tiles outside of a masked map are blank.
     pub fn ascii_art<W: Write, F: Fn(C, &D) -> u8>(&self, w: &mut W, f: F) -> std::io::Result<()> {
-        let mut y = -(self.size as i8);
+        let coords = match &self.shape {
+            MapShape::Masked(_) => MapShape::Radial(self.size).iter_coords(),
+            shape => shape.iter_coords(),
+        };
+        let mut rows = self.shape.aa_rows::<C>().into_iter();
         let mut next_row = 0;
 
-        for (i, (c, d)) in self.iter().enumerate() {
+        for (i, c) in coords.enumerate() {
             if i == next_row {
                 if i != 0 {
-                    w.write(&[b'\n'])?;
+                    w.write_all(b"\n")?;
                 }
 
-                for _ in 0..C::aa_indent(y) {
-                    w.write(&[b' '])?;
+                let (len, indent) = rows.next().unwrap();
+                for _ in 0..indent {
+                    w.write_all(b" ")?;
                 }
 
-                next_row += C::row_len(self.size, y);
-                y += 1;
+                next_row += len;
             }
 
-            w.write(&[b' ', f(c, d)])?;
+            w.write_all(&[b' ', self.get(c).map(|d| f(c, d)).unwrap_or(b' ')])?;
         }
 
-        w.write(&[b'\n'])?;
+        w.write_all(b"\n")?;
 
         Ok(())
     }
 
+    /// Is the coordinate within the map?
+    pub fn contains(&self, c: C) -> bool {
+        self.shape.contains(c)
+    }
+
     pub fn get(&self, c: C) -> Option<&D> {
-        // FIXME
-        if c.ring() > self.size {
+        if !self.contains(c) {
             return None;
         }
-        let i = C::index(self.size, c);
+        let i = self.shape.index(c);
         self.data.get(i)
     }
 
     pub fn get_mut(&mut self, c: C) -> Option<&mut D> {
-        // FIXME
-        if c.ring() > self.size {
+        if !self.contains(c) {
             return None;
         }
-        let i = C::index(self.size, c);
+        let i = self.shape.index(c);
         self.data.get_mut(i)
     }
 
+    /// The data of all tiles, in the order of `iter_coords`
     pub fn data(&self) -> &[D] {
         &self.data
     }
@@ -167,9 +243,13 @@ impl<C: Coord, D> MapData<C, D> {
         &mut self.data
     }
 
-    pub fn iter_coords(&self, max_r: Option<u8>) -> C::IterCoords {
-        let r = max_r.map(|r| r.min(self.size)).unwrap_or(self.size);
-        C::iter_coords(r)
+    /// All tiles of the map, optionally only up to the given radius
+    pub fn iter_coords(&self, max_r: Option<u8>) -> impl Iterator<Item = C> {
+        let coords: ShapeCoords<C> = match (&self.shape, max_r) {
+            (MapShape::Radial(r), Some(max_r)) => MapShape::Radial((*r).min(max_r)).iter_coords(),
+            (shape, _) => shape.iter_coords(),
+        };
+        coords.filter(move |c| max_r.map(|r| c.ring() <= r).unwrap_or(true))
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (C, &D)> {
@@ -181,8 +261,7 @@ impl<C: Coord, D> MapData<C, D> {
     }
 
     fn iter_at(&self, offset: C, r: u8) -> impl Iterator<Item = (C, C, &D)> {
-        self.iter_coords(Some(r))
-            .into_iter()
+        C::iter_coords(r)
             .map(move |c| (c, c + offset, &self[c + offset]))
     }
 
@@ -200,8 +279,234 @@ impl<C: Coord, D> MapData<C, D> {
     }
 }
 
+/// All coordinates of a map of the given radius, in concentric-ring order
+///
+/// Starts from the center of the map. Each ring starts from the lowest (Y,X)
+/// coordinate and follows the +X direction first.
+pub fn ring_order<C: Coord>(size: u8) -> Vec<C> {
+    let mut r = Vec::with_capacity(C::map_area(size));
+    r.push(C::origin());
+    for ring in 1..=size {
+        let y = -(ring as i8);
+        let start = (y..=(ring as i8))
+            .map(|x| C::from(Pos(y, x)))
+            .find(|c| c.ring() == ring)
+            .unwrap();
+        let mut prev = start;
+        let mut cur = C::from(Pos(start.y(), start.x() + 1));
+        r.push(start);
+        while cur != start {
+            r.push(cur);
+            let next = cur.iter_n0()
+                .find(|c| c.ring() == ring && *c != prev)
+                .unwrap_or(start);
+            prev = cur;
+            cur = next;
+        }
+    }
+    r
+}
+
+/// Problems with serialized map data
+#[derive(Error, Debug)]
+pub enum MapLoadError {
+    #[error("Map is for {0:?} topology, expected {1:?}")]
+    Topology(Topology, Topology),
+    #[error("Map size {0} is too big")]
+    Size(u8),
+    #[error("Map has {0} tiles, expected {1}")]
+    Length(usize, usize),
+    #[error("Map data contains a run of zero length")]
+    EmptyRun,
+    #[error("Invalid map shape")]
+    Shape,
+}
+
+/// Serialized form of `MapData`
+///
+/// For radial maps, the tile data is in `ring_order`, so that it does not
+/// depend on the memory layout of `MapData`. Other shapes are stored in the
+/// order of `MapShape::iter_coords`.
+#[derive(Serialize)]
+#[serde(rename = "MapData")]
+struct MapDataSer<'a, D> {
+    size: u8,
+    topology: Topology,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shape: Option<&'a MapShape>,
+    data: MapEncodingSer<'a, D>,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "MapEncoding")]
+enum MapEncodingSer<'a, D> {
+    Dense(Vec<&'a D>),
+    /// (run length, value)
+    Rle(Vec<(u32, &'a D)>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "MapData")]
+struct MapDataDe<D> {
+    size: u8,
+    topology: Topology,
+    #[serde(default)]
+    shape: Option<MapShape>,
+    data: MapEncodingDe<D>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "MapEncoding")]
+enum MapEncodingDe<D> {
+    Dense(Vec<D>),
+    Rle(Vec<(u32, D)>),
+}
+
+impl<C: Coord, D> MapData<C, D> {
+    fn ser_shape(&self) -> Option<&MapShape> {
+        match self.shape {
+            MapShape::Radial(_) => None,
+            _ => Some(&self.shape),
+        }
+    }
+
+    fn ser_data(&self) -> impl Iterator<Item = &D> {
+        let ring = match self.shape {
+            MapShape::Radial(_) => Some(ring_order::<C>(self.size).into_iter().map(|c| &self[c])),
+            _ => None,
+        };
+        let other = match self.shape {
+            MapShape::Radial(_) => None,
+            _ => Some(self.data.iter()),
+        };
+        ring.into_iter().flatten().chain(other.into_iter().flatten())
+    }
+
+    fn ser_dense(&self) -> MapDataSer<'_, D> {
+        MapDataSer {
+            size: self.size,
+            topology: C::TOPOLOGY,
+            shape: self.ser_shape(),
+            data: MapEncodingSer::Dense(self.ser_data().collect()),
+        }
+    }
+
+    fn ser_rle(&self) -> MapDataSer<'_, D> where D: PartialEq {
+        let mut runs: Vec<(u32, &D)> = vec![];
+        for d in self.ser_data() {
+            match runs.last_mut() {
+                Some((n, last)) if *last == d => *n += 1,
+                _ => runs.push((1, d)),
+            }
+        }
+        MapDataSer {
+            size: self.size,
+            topology: C::TOPOLOGY,
+            shape: self.ser_shape(),
+            data: MapEncodingSer::Rle(runs),
+        }
+    }
+}
+
+impl<D: Clone> MapDataDe<D> {
+    fn into_map<C: Coord>(self) -> Result<MapData<C, D>, MapLoadError> {
+        if self.topology != C::TOPOLOGY {
+            return Err(MapLoadError::Topology(self.topology, C::TOPOLOGY));
+        }
+        if self.size > 127 {
+            return Err(MapLoadError::Size(self.size));
+        }
+        let shape = self.shape.unwrap_or(MapShape::Radial(self.size));
+        if !shape.is_valid::<C>() || shape.size::<C>() != self.size {
+            return Err(MapLoadError::Shape);
+        }
+        let len = shape.area::<C>();
+        let tiles = match self.data {
+            MapEncodingDe::Dense(data) => data,
+            MapEncodingDe::Rle(runs) => {
+                let mut total = 0usize;
+                for (n, _) in runs.iter() {
+                    if *n == 0 {
+                        return Err(MapLoadError::EmptyRun);
+                    }
+                    total = total.saturating_add(*n as usize);
+                }
+                if total != len {
+                    return Err(MapLoadError::Length(total, len));
+                }
+                let mut data = Vec::with_capacity(len);
+                for (n, d) in runs {
+                    data.resize(data.len() + n as usize, d);
+                }
+                data
+            }
+        };
+        if tiles.len() != len {
+            return Err(MapLoadError::Length(tiles.len(), len));
+        }
+        let data = if let MapShape::Radial(_) = shape {
+            let mut slots: Vec<Option<D>> = vec![None; len];
+            for (c, d) in ring_order::<C>(self.size).into_iter().zip(tiles) {
+                slots[C::index(self.size, c)] = Some(d);
+            }
+            slots.into_iter().map(Option::unwrap).collect()
+        } else {
+            tiles
+        };
+        Ok(MapData {
+            size: self.size,
+            shape,
+            data,
+            _c: PhantomData,
+        })
+    }
+}
+
+impl<C: Coord, D: Serialize> Serialize for MapData<C, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.ser_dense().serialize(serializer)
+    }
+}
+
+/// Accepts both the dense and the run-length-encoded form
+impl<'de, C: Coord, D: Deserialize<'de> + Clone> Deserialize<'de> for MapData<C, D> {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        MapDataDe::deserialize(deserializer)?
+            .into_map()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Run-length-encoded serialization of `MapData`
+///
+/// For large maps where most tiles are identical.
+/// Use with `#[serde(with = "mw_common::grid::map::rle")]`.
+pub mod rle {
+    use super::*;
+
+    pub fn serialize<C, D, S>(map: &MapData<C, D>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        C: Coord,
+        D: Serialize + PartialEq,
+        S: Serializer,
+    {
+        map.ser_rle().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, C, D, De>(deserializer: De) -> Result<MapData<C, D>, De::Error>
+    where
+        C: Coord,
+        D: Deserialize<'de> + Clone,
+        De: Deserializer<'de>,
+    {
+        MapData::deserialize(deserializer)
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::prelude::*;
+
     use super::*;
     use crate::grid::hex::Hex;
 
@@ -221,6 +526,272 @@ mod test {
         map
     }
 
+    #[test]
+    fn transformed() {
+        let map = MapData::<Hex, Hex>::new_with(4, |c| c);
+        for t in Transform::all::<Hex>() {
+            let out = map.transformed(t);
+            for (c, d) in map.iter() {
+                assert_eq!(out[c.transformed(t)], *d);
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric() {
+        let mut calls = 0;
+        let map = MapData::<Hex, Hex>::new_symmetric(4, 3, |c| {
+            calls += 1;
+            c
+        });
+        assert_eq!(calls, (Hex::map_area(4) - 1) / 3 + 1);
+        for (c, d) in map.iter() {
+            assert_eq!(map[c.rotated(2)], *d);
+            assert_eq!(wedge_tile(*d, 3), *d);
+        }
+
+        let map = MapData::<crate::grid::Sq, u8>::new_symmetric(3, 2, |c| c.ring() * 7 + (c.0 + 3) as u8);
+        for (c, d) in map.iter() {
+            assert_eq!(map[c.rotated(2)], *d);
+        }
+    }
+
+    #[test]
+    fn ring_order_hex() {
+        let order = ring_order::<Hex>(2);
+        assert_eq!(&order[..7], &[
+            Hex(0, 0),
+            Hex(-1, 0), Hex(-1, 1), Hex(0, 1), Hex(1, 0), Hex(1, -1), Hex(0, -1),
+        ]);
+        assert_eq!(order[7], Hex(-2, 0));
+        assert_eq!(order[8], Hex(-2, 1));
+        assert_eq!(order.len(), Hex::map_area(2));
+        let unique: HashSet<_> = order.iter().collect();
+        assert_eq!(unique.len(), order.len());
+    }
+
+    #[test]
+    fn ring_order_sq() {
+        let order = ring_order::<Sq>(2);
+        assert_eq!(&order[..9], &[
+            Sq(0, 0),
+            Sq(-1, -1), Sq(-1, 0), Sq(-1, 1), Sq(0, 1),
+            Sq(1, 1), Sq(1, 0), Sq(1, -1), Sq(0, -1),
+        ]);
+        assert_eq!(order[9], Sq(-2, -2));
+        assert_eq!(order[10], Sq(-2, -1));
+        assert_eq!(order.len(), Sq::map_area(2));
+        let unique: HashSet<_> = order.iter().collect();
+        assert_eq!(unique.len(), order.len());
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Rle(#[serde(with = "rle")] MapData<Hex, u8>);
+
+    #[derive(Serialize)]
+    struct RleSq(#[serde(with = "rle")] MapData<Sq, u8>);
+
+    #[test]
+    fn serde_roundtrip() {
+        let map = rings_hex();
+        let s = ron::to_string(&map).unwrap();
+        let out: MapData<Hex, u8> = ron::from_str(&s).unwrap();
+        assert_eq!(out.size(), map.size());
+        assert_eq!(out.data(), map.data());
+
+        let s_rle = ron::to_string(&Rle(rings_hex())).unwrap();
+        let Rle(out) = ron::from_str(&s_rle).unwrap();
+        assert_eq!(out.data(), map.data());
+
+        // mostly identical tiles compress well
+        let mut map = MapData::<Hex, u8>::new(20, 0);
+        map[Hex(3, -5)] = 1;
+        let s = ron::to_string(&map).unwrap();
+        let s_rle = ron::to_string(&Rle(map.clone())).unwrap();
+        assert!(s_rle.len() * 10 < s.len());
+        let Rle(out) = ron::from_str(&s_rle).unwrap();
+        assert_eq!(out.data(), map.data());
+
+        let map = MapData::<Sq, Sq>::new_with(4, |c| c);
+        let s = ron::to_string(&map).unwrap();
+        let out: MapData<Sq, Sq> = ron::from_str(&s).unwrap();
+        assert_eq!(out.data(), map.data());
+    }
+
+    #[test]
+    fn serde_ring_order() {
+        let map = MapData::<Hex, u8>::new_with(1, |c| c.ring() * 10 + (c.0 + 1) as u8);
+        assert_eq!(
+            ron::to_string(&map).unwrap(),
+            "(size:1,topology:Hex,data:Dense([1,10,10,11,12,12,11]))",
+        );
+        let map = MapData::<Sq, u8>::new_with(2, |c| c.ring());
+        assert_eq!(
+            ron::to_string(&RleSq(map)).unwrap(),
+            "((size:2,topology:Sq,data:Rle([(1,0),(8,1),(16,2)])))",
+        );
+    }
+
+    #[test]
+    fn serde_invalid() {
+        let err = |s: &str| ron::from_str::<MapData<Hex, u8>>(s).err().unwrap().to_string();
+        assert!(err("(size:1,topology:Hex,data:Dense([1,2,3]))").contains("has 3 tiles, expected 7"));
+        assert!(err("(size:1,topology:Sq,data:Dense([1,2,3]))").contains("topology"));
+        assert!(err("(size:1,topology:Hex,data:Rle([(6,0)]))").contains("has 6 tiles"));
+        assert!(err("(size:1,topology:Hex,data:Rle([(0,1),(7,0)]))").contains("zero length"));
+        assert!(err("(size:200,topology:Hex,data:Dense([]))").contains("too big"));
+        // the default impl also accepts the rle form
+        let map: MapData<Hex, u8> = ron::from_str("(size:1,topology:Hex,data:Rle([(3,1),(4,0)]))").unwrap();
+        assert_eq!(map[Hex(-1, 1)], 1);
+        assert_eq!(map[Hex(0, 1)], 0);
+    }
+
+    fn check_edges<C: Coord>(size: u8, n_corners: usize) {
+        let mut map = MapData::<C, usize>::new(size, usize::MAX);
+        for (i, c) in C::iter_coords(size).enumerate() {
+            assert!(map.contains(c));
+            map[c] = i;
+        }
+        // no two coords share a slot
+        for (i, c) in C::iter_coords(size).enumerate() {
+            assert_eq!(map[c], i);
+        }
+
+        let mut corners = 0;
+        for c in C::origin().iter_ring(size) {
+            let inside: Vec<C> = c.iter_n1_in(&map).collect();
+            for c2 in c.iter_n1() {
+                assert_eq!(map.contains(c2), c2.ring() <= size, "{:?}", c2);
+                assert_eq!(inside.contains(&c2), map.contains(c2));
+                assert_eq!(map.get(c2).is_some(), map.contains(c2));
+                assert_eq!(map.get_mut(c2).is_some(), map.contains(c2));
+            }
+            assert!(inside.len() < C::N1, "{:?}", c);
+            assert!(c.iter_n0_in(&map).count() < C::N0, "{:?}", c);
+            if inside.len() == 3 {
+                corners += 1;
+            }
+        }
+        assert_eq!(corners, n_corners);
+
+        for c in C::origin().iter_ring(size + 1) {
+            assert!(!map.contains(c));
+            assert!(map.get(c).is_none());
+        }
+        for pos in [Pos(i8::MIN, i8::MIN), Pos(i8::MIN, i8::MAX), Pos(i8::MAX, i8::MIN), Pos(i8::MAX, 0)] {
+            assert!(!map.contains(pos.into()));
+            assert!(map.get(pos.into()).is_none());
+        }
+    }
+
+    #[test]
+    fn edges_hex() {
+        check_edges::<Hex>(1, 6);
+        check_edges::<Hex>(5, 6);
+        // the neighbors of the edge of a 127 map do not fit in an i8
+        check_edges::<Hex>(126, 6);
+    }
+
+    #[test]
+    fn edges_sq() {
+        check_edges::<Sq>(1, 4);
+        check_edges::<Sq>(5, 4);
+        check_edges::<Sq>(126, 4);
+    }
+
+    #[test]
+    fn biggest() {
+        let map = MapData::<Hex, Hex>::new_with(127, |c| c);
+        assert_eq!(map.iter().count(), Hex::map_area(127));
+        for (c, d) in map.iter() {
+            assert_eq!(map[c], *d);
+        }
+        let map = MapData::<Sq, Sq>::new_with(127, |c| c);
+        assert_eq!(map.iter().count(), Sq::map_area(127));
+        for (c, d) in map.iter() {
+            assert_eq!(map[c], *d);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds() {
+        let map = MapData::<Hex, u8>::new(2, 0);
+        // would alias a valid tile, if not for the debug assertion
+        let _ = map[Hex(-1, 3)];
+    }
+
+    #[test]
+    fn rect() {
+        let map = MapData::<Sq, u8>::new_shape(MapShape::rect::<Sq>(30, 16), 0);
+        assert_eq!(map.data().len(), 480);
+        assert_eq!(map.iter().count(), 480);
+        assert_eq!(map.size(), 15);
+        // corners
+        assert_eq!(Sq(-8, -15).iter_n1_in(&map).count(), 3);
+        assert_eq!(Sq(7, 14).iter_n1_in(&map).count(), 3);
+        assert_eq!(Sq(7, 0).iter_n1_in(&map).count(), 5);
+        assert!(map.get(Sq(8, 0)).is_none());
+        assert!(map.get(Sq(-9, 0)).is_none());
+        assert!(map.get(Sq(0, 15)).is_none());
+
+        let mut map = MapData::<Hex, u8>::new_shape_with(MapShape::rect::<Hex>(4, 3), |c| c.ring());
+        map[Hex(1, 1)] = 9;
+        let mut ascii = std::io::Cursor::new(Vec::new());
+        map.ascii_art(&mut ascii, |_, d| b'0' + d).unwrap();
+        assert_eq!(std::str::from_utf8(ascii.get_ref()).unwrap(), concat!(
+            " 3 2 1 1\n",
+            "  2 1 0 1\n",
+            "   2 1 1 9\n",
+        ));
+        let other = map.convert(|_, d| *d as u16);
+        assert_eq!(other.shape(), map.shape());
+        assert_eq!(other[Hex(1, 1)], 9);
+    }
+
+    #[test]
+    fn masked() {
+        let shape = MapShape::masked::<Sq>(2, |c: Sq| c.0 != 0);
+        let map = MapData::<Sq, u8>::new_shape_with(shape, |c| c.ring());
+        assert_eq!(map.iter().count(), 20);
+        assert!(!map.contains(Sq(0, 1)));
+        assert_eq!(Sq(1, 0).iter_n0_in(&map).count(), 3);
+        let mut ascii = std::io::Cursor::new(Vec::new());
+        map.ascii_art(&mut ascii, |_, d| b'0' + d).unwrap();
+        assert_eq!(std::str::from_utf8(ascii.get_ref()).unwrap(), concat!(
+            "  2 2 2 2 2\n",
+            "  2 1 1 1 2\n",
+            "           \n",
+            "  2 1 1 1 2\n",
+            "  2 2 2 2 2\n",
+        ));
+    }
+
+    #[test]
+    fn serde_shapes() {
+        let map = MapData::<Hex, u8>::new_shape_with(MapShape::rect::<Hex>(5, 2), |c| c.ring());
+        let s = ron::to_string(&map).unwrap();
+        assert_eq!(s, "(size:3,topology:Hex,shape:Some(Rect(w:5,h:2)),data:Dense([3,2,1,1,2,2,1,0,1,2]))");
+        let out: MapData<Hex, u8> = ron::from_str(&s).unwrap();
+        assert_eq!(out.shape(), map.shape());
+        assert_eq!(out.data(), map.data());
+
+        let shape = MapShape::masked::<Hex>(3, |c: Hex| c.0 > 0);
+        let map = MapData::<Hex, Hex>::new_shape_with(shape, |c| c);
+        let s = ron::to_string(&map).unwrap();
+        let out: MapData<Hex, Hex> = ron::from_str(&s).unwrap();
+        assert_eq!(out.shape(), map.shape());
+        assert_eq!(out.data(), map.data());
+
+        // wrong bounding size
+        assert!(ron::from_str::<MapData<Hex, u8>>(
+            "(size:2,topology:Hex,shape:Some(Rect(w:5,h:2)),data:Dense([3,2,1,1,2,2,1,0,1,2]))"
+        ).is_err());
+        assert!(ron::from_str::<MapData<Hex, u8>>(
+            "(size:3,topology:Hex,shape:Some(Rect(w:5,h:2)),data:Dense([3,2,1]))"
+        ).is_err());
+    }
+
     #[test]
     fn rings_hex_check() {
         let out = &[
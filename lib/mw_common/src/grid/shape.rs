@@ -0,0 +1,332 @@
+//! Shapes of maps
+//!
+//! `MapData` can store maps of different shapes:
+//!  - `Radial`: hexagon / square centered on the origin (the default)
+//!  - `Rect`: rectangle of rows and columns of coordinates, centered on the
+//!    origin (a parallelogram, for hex)
+//!  - `Masked`: any subset of a radial map
+//!
+//! Every shape has a bounding radius (`MapShape::size`): the smallest radial
+//! map that contains all of its tiles. Code that is not shape-aware can
+//! always use a radial map of that size to store data for the same tiles.
+
+use std::sync::Arc;
+
+use super::*;
+
+/// The set of tiles that make up a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+pub enum MapShape {
+    /// Hexagon / square with the given radius
+    Radial(u8),
+    /// `w` columns and `h` rows
+    Rect { w: u8, h: u8 },
+    /// Some of the tiles of a radial map
+    Masked(Mask),
+}
+
+/// Which tiles of a radial map are part of a `MapShape::Masked`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+#[serde(from = "MaskSer", into = "MaskSer")]
+pub struct Mask {
+    size: u8,
+    /// For each tile of the radial map (in `Coord::index` order),
+    /// where it is stored, or `None` if it is not part of the map
+    slots: Arc<[Option<u32>]>,
+    len: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Mask")]
+struct MaskSer {
+    size: u8,
+    tiles: Vec<bool>,
+}
+
+impl From<MaskSer> for Mask {
+    fn from(m: MaskSer) -> Mask {
+        Mask::from_bools(m.size, m.tiles)
+    }
+}
+
+impl From<Mask> for MaskSer {
+    fn from(m: Mask) -> MaskSer {
+        MaskSer {
+            size: m.size,
+            tiles: m.slots.iter().map(|s| s.is_some()).collect(),
+        }
+    }
+}
+
+impl Mask {
+    fn from_bools(size: u8, tiles: impl IntoIterator<Item = bool>) -> Mask {
+        let mut len = 0;
+        let slots: Arc<[Option<u32>]> = tiles.into_iter().map(|b| {
+            b.then(|| {
+                len += 1;
+                len as u32 - 1
+            })
+        }).collect();
+        Mask { size, slots, len }
+    }
+}
+
+impl MapShape {
+    /// A rectangle of `w` columns and `h` rows
+    ///
+    /// Panics if it does not fit in a radial map of size 127.
+    pub fn rect<C: Coord>(w: u8, h: u8) -> MapShape {
+        let shape = MapShape::Rect { w, h };
+        assert!(w > 0 && h > 0 && shape.bounding_size::<C>() <= 127);
+        shape
+    }
+
+    /// A radial map of the given size, with only the tiles for which `f` returns true
+    pub fn masked<C: Coord>(size: u8, f: impl FnMut(C) -> bool) -> MapShape {
+        assert!(size <= 127);
+        MapShape::Masked(Mask::from_bools(size, C::iter_coords(size).map(f)))
+    }
+
+    /// Check the shape against the given topology
+    ///
+    /// Shapes made with the constructors are always valid. This is for
+    /// shapes that come from elsewhere (like deserialization).
+    pub fn is_valid<C: Coord>(&self) -> bool {
+        match self {
+            MapShape::Radial(r) => *r <= 127,
+            MapShape::Rect { w, h } => *w > 0 && *h > 0 && self.bounding_size::<C>() <= 127,
+            MapShape::Masked(m) => m.size <= 127 && m.slots.len() == C::map_area(m.size),
+        }
+    }
+
+    fn rect_min(w: u8, h: u8) -> (i8, i8) {
+        (-((h / 2) as i8), -((w / 2) as i8))
+    }
+
+    fn bounding_size<C: Coord>(&self) -> u16 {
+        match self {
+            MapShape::Radial(r) => *r as u16,
+            MapShape::Rect { w, h } => {
+                let (y0, x0) = MapShape::rect_min(*w, *h);
+                let (y1, x1) = (y0 as i16 + *h as i16 - 1, x0 as i16 + *w as i16 - 1);
+                // the "ring" distance is a norm, so the furthest tile is a corner
+                [(y0 as i16, x0 as i16), (y0 as i16, x1), (y1, x0 as i16), (y1, x1)]
+                    .into_iter()
+                    .map(|(y, x)| match C::TOPOLOGY {
+                        Topology::Hex => (y.abs() + x.abs() + (y + x).abs()) as u16 / 2,
+                        Topology::Sq => y.unsigned_abs().max(x.unsigned_abs()),
+                    })
+                    .max()
+                    .unwrap()
+            }
+            MapShape::Masked(m) => m.size as u16,
+        }
+    }
+
+    /// Radius of the smallest radial map containing all tiles
+    pub fn size<C: Coord>(&self) -> u8 {
+        self.bounding_size::<C>() as u8
+    }
+
+    /// Number of tiles
+    pub fn area<C: Coord>(&self) -> usize {
+        match self {
+            MapShape::Radial(r) => C::map_area(*r),
+            MapShape::Rect { w, h } => *w as usize * *h as usize,
+            MapShape::Masked(m) => m.len,
+        }
+    }
+
+    /// Is the tile part of the map?
+    pub fn contains<C: Coord>(&self, c: C) -> bool {
+        match self {
+            MapShape::Radial(r) => c.in_bounds(*r),
+            MapShape::Rect { w, h } => {
+                let (y0, x0) = MapShape::rect_min(*w, *h);
+                let y = c.y() as i16 - y0 as i16;
+                let x = c.x() as i16 - x0 as i16;
+                y >= 0 && y < *h as i16 && x >= 0 && x < *w as i16
+            }
+            MapShape::Masked(m) => {
+                c.in_bounds(m.size) && m.slots[C::index(m.size, c)].is_some()
+            }
+        }
+    }
+
+    /// Where the tile is stored in a `MapData`
+    ///
+    /// The tile must be part of the map.
+    pub fn index<C: Coord>(&self, c: C) -> usize {
+        match self {
+            MapShape::Radial(r) => C::index(*r, c),
+            MapShape::Rect { w, h } => {
+                let (y0, x0) = MapShape::rect_min(*w, *h);
+                let y = (c.y() as i16 - y0 as i16) as usize;
+                let x = (c.x() as i16 - x0 as i16) as usize;
+                y * *w as usize + x
+            }
+            MapShape::Masked(m) => {
+                m.slots[C::index(m.size, c)].expect("tile is not part of the map") as usize
+            }
+        }
+    }
+
+    /// All the tiles, in storage order
+    pub fn iter_coords<C: Coord>(&self) -> ShapeCoords<C> {
+        match self {
+            MapShape::Radial(r) => ShapeCoords::Radial(C::iter_coords(*r)),
+            MapShape::Rect { w, h } => {
+                let (y0, x0) = MapShape::rect_min(*w, *h);
+                ShapeCoords::Rect {
+                    x0,
+                    x1: (x0 as i16 + *w as i16 - 1) as i8,
+                    y1: (y0 as i16 + *h as i16 - 1) as i8,
+                    next: Some(Pos(y0, x0).into()),
+                }
+            }
+            MapShape::Masked(m) => ShapeCoords::Masked {
+                iter: C::iter_coords(m.size),
+                slots: m.slots.clone(),
+                i: 0,
+            },
+        }
+    }
+
+    /// Number of tiles in each row, and how many spaces to put before them,
+    /// for ascii art
+    pub(super) fn aa_rows<C: Coord>(&self) -> Vec<(usize, usize)> {
+        match self {
+            MapShape::Radial(r) => {
+                (-(*r as i8)..=(*r as i8))
+                    .map(|y| (C::row_len(*r, y), C::aa_indent(y)))
+                    .collect()
+            }
+            MapShape::Rect { w, h } => {
+                (0..*h as usize)
+                    .map(|y| (*w as usize, match C::TOPOLOGY {
+                        // rows of a parallelogram shift by half a tile
+                        Topology::Hex => y,
+                        Topology::Sq => C::aa_indent(0),
+                    }))
+                    .collect()
+            }
+            MapShape::Masked(m) => MapShape::Radial(m.size).aa_rows::<C>(),
+        }
+    }
+}
+
+/// Iterator over the tiles of a `MapShape`
+pub enum ShapeCoords<C: Coord> {
+    Radial(C::IterCoords),
+    Rect {
+        x0: i8,
+        x1: i8,
+        y1: i8,
+        next: Option<C>,
+    },
+    Masked {
+        iter: C::IterCoords,
+        slots: Arc<[Option<u32>]>,
+        i: usize,
+    },
+}
+
+impl<C: Coord> Iterator for ShapeCoords<C> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<C> {
+        match self {
+            ShapeCoords::Radial(iter) => iter.next(),
+            ShapeCoords::Rect { x0, x1, y1, next } => {
+                let r = (*next)?;
+                // careful not to overflow at the last tile
+                *next = if r.x() < *x1 {
+                    Some(Pos(r.y(), r.x() + 1).into())
+                } else if r.y() < *y1 {
+                    Some(Pos(r.y() + 1, *x0).into())
+                } else {
+                    None
+                };
+                Some(r)
+            }
+            ShapeCoords::Masked { iter, slots, i } => {
+                for c in iter.by_ref() {
+                    *i += 1;
+                    if slots[*i - 1].is_some() {
+                        return Some(c);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+
+    use super::*;
+
+    fn check<C: Coord>(shape: &MapShape) {
+        assert!(shape.is_valid::<C>());
+        let coords: Vec<C> = shape.iter_coords().collect();
+        assert_eq!(coords.len(), shape.area::<C>());
+        let size = shape.size::<C>();
+        for (i, c) in coords.iter().enumerate() {
+            assert!(shape.contains(*c));
+            assert!(c.in_bounds(size));
+            assert_eq!(shape.index(*c), i);
+        }
+        let set: HashSet<C> = coords.iter().copied().collect();
+        for c in C::iter_coords(size) {
+            assert_eq!(shape.contains(c), set.contains(&c));
+        }
+        assert!(coords.iter().any(|c| c.ring() == size));
+        let rows = shape.aa_rows::<C>();
+        assert_eq!(rows.iter().map(|r| r.0).sum::<usize>(), match shape {
+            MapShape::Masked(m) => C::map_area(m.size),
+            _ => coords.len(),
+        });
+    }
+
+    #[test]
+    fn rect() {
+        let shape = MapShape::rect::<Sq>(30, 16);
+        check::<Sq>(&shape);
+        assert_eq!(shape.area::<Sq>(), 480);
+        assert_eq!(shape.size::<Sq>(), 15);
+        assert!(shape.contains(Sq(-8, -15)));
+        assert!(shape.contains(Sq(7, 14)));
+        assert!(!shape.contains(Sq(8, 0)));
+        assert!(!shape.contains(Sq(0, 15)));
+
+        let shape = MapShape::rect::<Hex>(7, 4);
+        check::<Hex>(&shape);
+        assert_eq!(shape.size::<Hex>(), 5);
+
+        check::<Sq>(&MapShape::rect::<Sq>(1, 1));
+        check::<Sq>(&MapShape::rect::<Sq>(255, 255));
+        check::<Hex>(&MapShape::rect::<Hex>(127, 127));
+    }
+
+    #[test]
+    fn masked() {
+        let shape = MapShape::masked::<Hex>(6, |c: Hex| matches!(c.ring(), 0 | 2 | 4 | 6) || c.0 == 0);
+        check::<Hex>(&shape);
+        assert!(shape.contains(Hex(0, 3)));
+        assert!(!shape.contains(Hex(1, 0)));
+        assert!(!shape.contains(Hex(0, 7)));
+
+        check::<Sq>(&MapShape::masked::<Sq>(4, |c: Sq| c.0 != c.1));
+        assert!(!MapShape::masked::<Sq>(4, |_: Sq| true).is_valid::<Hex>());
+    }
+
+    #[test]
+    fn radial() {
+        check::<Hex>(&MapShape::Radial(5));
+        check::<Sq>(&MapShape::Radial(5));
+    }
+}
@@ -33,6 +33,7 @@ impl Coord for Sq {
     const N2: usize = 8;
 
     const TOPOLOGY: super::Topology = super::Topology::Sq;
+    const ROTATIONS: u8 = 4;
 
     type IterN0 = IterNeigh;
     type IterN1 = IterNeigh;
@@ -66,6 +67,10 @@ impl Coord for Sq {
         (r as usize * 2 + 1) * 4
     }
 
+    fn in_bounds(self, r: u8) -> bool {
+        self.0.unsigned_abs() <= r && self.1.unsigned_abs() <= r
+    }
+
     fn translation(self) -> glam::Vec2 {
         glam::Vec2::new(self.1 as f32, self.0 as f32)
     }
@@ -116,10 +121,10 @@ impl Coord for Sq {
         assert!(r <= 127);
         let r = r as i8;
         assert!(c.0 >= -r && c.0 <= r && c.1 >= -r && c.1 <= r);
-        let r = r as i16;
+        let r = r as isize;
         let w = r * 2 + 1;
-        let x = c.1 as i16 + r;
-        let y = c.0 as i16 + r;
+        let x = c.1 as isize + r;
+        let y = c.0 as isize + r;
         (y * w + x) as usize
     }
     fn row_len(r: u8, _y: i8) -> usize {
@@ -135,6 +140,17 @@ impl Coord for Sq {
             next: Some(Sq(-r, -r)),
         }
     }
+    fn rotated(self, steps: u8) -> Self {
+        match steps % Self::ROTATIONS {
+            0 => self,
+            1 => Sq(self.1, -self.0),
+            2 => Sq(-self.0, -self.1),
+            _ => Sq(-self.1, self.0),
+        }
+    }
+    fn mirrored(self, axis: u8) -> Self {
+        Sq(self.1, self.0).rotated(axis)
+    }
 }
 
 impl TryFrom<(f32, f32)> for Sq {
@@ -371,14 +387,13 @@ impl Iterator for IterCoords {
         let next = self.next;
 
         if let Some(Sq(y, x)) = &mut self.next {
-            *x += 1;
-
-            if *x > self.r {
+            // careful not to overflow at the last tile of the biggest maps
+            if *x < r {
+                *x += 1;
+            } else if *y < r {
                 *y += 1;
                 *x = -r;
-            }
-
-            if *y > r {
+            } else {
                 self.next = None;
             }
         }
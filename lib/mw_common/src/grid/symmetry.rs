@@ -0,0 +1,90 @@
+//! Rotations and reflections of the grid
+//!
+//! `Transform` is not re-exported from `grid`, so that it does not clash
+//! with Bevy's `Transform` in code that glob-imports both.
+
+use super::*;
+
+/// A symmetry of the grid, around the origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[derive(Serialize, Deserialize)]
+pub enum Transform {
+    #[default]
+    Identity,
+    /// Rotate by the given number of steps (see `Coord::rotated`)
+    Rotate(u8),
+    /// Mirror across the given axis (see `Coord::mirrored`)
+    Mirror(u8),
+}
+
+impl Transform {
+    /// All the distinct symmetries for the given topology
+    pub fn all<C: Coord>() -> impl Iterator<Item = Transform> {
+        (1..C::ROTATIONS).map(Transform::Rotate)
+            .chain((0..C::ROTATIONS).map(Transform::Mirror))
+            .chain(std::iter::once(Transform::Identity))
+    }
+
+    /// The transform that undoes this one
+    pub fn inverse<C: Coord>(self) -> Transform {
+        match self {
+            Transform::Rotate(steps) => {
+                Transform::Rotate((C::ROTATIONS - steps % C::ROTATIONS) % C::ROTATIONS)
+            }
+            other => other,
+        }
+    }
+}
+
+/// Which tiles make up the "wedge" for `MapData::new_symmetric`
+///
+/// Returns the tile that `c` is a copy of, in an `n`-fold rotationally
+/// symmetric map. `n` must divide `C::ROTATIONS`.
+pub fn wedge_tile<C: Coord>(c: C, n: u8) -> C {
+    let step = C::ROTATIONS / n;
+    (0..n).map(|i| c.rotated(i * step)).min().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check<C: Coord>() {
+        let coords: Vec<C> = C::iter_coords(5).collect();
+        let mut seen = vec![];
+        for t in Transform::all::<C>() {
+            let out: Vec<C> = coords.iter().map(|c| c.transformed(t)).collect();
+            // every transform is a distinct permutation of the map
+            let mut sorted = out.clone();
+            sorted.sort();
+            let mut orig = coords.clone();
+            orig.sort();
+            assert_eq!(sorted, orig, "{:?}", t);
+            assert!(!seen.contains(&out), "{:?}", t);
+            seen.push(out);
+
+            for &a in coords.iter() {
+                assert_eq!(a.transformed(t).transformed(t.inverse::<C>()), a);
+                for b in a.iter_n1() {
+                    assert!(a.transformed(t).iter_n1().any(|c| c == b.transformed(t)));
+                }
+            }
+        }
+        assert_eq!(seen.len(), C::ROTATIONS as usize * 2);
+    }
+
+    #[test]
+    fn hex() {
+        check::<Hex>();
+        assert_eq!(Hex(1, 0).rotated(1), Hex(0, 1));
+        assert_eq!(Hex(1, 0).rotated(3), Hex(-1, 0));
+        assert_eq!(Hex(2, -1).mirrored(0), Hex(-1, 2));
+    }
+
+    #[test]
+    fn sq() {
+        check::<Sq>();
+        assert_eq!(Sq(1, 2).rotated(2), Sq(-1, -2));
+        assert_eq!(Sq(1, 2).mirrored(0), Sq(2, 1));
+    }
+}
@@ -43,5 +43,8 @@ pub mod net;
 pub mod algo;
 pub mod driver;
 pub mod grid;
+pub mod mapgen;
 pub mod plid;
+#[cfg(feature = "render")]
+pub mod render;
 pub mod game;
@@ -0,0 +1,299 @@
+//! Procedural generation of MineWars maps
+//!
+//! The generator works in stages:
+//!  1. Land and water: random noise, biased towards land near the center of
+//!     the map (more so with higher `land_bias`), smoothed with a few rounds
+//!     of a majority-rule cellular automaton. The outer ring is always water.
+//!  2. Mountain and forest clusters, grown from random seed tiles.
+//!  3. Fertile land, more likely along coasts and forest edges.
+//!  4. Cities, on the biggest landmass, at least `cit_spacing` apart.
+//!  5. Regions (see `algo::regions`).
+//!
+//! Every city is guaranteed to be reachable over land from every other city.
+//! If an attempt does not satisfy that, it is discarded and the generator
+//! tries again. Everything is derived from `seed`, so the same parameters
+//! always give the same map.
+
+use crate::algo::{flood, reach, regions, FloodQ, FloodSelect, PathQ, ReachQ};
+use crate::game::TileKind;
+use crate::grid::{Coord, MapData};
+use crate::prelude::*;
+
+/// How many times to try before giving up
+const MAX_ATTEMPTS: u32 = 32;
+/// Rounds of smoothing for the coastlines
+const SMOOTH_ROUNDS: u32 = 4;
+
+/// Parameters for the map generator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapGenParams {
+    pub size: u8,
+    pub seed: u64,
+    /// How much of the map should be land rather than water (0-255)
+    pub land_bias: u8,
+    pub n_cits: u8,
+    /// Minimum distance between any two cities
+    pub cit_spacing: u8,
+}
+
+impl Default for MapGenParams {
+    fn default() -> Self {
+        Self {
+            size: 24,
+            seed: 0,
+            land_bias: 48,
+            n_cits: 5,
+            cit_spacing: 6,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MapGenError {
+    #[error("Map size must be between 4 and 127")]
+    BadSize,
+    #[error("Too many cities (max 254)")]
+    TooManyCits,
+    #[error("Could not generate a valid map in {0} attempts")]
+    Exhausted(u32),
+}
+
+/// The output of the map generator
+pub struct GeneratedMap<C: Coord> {
+    pub tiles: MapData<C, TileKind>,
+    pub regions: MapData<C, u8>,
+    pub cits: Vec<C>,
+}
+
+/// Generate a new map
+pub fn generate<C: Coord>(params: &MapGenParams) -> Result<GeneratedMap<C>, MapGenError> {
+    if params.size < 4 || params.size > 127 {
+        return Err(MapGenError::BadSize);
+    }
+    if params.n_cits == u8::MAX {
+        return Err(MapGenError::TooManyCits);
+    }
+    let mut rng = rand_pcg::Pcg64::seed_from_u64(params.seed);
+    for attempt in 0..MAX_ATTEMPTS {
+        if let Some(map) = try_generate(params, &mut rng) {
+            debug!("Generated map with seed {} in {} attempts", params.seed, attempt + 1);
+            return Ok(map);
+        }
+    }
+    Err(MapGenError::Exhausted(MAX_ATTEMPTS))
+}
+
+fn try_generate<C: Coord>(params: &MapGenParams, rng: &mut impl Rng) -> Option<GeneratedMap<C>> {
+    let mut tiles = gen_land(params, rng);
+    let mainland = biggest_landmass(&tiles);
+    gen_clusters(&mut tiles, rng);
+    gen_fertile(&mut tiles, rng);
+    let cits = place_cits(params, &tiles, &mainland, rng)?;
+
+    // clusters may have cut off parts of the mainland
+    let mut rq = ReachQ::new();
+    for &cit in cits.iter().skip(1) {
+        let reachable = reach(&mut rq, cits[0], |c: C| {
+            tiles.get(c).filter(|k| k.is_land()).map(|_| c.distance(cit))
+        });
+        if !reachable {
+            return None;
+        }
+    }
+
+    let regions = regions(&mut PathQ::default(), &tiles, &cits);
+    Some(GeneratedMap { tiles, regions, cits })
+}
+
+fn gen_land<C: Coord>(params: &MapGenParams, rng: &mut impl Rng) -> MapData<C, TileKind> {
+    let size = params.size as f64;
+    let bias = params.land_bias as f64 / 255.0;
+    let mut land = MapData::<C, bool>::new_with(params.size, |c| {
+        let edge = c.ring() as f64 / size;
+        let p = (0.45 + 0.5 * bias) * (1.0 - edge.powi(4));
+        rng.gen_bool(p.clamp(0.0, 1.0))
+    });
+
+    for _ in 0..SMOOTH_ROUNDS {
+        let prev = land.clone();
+        for (c, l) in land.iter_mut() {
+            let n = c.iter_n1_in(&prev).filter(|c2| prev[*c2]).count() + prev[c] as usize;
+            *l = n * 2 > C::N1 + 1;
+        }
+    }
+
+    land.convert(|c, l| {
+        if *l && c.ring() < params.size {
+            TileKind::Regular
+        } else {
+            TileKind::Water
+        }
+    })
+}
+
+/// All tiles of the biggest connected area of land
+fn biggest_landmass<C: Coord>(tiles: &MapData<C, TileKind>) -> HashSet<C> {
+    let mut done = tiles.convert(|_, _| false);
+    let mut q = FloodQ::new();
+    let mut best = HashSet::new();
+    for c in tiles.iter_coords(None) {
+        if done[c] || !tiles[c].is_land() {
+            continue;
+        }
+        done[c] = true;
+        let mut area = HashSet::from_iter([c]);
+        q.push_back(c.into());
+        flood(&mut q, |c2: C, _| {
+            if !tiles.get(c2).map(|k| k.is_land()).unwrap_or(false) || done[c2] {
+                return FloodSelect::No;
+            }
+            done[c2] = true;
+            area.insert(c2);
+            FloodSelect::Yes
+        });
+        if area.len() > best.len() {
+            best = area;
+        }
+    }
+    best
+}
+
+fn gen_clusters<C: Coord>(tiles: &mut MapData<C, TileKind>, rng: &mut impl Rng) {
+    let land: Vec<C> = tiles.iter().filter(|(_, k)| k.is_land()).map(|(c, _)| c).collect();
+    let n_clusters = land.len() / 48;
+    for &seed in land.choose_multiple(rng, n_clusters) {
+        let kind = if rng.gen_bool(0.5) {
+            TileKind::Mountain
+        } else {
+            TileKind::Forest
+        };
+        let target = rng.gen_range(2..=7);
+        let mut cluster = vec![seed];
+        tiles[seed] = kind;
+        for _ in 0..target * 4 {
+            if cluster.len() >= target {
+                break;
+            }
+            let from = *cluster.choose(rng).unwrap();
+            let next = from.iter_n0_in(tiles)
+                .filter(|c| tiles[*c].is_land())
+                .choose(rng);
+            if let Some(next) = next {
+                tiles[next] = kind;
+                cluster.push(next);
+            }
+        }
+    }
+}
+
+fn gen_fertile<C: Coord>(tiles: &mut MapData<C, TileKind>, rng: &mut impl Rng) {
+    let prev = tiles.clone();
+    for (c, kind) in tiles.iter_mut() {
+        if *kind != TileKind::Regular {
+            continue;
+        }
+        let edge = c.iter_n0_in(&prev)
+            .any(|c2| matches!(prev[c2], TileKind::Water | TileKind::Forest));
+        if rng.gen_bool(if edge { 0.4 } else { 0.08 }) {
+            *kind = TileKind::Fertile;
+        }
+    }
+}
+
+fn place_cits<C: Coord>(
+    params: &MapGenParams,
+    tiles: &MapData<C, TileKind>,
+    mainland: &HashSet<C>,
+    rng: &mut impl Rng,
+) -> Option<Vec<C>> {
+    let mut candidates: Vec<C> = mainland.iter()
+        .copied()
+        .filter(|c| tiles[*c].is_land())
+        .collect();
+    // HashSet order is not deterministic
+    candidates.sort();
+    candidates.shuffle(rng);
+
+    let mut cits: Vec<C> = Vec::with_capacity(params.n_cits as usize);
+    for c in candidates {
+        if cits.len() >= params.n_cits as usize {
+            break;
+        }
+        if cits.iter().all(|cit| cit.distance(c) >= params.cit_spacing as u16) {
+            cits.push(c);
+        }
+    }
+    (cits.len() == params.n_cits as usize).then_some(cits)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::algo::NO_REGION;
+    use crate::grid::{Hex, Sq};
+
+    use super::*;
+
+    fn check<C: Coord>(params: &MapGenParams) -> GeneratedMap<C> {
+        let map = generate::<C>(params).unwrap();
+        assert_eq!(map.cits.len(), params.n_cits as usize);
+        for (i, a) in map.cits.iter().enumerate() {
+            assert!(map.tiles[*a].is_land());
+            assert_eq!(map.regions[*a], i as u8);
+            for b in map.cits.iter().skip(i + 1) {
+                assert!(a.distance(*b) >= params.cit_spacing as u16);
+                let path = crate::algo::find_path(
+                    &mut PathQ::default(), *a, *b, crate::algo::Neighbors::N0,
+                    |c| map.tiles.get(c).filter(|k| k.is_land()).map(|_| 1),
+                );
+                assert!(path.is_some());
+            }
+        }
+        for c in C::origin().iter_ring(params.size) {
+            assert_eq!(map.tiles[c], TileKind::Water);
+        }
+        for (c, kind) in map.tiles.iter() {
+            assert_eq!(map.regions[c] == NO_REGION, !kind.ownable());
+        }
+        map
+    }
+
+    #[test]
+    fn deterministic() {
+        let params = MapGenParams {
+            seed: 1503,
+            ..Default::default()
+        };
+        let a = check::<Hex>(&params);
+        let b = check::<Hex>(&params);
+        assert_eq!(a.tiles.data(), b.tiles.data());
+        assert_eq!(a.cits, b.cits);
+        let c = check::<Hex>(&MapGenParams { seed: 1504, ..params });
+        assert_ne!(a.tiles.data(), c.tiles.data());
+    }
+
+    #[test]
+    fn many_seeds() {
+        for seed in 0..20 {
+            check::<Hex>(&MapGenParams { seed, ..Default::default() });
+            check::<Sq>(&MapGenParams { seed, size: 16, n_cits: 4, ..Default::default() });
+        }
+    }
+
+    #[test]
+    fn land_bias() {
+        let land = |land_bias| {
+            let map = check::<Hex>(&MapGenParams { seed: 7, land_bias, n_cits: 2, ..Default::default() });
+            map.tiles.iter().filter(|(_, k)| k.ownable()).count()
+        };
+        assert!(land(16) < land(128));
+        assert!(land(128) < land(240));
+    }
+
+    #[test]
+    fn impossible() {
+        let params = MapGenParams { size: 6, n_cits: 20, ..Default::default() };
+        assert!(matches!(generate::<Sq>(&params), Err(MapGenError::Exhausted(_))));
+        let params = MapGenParams { size: 2, ..Default::default() };
+        assert!(matches!(generate::<Sq>(&params), Err(MapGenError::BadSize)));
+    }
+}
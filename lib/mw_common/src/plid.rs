@@ -63,13 +63,38 @@ impl PartialEq<PlayerId> for u8 {
     }
 }
 
+/// Common interface of [`Plids`] and [`PlidsBig`]
+///
+/// Allows APIs (like `Host::msg`) to accept either kind of set.
+pub trait PlidMask: Debug + Default + Copy + Eq + From<PlayerId> + Into<PlidsBig> + Send + Sync + 'static {
+    /// The highest player id that can be in the set
+    const MAX_PLID: u8;
+
+    fn contains(&self, plid: PlayerId) -> bool;
+
+    /// Iterate over the plids in the set, up to `max` (inclusive)
+    fn iter(&self, max: Option<u8>) -> PlidsIter<Self> {
+        PlidsIter {
+            plids: *self,
+            next: 0,
+            max: max.unwrap_or(Self::MAX_PLID).min(Self::MAX_PLID),
+        }
+    }
+}
+
 /// Bitmask to mux player IDs: can support a game with up to 15 players
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Plids(pub u16);
 
-impl Plids {
+impl PlidMask for Plids {
     const MAX_PLID: u8 = 15;
 
+    fn contains(&self, plid: PlayerId) -> bool {
+        Plids::contains(self, plid)
+    }
+}
+
+impl Plids {
     pub fn all(with_spect: bool) -> Self {
         Self(!((!with_spect) as u16))
     }
@@ -96,11 +121,7 @@ impl Plids {
     }
 
     pub fn iter(&self, max: Option<u8>) -> PlidsIter {
-        PlidsIter {
-            plids: *self,
-            next: 0,
-            max: max.unwrap_or(Self::MAX_PLID).min(Self::MAX_PLID),
-        }
+        PlidMask::iter(self, max)
     }
 }
 
@@ -176,22 +197,162 @@ impl Not for Plids {
     }
 }
 
-pub struct PlidsIter {
-    plids: Plids,
-    next: u8,
+/// Bitmask to mux player IDs: can support a session with up to 255 plids
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlidsBig(pub [u64; 4]);
+
+impl PlidMask for PlidsBig {
+    const MAX_PLID: u8 = 255;
+
+    fn contains(&self, plid: PlayerId) -> bool {
+        PlidsBig::contains(self, plid)
+    }
+}
+
+impl PlidsBig {
+    /// Word index and bit for a plid
+    fn bit(plid: PlayerId) -> (usize, u64) {
+        let b: u8 = plid.into();
+        ((b / 64) as usize, 1 << (b % 64))
+    }
+
+    pub fn all(with_spect: bool) -> Self {
+        let mut r = Self([!0; 4]);
+        if !with_spect {
+            r -= PlayerId::Neutral;
+        }
+        r
+    }
+
+    pub fn spect() -> Self {
+        Self::from(PlayerId::Neutral)
+    }
+
+    pub fn with_spect(plid: PlayerId) -> Self {
+        Self::from(plid) + PlayerId::Neutral
+    }
+
+    pub fn contains(&self, plid: PlayerId) -> bool {
+        let (i, bit) = Self::bit(plid);
+        (self.0[i] & bit) != 0
+    }
+
+    pub fn contains_all(&self, other: Self) -> bool {
+        self.0.iter().zip(other.0.iter()).all(|(a, b)| (a & b) == *b)
+    }
+
+    pub fn contains_any(&self, other: Self) -> bool {
+        self.0.iter().zip(other.0.iter()).any(|(a, b)| (a & b) != 0)
+    }
+
+    pub fn iter(&self, max: Option<u8>) -> PlidsIter<Self> {
+        PlidMask::iter(self, max)
+    }
+
+    fn zip_with(self, rhs: Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        Self(std::array::from_fn(|i| f(self.0[i], rhs.0[i])))
+    }
+}
+
+impl From<PlayerId> for PlidsBig {
+    fn from(plid: PlayerId) -> Self {
+        Self::default() + plid
+    }
+}
+
+impl From<Plids> for PlidsBig {
+    fn from(plids: Plids) -> Self {
+        Self([plids.0 as u64, 0, 0, 0])
+    }
+}
+
+impl Add<PlayerId> for PlidsBig {
+    type Output = Self;
+
+    // adding a plid sets its bit, same as for `Plids`
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(mut self, rhs: PlayerId) -> Self {
+        let (i, bit) = Self::bit(rhs);
+        self.0[i] |= bit;
+        self
+    }
+}
+
+impl AddAssign<PlayerId> for PlidsBig {
+    fn add_assign(&mut self, rhs: PlayerId) {
+        *self = *self + rhs;
+    }
+}
+
+impl Add<PlidsBig> for PlidsBig {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| a | b)
+    }
+}
+
+impl AddAssign<PlidsBig> for PlidsBig {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<PlayerId> for PlidsBig {
+    type Output = Self;
+
+    fn sub(mut self, rhs: PlayerId) -> Self {
+        let (i, bit) = Self::bit(rhs);
+        self.0[i] &= !bit;
+        self
+    }
+}
+
+impl SubAssign<PlayerId> for PlidsBig {
+    fn sub_assign(&mut self, rhs: PlayerId) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sub<PlidsBig> for PlidsBig {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| a & !b)
+    }
+}
+
+impl SubAssign<PlidsBig> for PlidsBig {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Not for PlidsBig {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(self.0.map(|w| !w))
+    }
+}
+
+pub struct PlidsIter<P: PlidMask = Plids> {
+    plids: P,
+    // wider than a plid, to not overflow after 255
+    next: u16,
     max: u8,
 }
 
-impl Iterator for PlidsIter {
+impl<P: PlidMask> Iterator for PlidsIter<P> {
     type Item = PlayerId;
 
     fn next(&mut self) -> Option<PlayerId> {
         let ret = loop {
-            if self.next > self.max {
+            if self.next > self.max as u16 {
                 return None;
             }
 
-            let cur = self.next;
+            let cur = self.next as u8;
             self.next += 1;
 
             if self.plids.contains(cur.into()) {
@@ -203,3 +364,36 @@ impl Iterator for PlidsIter {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn big() {
+        let p = |b: u8| PlayerId::from(b);
+        let mut plids = PlidsBig::with_spect(p(200));
+        plids += p(15);
+        plids += p(64);
+        assert!(plids.contains(p(0)) && plids.contains(p(64)) && plids.contains(p(200)));
+        assert!(!plids.contains(p(1)) && !plids.contains(p(63)) && !plids.contains(p(255)));
+        assert_eq!(plids.iter(None).map(u8::from).collect::<Vec<_>>(), [0, 15, 64, 200]);
+        assert_eq!(plids.iter(Some(64)).count(), 3);
+
+        let all = PlidsBig::all(false);
+        assert_eq!(all.iter(None).count(), 255);
+        assert!(all.contains(p(255)) && !all.contains(p(0)));
+        assert!(all.contains_all(plids - p(0)));
+        assert!(!all.contains_all(plids));
+        assert!(!(!all).contains_any(plids - p(0)));
+        assert_eq!(plids - plids, PlidsBig::default());
+        assert_eq!(PlidsBig::spect() + (plids - p(0)), plids);
+
+        let small = Plids::with_spect(p(3)) + p(15);
+        let converted = PlidsBig::from(small);
+        assert_eq!(
+            converted.iter(None).collect::<Vec<_>>(),
+            small.iter(None).collect::<Vec<_>>(),
+        );
+    }
+}
+
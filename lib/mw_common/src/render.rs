@@ -0,0 +1,481 @@
+//! Headless rendering of maps to images (PNG / SVG)
+//!
+//! Meant for debugging and sharing, without needing the game client.
+//! What each tile looks like is decided by the caller, with a closure
+//! returning a [`TileStyle`].
+//!
+//! Tiles are laid out the same way as in `MapData::ascii_art` (and in the
+//! game): the `y` axis points down in the image, so the top row is `-size`.
+
+use std::fmt::Write;
+
+use glam::Vec2;
+
+use crate::algo::NO_REGION;
+use crate::game::TileKind;
+use crate::grid::{Coord, MapData, Topology};
+use crate::prelude::*;
+
+/// An RGB color
+pub type Rgb = [u8; 3];
+
+/// Refuse to make PNGs bigger than this (in each dimension)
+const MAX_PIXELS: u32 = 16384;
+
+/// 3x5 pixel font for the digits, one row per byte (low 3 bits)
+const FONT: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// How to draw a tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileStyle {
+    pub fill: Rgb,
+    /// Digit (0-9) to show on the tile
+    pub digit: Option<u8>,
+    /// Draw an outline of this color inside the tile (to show the owner)
+    pub owner: Option<Rgb>,
+}
+
+impl TileStyle {
+    /// Just a plain color, no overlays
+    pub fn fill(fill: Rgb) -> Self {
+        TileStyle { fill, digit: None, owner: None }
+    }
+}
+
+/// Settings for rendering a map
+#[derive(Clone)]
+pub struct RenderOptions<'a, C: Coord> {
+    /// Width of a tile, in pixels
+    pub tile_size: u32,
+    pub background: Rgb,
+    /// If set, draw borders between tiles of different regions
+    ///
+    /// Tiles with `NO_REGION` (like water) do not get borders.
+    pub regions: Option<&'a MapData<C, u8>>,
+    pub border_color: Rgb,
+}
+
+impl<'a, C: Coord> Default for RenderOptions<'a, C> {
+    fn default() -> Self {
+        RenderOptions {
+            tile_size: 16,
+            background: [0x20, 0x20, 0x20],
+            regions: None,
+            border_color: [0xff, 0xff, 0xff],
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error("Image too big: {0}x{1} pixels")]
+    TooBig(u32, u32),
+    #[error("PNG encoding failed: {0}")]
+    Png(#[from] png::EncodingError),
+}
+
+/// Default colors for the different kinds of tiles
+pub fn tile_color(kind: TileKind) -> Rgb {
+    match kind {
+        TileKind::Water => [0x2a, 0x5d, 0x9e],
+        TileKind::Foundation => [0x9a, 0x8f, 0x7d],
+        TileKind::Regular => [0x8c, 0xb3, 0x69],
+        TileKind::Fertile => [0xb8, 0xd0, 0x60],
+        TileKind::Forest => [0x2f, 0x6b, 0x2f],
+        TileKind::Mountain => [0x7a, 0x6a, 0x5a],
+        TileKind::Destroyed => [0x4a, 0x40, 0x38],
+    }
+}
+
+/// Black or white, whichever is readable on top of `bg`
+fn text_color(bg: Rgb) -> Rgb {
+    let luma = 299 * bg[0] as u32 + 587 * bg[1] as u32 + 114 * bg[2] as u32;
+    if luma > 128 * 1000 {
+        [0, 0, 0]
+    } else {
+        [0xff, 0xff, 0xff]
+    }
+}
+
+/// Corners of a tile, in tile units, in clockwise order (with `y` down)
+fn corners<C: Coord>(c: C) -> Vec<Vec2> {
+    let t = c.translation();
+    let offsets: &[(f32, f32)] = match C::TOPOLOGY {
+        Topology::Hex => &[
+            (0.0, -0.5), (0.5, -0.25), (0.5, 0.25),
+            (0.0, 0.5), (-0.5, 0.25), (-0.5, -0.25),
+        ],
+        Topology::Sq => &[(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)],
+    };
+    offsets.iter().map(|(x, y)| t + Vec2::new(*x, *y)).collect()
+}
+
+/// Maps tile units to image pixels
+struct Layout {
+    scale: f32,
+    origin: Vec2,
+    width: u32,
+    height: u32,
+}
+
+impl Layout {
+    fn new<C: Coord, D>(map: &MapData<C, D>, tile_size: u32) -> Layout {
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+        for c in map.iter_coords(None) {
+            for v in corners(c) {
+                min = min.min(v);
+                max = max.max(v);
+            }
+        }
+        if min.x > max.x {
+            min = Vec2::ZERO;
+            max = Vec2::ZERO;
+        }
+        let scale = tile_size.max(1) as f32;
+        let margin = scale / 4.0;
+        let size = ((max - min) * scale + 2.0 * margin).ceil();
+        Layout {
+            scale,
+            origin: min * scale - margin,
+            width: size.x as u32,
+            height: size.y as u32,
+        }
+    }
+
+    fn px(&self, v: Vec2) -> Vec2 {
+        v * self.scale - self.origin
+    }
+
+    fn corners<C: Coord>(&self, c: C) -> Vec<Vec2> {
+        corners(c).into_iter().map(|v| self.px(v)).collect()
+    }
+
+    fn center<C: Coord>(&self, c: C) -> Vec2 {
+        self.px(c.translation())
+    }
+
+    fn line_width(&self) -> f32 {
+        (self.scale / 8.0).max(1.0)
+    }
+}
+
+/// Shrink a polygon towards its center, for drawing owner outlines
+fn inset(poly: &[Vec2], center: Vec2) -> Vec<Vec2> {
+    poly.iter().map(|v| center + (*v - center) * 0.75).collect()
+}
+
+/// Segments between adjacent tiles of different regions
+fn region_borders<C: Coord, D>(
+    map: &MapData<C, D>,
+    regions: &MapData<C, u8>,
+) -> Vec<(Vec2, Vec2)> {
+    let mut out = vec![];
+    for c in map.iter_coords(None) {
+        let Some(r) = regions.get(c).filter(|r| **r != NO_REGION) else {
+            continue;
+        };
+        for c2 in c.iter_n0() {
+            // every edge only once
+            if c2 <= c || !map.contains(c2) {
+                continue;
+            }
+            if regions.get(c2).map(|r2| r2 == r || *r2 == NO_REGION).unwrap_or(true) {
+                continue;
+            }
+            let b = corners(c2);
+            let shared: Vec<Vec2> = corners(c).into_iter()
+                .filter(|v| b.iter().any(|v2| v.distance_squared(*v2) < 1e-4))
+                .collect();
+            if let [v0, v1] = shared[..] {
+                out.push((v0, v1));
+            }
+        }
+    }
+    out
+}
+
+/// Render the map as a PNG image
+pub fn render_png<C: Coord, D>(
+    map: &MapData<C, D>,
+    opts: &RenderOptions<C>,
+    mut f: impl FnMut(C, &D) -> TileStyle,
+) -> Result<Vec<u8>, RenderError> {
+    let layout = Layout::new(map, opts.tile_size);
+    if layout.width > MAX_PIXELS || layout.height > MAX_PIXELS {
+        return Err(RenderError::TooBig(layout.width, layout.height));
+    }
+    let mut img = Raster::new(layout.width, layout.height, opts.background);
+
+    for (c, d) in map.iter() {
+        let style = f(c, d);
+        let poly = layout.corners(c);
+        let center = layout.center(c);
+        if let Some(owner) = style.owner {
+            img.fill_poly(&poly, owner);
+            img.fill_poly(&inset(&poly, center), style.fill);
+        } else {
+            img.fill_poly(&poly, style.fill);
+        }
+        if let Some(digit) = style.digit {
+            img.digit(center, digit, (layout.scale / 10.0).max(1.0) as u32, text_color(style.fill));
+        }
+    }
+    if let Some(regions) = opts.regions {
+        for (a, b) in region_borders(map, regions) {
+            img.line(layout.px(a), layout.px(b), layout.line_width(), opts.border_color);
+        }
+    }
+
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, layout.width, layout.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&img.data)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// Render the map as an SVG document
+pub fn render_svg<C: Coord, D>(
+    map: &MapData<C, D>,
+    opts: &RenderOptions<C>,
+    mut f: impl FnMut(C, &D) -> TileStyle,
+) -> String {
+    let layout = Layout::new(map, opts.tile_size);
+    let hex = |rgb: Rgb| format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]);
+    let points = |poly: &[Vec2]| {
+        poly.iter()
+            .map(|v| format!("{:.1},{:.1}", v.x, v.y))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    // writing to a String cannot fail
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        layout.width, layout.height,
+    ).unwrap();
+    writeln!(out, r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(opts.background)).unwrap();
+
+    let mut texts = vec![];
+    for (c, d) in map.iter() {
+        let style = f(c, d);
+        let poly = layout.corners(c);
+        let center = layout.center(c);
+        if let Some(owner) = style.owner {
+            writeln!(out, r#"<polygon points="{}" fill="{}"/>"#, points(&poly), hex(owner)).unwrap();
+            writeln!(out, r#"<polygon points="{}" fill="{}"/>"#, points(&inset(&poly, center)), hex(style.fill)).unwrap();
+        } else {
+            writeln!(out, r#"<polygon points="{}" fill="{}"/>"#, points(&poly), hex(style.fill)).unwrap();
+        }
+        if let Some(digit) = style.digit {
+            texts.push((center, digit, text_color(style.fill)));
+        }
+    }
+    if let Some(regions) = opts.regions {
+        for (a, b) in region_borders(map, regions) {
+            let (a, b) = (layout.px(a), layout.px(b));
+            writeln!(
+                out,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="{:.1}" stroke-linecap="round"/>"#,
+                a.x, a.y, b.x, b.y, hex(opts.border_color), layout.line_width(),
+            ).unwrap();
+        }
+    }
+    for (center, digit, color) in texts {
+        writeln!(
+            out,
+            r#"<text x="{:.1}" y="{:.1}" font-family="monospace" font-size="{:.1}" text-anchor="middle" dominant-baseline="central" fill="{}">{}</text>"#,
+            center.x, center.y, layout.scale / 2.0, hex(color), digit,
+        ).unwrap();
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// A simple RGB pixel buffer
+struct Raster {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Raster {
+    fn new(width: u32, height: u32, bg: Rgb) -> Raster {
+        Raster {
+            width,
+            height,
+            data: bg.repeat(width as usize * height as usize),
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, color: Rgb) {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        self.data[i..i + 3].copy_from_slice(&color);
+    }
+
+    /// Call `f` for every pixel whose center is in the given box
+    fn for_each_px(&mut self, min: Vec2, max: Vec2, mut f: impl FnMut(Vec2) -> Option<Rgb>) {
+        let x0 = min.x.floor().max(0.0) as u32;
+        let y0 = min.y.floor().max(0.0) as u32;
+        let x1 = (max.x.ceil() as u32).min(self.width);
+        let y1 = (max.y.ceil() as u32).min(self.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if let Some(color) = f(Vec2::new(x as f32 + 0.5, y as f32 + 0.5)) {
+                    self.set(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Fill a convex polygon (with clockwise corners)
+    fn fill_poly(&mut self, poly: &[Vec2], color: Rgb) {
+        let min = poly.iter().fold(Vec2::splat(f32::MAX), |a, v| a.min(*v));
+        let max = poly.iter().fold(Vec2::splat(f32::MIN), |a, v| a.max(*v));
+        self.for_each_px(min, max, |p| {
+            let inside = poly.iter()
+                .zip(poly.iter().cycle().skip(1))
+                .all(|(a, b)| (*b - *a).perp_dot(p - *a) >= 0.0);
+            inside.then_some(color)
+        });
+    }
+
+    fn line(&mut self, a: Vec2, b: Vec2, width: f32, color: Rgb) {
+        let r = width / 2.0;
+        self.for_each_px(a.min(b) - r, a.max(b) + r, |p| {
+            let ab = b - a;
+            let t = ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0);
+            (p.distance(a + ab * t) <= r).then_some(color)
+        });
+    }
+
+    /// Draw a digit centered on `center`, with `cell`-sized font pixels
+    fn digit(&mut self, center: Vec2, digit: u8, cell: u32, color: Rgb) {
+        let Some(glyph) = FONT.get(digit as usize) else {
+            return;
+        };
+        let cell = cell as f32;
+        let top_left = center - Vec2::new(1.5, 2.5) * cell;
+        self.for_each_px(top_left, top_left + Vec2::new(3.0, 5.0) * cell, |p| {
+            let gx = ((p.x - top_left.x) / cell) as usize;
+            let gy = ((p.y - top_left.y) / cell) as usize;
+            let on = gy < 5 && gx < 3 && glyph[gy] & (0b100 >> gx) != 0;
+            on.then_some(color)
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::grid::{Hex, Sq};
+
+    use super::*;
+
+    fn decode(data: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let mut reader = png::Decoder::new(data).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(info.buffer_size());
+        (info, buf)
+    }
+
+    fn pixel(info: &png::OutputInfo, buf: &[u8], p: Vec2) -> Rgb {
+        let i = (p.y as usize * info.width as usize + p.x as usize) * 3;
+        [buf[i], buf[i + 1], buf[i + 2]]
+    }
+
+    #[test]
+    fn png_colors() {
+        let map = MapData::<Hex, _>::new_with(3, |c| c.ring() < 2);
+        let opts = RenderOptions { tile_size: 20, ..Default::default() };
+        let style = |_, land: &bool| {
+            TileStyle::fill(tile_color(if *land { TileKind::Regular } else { TileKind::Water }))
+        };
+        let data = render_png(&map, &opts, style).unwrap();
+        let (info, buf) = decode(&data);
+        let layout = Layout::new(&map, 20);
+        assert_eq!((info.width, info.height), (layout.width, layout.height));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        // off-center, so that digits/outlines would not be hit
+        let probe = |c: Hex| layout.center(c) + Vec2::new(-3.0, 4.0);
+        assert_eq!(pixel(&info, &buf, probe(Hex(0, 0))), tile_color(TileKind::Regular));
+        assert_eq!(pixel(&info, &buf, probe(Hex(-3, 1))), tile_color(TileKind::Water));
+        assert_eq!(pixel(&info, &buf, Vec2::ZERO), opts.background);
+    }
+
+    #[test]
+    fn png_overlays() {
+        let map = MapData::<Sq, _>::new(1, ());
+        let regions = MapData::<Sq, _>::new_with(1, |c| (c.1 > 0) as u8);
+        let opts = RenderOptions {
+            tile_size: 30,
+            regions: Some(&regions),
+            ..Default::default()
+        };
+        let fill = [0xa0, 0xa0, 0xa0];
+        let owner = [0xff, 0, 0];
+        let data = render_png(&map, &opts, |c, _| TileStyle {
+            fill,
+            digit: Some(8),
+            owner: (c == Sq(0, 0)).then_some(owner),
+        }).unwrap();
+        let (info, buf) = decode(&data);
+        let layout = Layout::new(&map, 30);
+        let center = layout.center(Sq(0, 0));
+        // the middle of an 8 is lit
+        assert_eq!(pixel(&info, &buf, center), [0, 0, 0]);
+        // owner outline along the edge of the tile
+        assert_eq!(pixel(&info, &buf, center - Vec2::new(0.0, 13.0)), owner);
+        assert_eq!(pixel(&info, &buf, center - Vec2::new(8.0, 8.0)), fill);
+        // region border between x=0 and x=1
+        let border = layout.px(Vec2::new(0.5, -1.0));
+        assert_eq!(pixel(&info, &buf, border), opts.border_color);
+        let no_border = layout.px(Vec2::new(-0.5, -1.0));
+        assert_ne!(pixel(&info, &buf, no_border), opts.border_color);
+    }
+
+    #[test]
+    fn svg() {
+        let map = MapData::<Hex, _>::new(2, ());
+        let regions = MapData::<Hex, _>::new_with(2, |c: Hex| (c.0 > 0) as u8);
+        let opts = RenderOptions { regions: Some(&regions), ..Default::default() };
+        let svg = render_svg(&map, &opts, |c: Hex, _| TileStyle {
+            fill: [0x12, 0x34, 0x56],
+            digit: (c == Hex(0, 0)).then_some(5),
+            owner: None,
+        });
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<polygon ").count(), map.data().len());
+        assert_eq!(svg.matches("#123456").count(), map.data().len());
+        assert_eq!(svg.matches("<text ").count(), 1);
+        assert!(svg.contains(">5</text>"));
+        // the border between rows 0 and 1 is made of 2 edges for each of
+        // the 5 tiles of row 0, except the ends, which have only 1
+        assert_eq!(svg.matches("<line ").count(), 8);
+    }
+
+    #[test]
+    fn too_big() {
+        let map = MapData::<Sq, _>::new(20, ());
+        let opts = RenderOptions { tile_size: 1000, ..Default::default() };
+        let r = render_png(&map, &opts, |_, _| TileStyle::fill([0; 3]));
+        assert!(matches!(r, Err(RenderError::TooBig(..))));
+    }
+}
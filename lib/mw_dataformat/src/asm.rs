@@ -2,7 +2,7 @@ use std::fmt::Formatter;
 use mw_common::{grid::Pos, plid::PlayerId};
 use thiserror::Error;
 
-use crate::msg::{Msg, MsgItem, MsgStructureKind};
+use crate::msg::{Msg, MsgItem, MsgPlayer, MsgStructureKind, MsgTileKind};
 
 pub trait Assembly: Sized {
     type DisasmError: std::error::Error;
@@ -111,20 +111,62 @@ impl Assembly for Msg {
 
         match first {
             Msg::Player { plid, status } => {
-                writeln!(fmt, "PLAYER {} {}", u8::from(*plid), status)?;
+                write!(fmt, "PLAYER {} ", u8::from(*plid))?;
+                match status {
+                    MsgPlayer::Joined => writeln!(fmt, "joined")?,
+                    MsgPlayer::Ping { millis } => writeln!(fmt, "ping {}", millis)?,
+                    MsgPlayer::Stunned { millis } => writeln!(fmt, "stunned {}", millis)?,
+                    MsgPlayer::Unstunned => writeln!(fmt, "unstunned")?,
+                    MsgPlayer::Blinded { millis } => writeln!(fmt, "blinded {}", millis)?,
+                    MsgPlayer::Unblinded => writeln!(fmt, "unblinded")?,
+                    MsgPlayer::Protected => writeln!(fmt, "protected")?,
+                    MsgPlayer::Unprotected => writeln!(fmt, "unprotected")?,
+                    MsgPlayer::Eliminated => writeln!(fmt, "eliminated")?,
+                    MsgPlayer::Surrendered => writeln!(fmt, "surrendered")?,
+                    MsgPlayer::Disconnected => writeln!(fmt, "disconnected")?,
+                    MsgPlayer::Kicked => writeln!(fmt, "kicked")?,
+                    MsgPlayer::FriendlyChat(text) => writeln!(fmt, "chat {}", text)?,
+                    MsgPlayer::AllChat(text) => writeln!(fmt, "allchat {}", text)?,
+                    MsgPlayer::LivesRemain { lives } => writeln!(fmt, "lives {}", lives)?,
+                    MsgPlayer::MatchTimeRemain { secs } => writeln!(fmt, "timeremain {}", secs)?,
+                    MsgPlayer::Exploded { pos } => writeln!(fmt, "exploded {},{}", pos.0, pos.1)?,
+                    MsgPlayer::Hint { pos, mine } => {
+                        writeln!(fmt, "hint {},{} {}", pos.0, pos.1, if *mine { "mine" } else { "safe" })?
+                    }
+                    MsgPlayer::GameResult { rank, tiles, mines_hit, lives, eliminated_secs } => {
+                        write!(fmt, "result {} {} {} {} ", rank, tiles, mines_hit, lives)?;
+                        if let Some(secs) = eliminated_secs {
+                            writeln!(fmt, "{}", secs)?
+                        } else {
+                            writeln!(fmt, "-")?
+                        }
+                    }
+                }
             },
             Msg::Capture { pos, digit } => {
-                writeln!(fmt, "DIGITS {}/{},{}", digit, pos.0, pos.1)?;
+                writeln!(fmt, "DIGITS {}/{},{}", fmt_digit(*digit), pos.0, pos.1)?;
             },
             Msg::TileOwner { pos, plid } => {
                 writeln!(fmt, "OWNER {} {},{}", u8::from(*plid), pos.0, pos.1)?;
             },
             Msg::Digit { pos, digit } => {
-                writeln!(fmt, "DIGIT {}/{},{}", digit, pos.0, pos.1)?;
+                writeln!(fmt, "DIGIT {}/{},{}", fmt_digit(*digit), pos.0, pos.1)?;
             },
             Msg::CitUpdate { cit, money, income, res } => {
                 writeln!(fmt, "CIT {} {} {} {}", cit, res, money, income)?;
             },
+            Msg::CitMoney { cit, money, income } => {
+                writeln!(fmt, "CITMONEY {} {} {}", cit, money, income)?;
+            },
+            Msg::CitRes { cit, res } => {
+                writeln!(fmt, "CITRES {} {}", cit, res)?;
+            },
+            Msg::CitSpend { cit, spent } => {
+                writeln!(fmt, "CITSPEND {} {}", cit, spent)?;
+            },
+            Msg::CitTrade { cit, export, import } => {
+                writeln!(fmt, "CITTRADE {} {} {}", cit, export, import)?;
+            },
             Msg::RevealStructure { pos, kind } => {
                 writeln!(fmt, "STRUCT {},{} {}", pos.0, pos.1, match kind {
                     MsgStructureKind::Road => "road",
@@ -150,6 +192,21 @@ impl Assembly for Msg {
             Msg::Construction { pos, current, rate } => {
                 writeln!(fmt, "BUILD {},{} {} {}", pos.0, pos.1, current, rate)?;
             },
+            Msg::TileKind { pos, kind } => {
+                writeln!(fmt, "TILE {},{} {}", pos.0, pos.1, match kind {
+                    MsgTileKind::Water => "water",
+                    MsgTileKind::Foundation => "foundation",
+                    MsgTileKind::Mountain => "mountain",
+                    MsgTileKind::Forest => "forest",
+                    MsgTileKind::Destroyed => "destroyed",
+                    MsgTileKind::Regular => "regular",
+                    MsgTileKind::Fertile => "fertile",
+                })?;
+            },
+            Msg::Flag { pos, plid, question } => {
+                let mark = if *question { "?" } else { "" };
+                writeln!(fmt, "FLAG {}{} {},{}", u8::from(*plid), mark, pos.0, pos.1)?;
+            },
             Msg::RevealItem { pos, item } => {
                 writeln!(fmt, "ITEM {},{} {}", pos.0, pos.1, match item {
                     MsgItem::None => "none",
@@ -164,6 +221,9 @@ impl Assembly for Msg {
             Msg::Smoke { pos } => {
                 writeln!(fmt, "SMOKE {},{}", pos.0, pos.1)?;
             },
+            Msg::Unsmoke { pos } => {
+                writeln!(fmt, "UNSMOKE {},{}", pos.0, pos.1)?;
+            },
             Msg::Tremor => {
                 writeln!(fmt, "SHAKE")?;
             },
@@ -202,9 +262,6 @@ impl Assembly for Msg {
                 let Some(arg_status) = components.next() else {
                     return Err(MsgAsmError::NotEnoughArgs);
                 };
-                if components.next().is_some() {
-                    return Err(MsgAsmError::TooManyArgs);
-                }
                 let Ok(plid) = arg_plid.parse::<u8>() else {
                     return Err(MsgAsmError::BadArg(arg_plid.to_owned()));
                 };
@@ -212,8 +269,73 @@ impl Assembly for Msg {
                     return Err(MsgAsmError::BadArg(arg_plid.to_owned()));
                 }
                 let plid = PlayerId::from(plid);
-                let Ok(status) = arg_status.parse() else {
-                    return Err(MsgAsmError::BadArg(arg_status.to_owned()));
+                let status = match arg_status.to_ascii_uppercase().as_str() {
+                    "CHAT" | "ALLCHAT" => {
+                        // the chat text is the rest of the line, as-is
+                        let Some((_, text)) = source.split_once(arg_status) else {
+                            return Err(MsgAsmError::NotEnoughArgs);
+                        };
+                        let text = text.trim().to_owned();
+                        if arg_status.eq_ignore_ascii_case("CHAT") {
+                            MsgPlayer::FriendlyChat(text)
+                        } else {
+                            MsgPlayer::AllChat(text)
+                        }
+                    }
+                    other => {
+                        let status = match other {
+                            "JOINED" => MsgPlayer::Joined,
+                            "PING" => MsgPlayer::Ping { millis: parse_arg(components.next())? },
+                            "STUNNED" => MsgPlayer::Stunned { millis: parse_arg(components.next())? },
+                            "UNSTUNNED" => MsgPlayer::Unstunned,
+                            "BLINDED" => MsgPlayer::Blinded { millis: parse_arg(components.next())? },
+                            "UNBLINDED" => MsgPlayer::Unblinded,
+                            "PROTECTED" => MsgPlayer::Protected,
+                            "UNPROTECTED" => MsgPlayer::Unprotected,
+                            "ELIMINATED" => MsgPlayer::Eliminated,
+                            "SURRENDERED" => MsgPlayer::Surrendered,
+                            "DISCONNECTED" => MsgPlayer::Disconnected,
+                            "KICKED" => MsgPlayer::Kicked,
+                            "LIVES" => MsgPlayer::LivesRemain { lives: parse_arg(components.next())? },
+                            "TIMEREMAIN" => MsgPlayer::MatchTimeRemain { secs: parse_arg(components.next())? },
+                            "EXPLODED" => {
+                                let Some(arg_pos) = components.next() else {
+                                    return Err(MsgAsmError::NotEnoughArgs);
+                                };
+                                MsgPlayer::Exploded { pos: parse_pos(arg_pos)? }
+                            }
+                            "HINT" => {
+                                let Some(arg_pos) = components.next() else {
+                                    return Err(MsgAsmError::NotEnoughArgs);
+                                };
+                                let Some(arg_kind) = components.next() else {
+                                    return Err(MsgAsmError::NotEnoughArgs);
+                                };
+                                let mine = match arg_kind.to_ascii_uppercase().as_str() {
+                                    "SAFE" => false,
+                                    "MINE" => true,
+                                    _ => return Err(MsgAsmError::BadArg(arg_kind.to_owned())),
+                                };
+                                MsgPlayer::Hint { pos: parse_pos(arg_pos)?, mine }
+                            }
+                            "RESULT" => {
+                                let rank = parse_arg(components.next())?;
+                                let tiles = parse_arg(components.next())?;
+                                let mines_hit = parse_arg(components.next())?;
+                                let lives = parse_arg(components.next())?;
+                                let eliminated_secs = match components.next() {
+                                    Some("-") => None,
+                                    arg => Some(parse_arg(arg)?),
+                                };
+                                MsgPlayer::GameResult { rank, tiles, mines_hit, lives, eliminated_secs }
+                            }
+                            _ => return Err(MsgAsmError::BadArg(arg_status.to_owned())),
+                        };
+                        if components.next().is_some() {
+                            return Err(MsgAsmError::TooManyArgs);
+                        }
+                        status
+                    }
                 };
                 if buffer.len() < 1 {
                     return Err(MsgAsmError::BufferFull);
@@ -229,9 +351,7 @@ impl Assembly for Msg {
                     let Some((arg_digit, arg_pos)) = arg.split_once('/') else {
                         return Err(MsgAsmError::BadArg(arg.to_owned()));
                     };
-                    let Ok(digit) = arg_digit.parse() else {
-                        return Err(MsgAsmError::BadArg(arg_digit.to_owned()));
-                    };
+                    let digit = parse_digit(arg_digit)?;
                     let pos = parse_pos(arg_pos)?;
                     if buffer.len() < n + 1 {
                         return Err(MsgAsmError::BufferFull);
@@ -283,9 +403,7 @@ impl Assembly for Msg {
                 if components.next().is_some() {
                     return Err(MsgAsmError::TooManyArgs);
                 }
-                let Ok(digit) = arg_digit.parse() else {
-                    return Err(MsgAsmError::BadArg(arg_digit.to_owned()));
-                };
+                let digit = parse_digit(arg_digit)?;
                 let pos = parse_pos(arg_pos)?;
                 if buffer.len() < 1 {
                     return Err(MsgAsmError::BufferFull);
@@ -331,6 +449,126 @@ impl Assembly for Msg {
                 };
                 Ok(1)
             }
+            "CITMONEY" => {
+                let cit = parse_arg(components.next())?;
+                let money = parse_arg(components.next())?;
+                let income = parse_arg(components.next())?;
+                if components.next().is_some() {
+                    return Err(MsgAsmError::TooManyArgs);
+                }
+                if buffer.is_empty() {
+                    return Err(MsgAsmError::BufferFull);
+                }
+                buffer[0] = Msg::CitMoney {
+                    cit, money, income,
+                };
+                Ok(1)
+            }
+            "CITRES" => {
+                let cit = parse_arg(components.next())?;
+                let res = parse_arg(components.next())?;
+                if components.next().is_some() {
+                    return Err(MsgAsmError::TooManyArgs);
+                }
+                if buffer.is_empty() {
+                    return Err(MsgAsmError::BufferFull);
+                }
+                buffer[0] = Msg::CitRes {
+                    cit, res,
+                };
+                Ok(1)
+            }
+            "CITSPEND" => {
+                let cit = parse_arg(components.next())?;
+                let spent = parse_arg(components.next())?;
+                if components.next().is_some() {
+                    return Err(MsgAsmError::TooManyArgs);
+                }
+                if buffer.is_empty() {
+                    return Err(MsgAsmError::BufferFull);
+                }
+                buffer[0] = Msg::CitSpend {
+                    cit, spent,
+                };
+                Ok(1)
+            }
+            "CITTRADE" => {
+                let cit = parse_arg(components.next())?;
+                let export = parse_arg(components.next())?;
+                let import = parse_arg(components.next())?;
+                if components.next().is_some() {
+                    return Err(MsgAsmError::TooManyArgs);
+                }
+                if buffer.is_empty() {
+                    return Err(MsgAsmError::BufferFull);
+                }
+                buffer[0] = Msg::CitTrade {
+                    cit, export, import,
+                };
+                Ok(1)
+            }
+            "TILE" => {
+                let Some(arg_pos) = components.next() else {
+                    return Err(MsgAsmError::NotEnoughArgs);
+                };
+                let Some(arg_kind) = components.next() else {
+                    return Err(MsgAsmError::NotEnoughArgs);
+                };
+                if components.next().is_some() {
+                    return Err(MsgAsmError::TooManyArgs);
+                }
+                let pos = parse_pos(arg_pos)?;
+                let kind = match arg_kind.to_ascii_uppercase().as_str() {
+                    "WATER" => MsgTileKind::Water,
+                    "FOUNDATION" => MsgTileKind::Foundation,
+                    "MOUNTAIN" => MsgTileKind::Mountain,
+                    "FOREST" => MsgTileKind::Forest,
+                    "DESTROYED" => MsgTileKind::Destroyed,
+                    "REGULAR" => MsgTileKind::Regular,
+                    "FERTILE" => MsgTileKind::Fertile,
+                    other => {
+                        return Err(MsgAsmError::BadArg(other.to_owned()));
+                    }
+                };
+                if buffer.is_empty() {
+                    return Err(MsgAsmError::BufferFull);
+                }
+                buffer[0] = Msg::TileKind {
+                    pos, kind,
+                };
+                Ok(1)
+            }
+            "FLAG" => {
+                let Some(arg_plid) = components.next() else {
+                    return Err(MsgAsmError::NotEnoughArgs);
+                };
+                let Some(arg_pos) = components.next() else {
+                    return Err(MsgAsmError::NotEnoughArgs);
+                };
+                if components.next().is_some() {
+                    return Err(MsgAsmError::TooManyArgs);
+                }
+                // question marks have a `?` suffix (like `3?`)
+                let (s_plid, question) = match arg_plid.strip_suffix('?') {
+                    Some(s_plid) => (s_plid, true),
+                    None => (arg_plid, false),
+                };
+                let Ok(plid) = s_plid.parse::<u8>() else {
+                    return Err(MsgAsmError::BadArg(arg_plid.to_owned()));
+                };
+                if plid > 15 || (question && plid == 0) {
+                    return Err(MsgAsmError::BadArg(arg_plid.to_owned()));
+                }
+                let plid = PlayerId::from(plid);
+                let pos = parse_pos(arg_pos)?;
+                if buffer.is_empty() {
+                    return Err(MsgAsmError::BufferFull);
+                }
+                buffer[0] = Msg::Flag {
+                    pos, plid, question,
+                };
+                Ok(1)
+            }
             "STRUCT" => {
                 let Some(arg_pos) = components.next() else {
                     return Err(MsgAsmError::NotEnoughArgs);
@@ -520,6 +758,22 @@ impl Assembly for Msg {
                 };
                 Ok(1)
             }
+            "UNSMOKE" => {
+                let Some(arg_pos) = components.next() else {
+                    return Err(MsgAsmError::NotEnoughArgs);
+                };
+                if components.next().is_some() {
+                    return Err(MsgAsmError::TooManyArgs);
+                }
+                let pos = parse_pos(arg_pos)?;
+                if buffer.is_empty() {
+                    return Err(MsgAsmError::BufferFull);
+                }
+                buffer[0] = Msg::Unsmoke {
+                    pos
+                };
+                Ok(1)
+            }
             "SHAKE" => {
                 if components.next().is_some() {
                     return Err(MsgAsmError::TooManyArgs);
@@ -547,6 +801,37 @@ impl Assembly for Msg {
     }
 }
 
+/// Numeric operand
+fn parse_arg<T: std::str::FromStr>(arg: Option<&str>) -> Result<T, MsgAsmError> {
+    let Some(arg) = arg else {
+        return Err(MsgAsmError::NotEnoughArgs);
+    };
+    arg.parse().map_err(|_| MsgAsmError::BadArg(arg.to_owned()))
+}
+
+/// Digit with optional asterisk suffix (like `3*`)
+fn parse_digit(s: &str) -> Result<u8, MsgAsmError> {
+    let (s_digit, asterisk) = match s.strip_suffix('*') {
+        Some(s_digit) => (s_digit, true),
+        None => (s, false),
+    };
+    let Ok(digit) = s_digit.parse::<u8>() else {
+        return Err(MsgAsmError::BadArg(s.to_owned()));
+    };
+    if digit > 7 {
+        return Err(MsgAsmError::BadArg(s.to_owned()));
+    }
+    Ok(if asterisk { digit | 0x08 } else { digit })
+}
+
+fn fmt_digit(digit: u8) -> String {
+    if digit & 0x08 != 0 {
+        format!("{}*", digit & 0x07)
+    } else {
+        format!("{}", digit & 0x07)
+    }
+}
+
 fn parse_pos(s: &str) -> Result<Pos, MsgAsmError> {
     // error we return if anything goes wrong
     let err = Err(MsgAsmError::BadArg(s.to_owned()));
@@ -578,6 +863,7 @@ mod test {
             DIGIT 4/0,0
             OWNER 3 4,5 7,8 ; varargs
             DIGITS 1/1,1 0/-1,-2 3/6,7
+            DIGIT 2*/1,1
             item 3,-4 None
             ITEM 3,-3 Flash
             ITEM 3,-2 mine
@@ -591,9 +877,23 @@ mod test {
             BUILDNEW 0,1 bridge 420
             StructHp 10,11 5
             DECONSTRUCT 0,1
-            PLAYER 5 0
+            PLAYER 5 joined
             BUILD 0,1 123 42
             SMOKE 0,0
+            UNSMOKE 0,0
+            PLAYER 2 stunned 1500
+            player 3 chat hello world
+            CITMONEY 1 500 20
+            CITRES 1 30
+            CITSPEND 1 250
+            CITTRADE 1 50 25
+            TILE 1,-1 destroyed
+            FLAG 3 1,1
+            FLAG 0 1,1
+            FLAG 2? 1,1
+            PLAYER 1 hint 2,-3 Mine
+            PLAYER 2 result 1 150 2 0 95
+            PLAYER 3 result 2 80 0 1 -
         ";
         let output = &[
             Msg::Nop,
@@ -604,6 +904,7 @@ mod test {
             Msg::Capture { digit: 1, pos: Pos(1,1) },
             Msg::Capture { digit: 0, pos: Pos(-1,-2) },
             Msg::Capture { digit: 3, pos: Pos(6,7) },
+            Msg::Digit { digit: 0x08 | 2, pos: Pos(1,1) },
             Msg::RevealItem { pos: Pos(3,-4), item: MsgItem::None },
             Msg::RevealItem { pos: Pos(3,-3), item: MsgItem::Flash },
             Msg::RevealItem { pos: Pos(3,-2), item: MsgItem::Mine },
@@ -619,9 +920,29 @@ mod test {
             Msg::BuildNew { pos: Pos(0, 1), kind: MsgStructureKind::Bridge, pts: 420 },
             Msg::StructureHp { pos: Pos(10, 11), hp: 5 },
             Msg::StructureGone { pos: Pos(0, 1) },
-            Msg::Player { plid: 5.into(), status: 0 },
+            Msg::Player { plid: 5.into(), status: MsgPlayer::Joined },
             Msg::Construction { pos: Pos(0, 1), current: 123, rate: 42 },
             Msg::Smoke { pos: Pos(0, 0) },
+            Msg::Unsmoke { pos: Pos(0, 0) },
+            Msg::Player { plid: 2.into(), status: MsgPlayer::Stunned { millis: 1500 } },
+            Msg::Player { plid: 3.into(), status: MsgPlayer::FriendlyChat("hello world".into()) },
+            Msg::CitMoney { cit: 1, money: 500, income: 20 },
+            Msg::CitRes { cit: 1, res: 30 },
+            Msg::CitSpend { cit: 1, spent: 250 },
+            Msg::CitTrade { cit: 1, export: 50, import: 25 },
+            Msg::TileKind { pos: Pos(1, -1), kind: MsgTileKind::Destroyed },
+            Msg::Flag { pos: Pos(1, 1), plid: 3.into(), question: false },
+            Msg::Flag { pos: Pos(1, 1), plid: PlayerId::Neutral, question: false },
+            Msg::Flag { pos: Pos(1, 1), plid: 2.into(), question: true },
+            Msg::Player { plid: 1.into(), status: MsgPlayer::Hint { pos: Pos(2, -3), mine: true } },
+            Msg::Player {
+                plid: 2.into(),
+                status: MsgPlayer::GameResult { rank: 1, tiles: 150, mines_hit: 2, lives: 0, eliminated_secs: Some(95) },
+            },
+            Msg::Player {
+                plid: 3.into(),
+                status: MsgPlayer::GameResult { rank: 2, tiles: 80, mines_hit: 0, lives: 1, eliminated_secs: None },
+            },
         ];
         let mut buffer = vec![Msg::Nop; 64];
         let len = Msg::asm_all(source, &mut buffer)
@@ -0,0 +1,1006 @@
+//! Binary encoding of the Player Stream gameplay messages
+//!
+//! Converts between the `Msg` IR and the compact opcode bytes described
+//! in the "Player Stream Format" documentation.
+
+use std::collections::VecDeque;
+
+use mw_common::{grid::Pos, plid::PlayerId};
+use thiserror::Error;
+
+use crate::msg::{Msg, MsgItem, MsgPlayer, MsgStructureKind, MsgTileKind};
+
+/// Max number of tiles that can be encoded in one "Digits (multi)" message
+const MAX_DIGITS_MULTI: usize = 8;
+/// Max number of tiles that can be encoded in one "Ownership Update" message
+const MAX_OWNERSHIP: usize = 8;
+/// Max number of tiles that can be encoded in one "Ownership Lost" message
+const MAX_OWNERSHIP_LOST: usize = 8;
+/// Max number of tiles that can be encoded in one "Explosions" message
+const MAX_EXPLOSIONS: usize = 16;
+
+/// Bit in the City MoneyInfo money field indicating that income follows
+const CITMONEY_HAS_INCOME: u32 = 1 << 31;
+
+#[derive(Debug, Error)]
+pub enum MsgEncodeError {
+    #[error("PlayerId {0} cannot be encoded in this message")]
+    BadPlid(u8),
+    #[error("Chat message too long ({0} bytes, max 255)")]
+    ChatTooLong(usize),
+    #[error("Digit value {0} out of range")]
+    BadDigit(u8),
+    #[error("Structure HP {0} out of range (must be 1-15)")]
+    BadHp(u8),
+    #[error("City money value {0} out of range (must fit in 31 bits)")]
+    BadMoney(u32),
+}
+
+#[derive(Debug, Error)]
+pub enum MsgDecodeError {
+    #[error("Unexpected end of data")]
+    Truncated,
+    #[error("Reserved opcode: {0:#010b}")]
+    Reserved(u8),
+    #[error("Opcode {0:#010b} has no `Msg` representation")]
+    Unsupported(u8),
+    #[error("Invalid value in message with opcode {0:#010b}")]
+    BadValue(u8),
+}
+
+/// Encode a sequence of messages into the binary Player Stream format
+///
+/// The encoded bytes are appended to `out`.
+///
+/// Consecutive messages that have a compact multi-tile encoding (`Capture`,
+/// `TileOwner` with the same plid, `Explode`) are grouped together. Sort the
+/// sequence beforehand, if you want the most compact output.
+///
+/// `Msg::Nop` has no binary representation and is skipped.
+///
+/// `Msg::CitUpdate` is encoded as a City MoneyInfo + City ResInfo pair. This
+/// means that a `Msg::CitMoney` immediately followed by a `Msg::CitRes` for
+/// the same city will decode as a `Msg::CitUpdate`.
+///
+/// On error, `out` may contain partially-written data.
+pub fn encode_msgs(msgs: &[Msg], out: &mut Vec<u8>) -> Result<(), MsgEncodeError> {
+    let mut i = 0;
+    while i < msgs.len() {
+        i += encode_one(&msgs[i..], out)?;
+    }
+    Ok(())
+}
+
+/// Encode a sequence of messages, splitting the output into chunks
+///
+/// Each chunk is at most `max_len` bytes long. Chunks are split at message
+/// boundaries, so each one can be decoded independently.
+pub(crate) fn encode_msgs_chunked(msgs: &[Msg], max_len: usize) -> Result<Vec<Vec<u8>>, MsgEncodeError> {
+    let mut chunks = vec![];
+    let mut chunk = vec![];
+    let mut unit = vec![];
+    let mut i = 0;
+    while i < msgs.len() {
+        unit.clear();
+        i += encode_one(&msgs[i..], &mut unit)?;
+        if !chunk.is_empty() && chunk.len() + unit.len() > max_len {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        chunk.extend_from_slice(&unit);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+/// Decode the binary Player Stream format into messages
+///
+/// Returns an iterator that produces messages as they are decoded.
+/// The iterator stops after the first error.
+pub fn decode_msgs(data: &[u8]) -> MsgDecoder<'_> {
+    MsgDecoder {
+        data,
+        offset: 0,
+        pending: VecDeque::with_capacity(MAX_EXPLOSIONS),
+        failed: false,
+    }
+}
+
+/// Streaming decoder for the binary Player Stream format
+///
+/// See [`decode_msgs`].
+pub struct MsgDecoder<'a> {
+    data: &'a [u8],
+    offset: usize,
+    pending: VecDeque<Msg>,
+    failed: bool,
+}
+
+impl<'a> MsgDecoder<'a> {
+    /// Byte offset of the next message to be decoded
+    ///
+    /// After an error, this is the offset of the message that failed to decode.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn push(&mut self, msg: Msg) {
+        self.pending.push_back(msg);
+    }
+
+    fn decode_next(&mut self) -> Result<(), MsgDecodeError> {
+        let mut r = Reader {
+            data: &self.data[self.offset..],
+            pos: 0,
+        };
+        let op = r.u8()?;
+        match op {
+            0b00000000 => {
+                let b = r.u8()?;
+                if b & 0xF0 != 0 {
+                    return Err(MsgDecodeError::Unsupported(op));
+                }
+                let status = match r.u8()? {
+                    0x00 => MsgPlayer::Joined,
+                    0x01 => MsgPlayer::Ping { millis: r.u16()? },
+                    0x02 => MsgPlayer::Stunned { millis: r.u16()? },
+                    0x03 => MsgPlayer::Unstunned,
+                    0x04 => MsgPlayer::Blinded { millis: r.u16()? },
+                    0x05 => MsgPlayer::Unblinded,
+                    0x06 => MsgPlayer::Protected,
+                    0x07 => MsgPlayer::Unprotected,
+                    0x08 => MsgPlayer::Eliminated,
+                    0x09 => MsgPlayer::Surrendered,
+                    0x0A => MsgPlayer::Disconnected,
+                    0x0B => MsgPlayer::Kicked,
+                    0x0C | 0x0D => return Err(MsgDecodeError::Unsupported(op)),
+                    0x0E => MsgPlayer::FriendlyChat(r.string(op)?),
+                    0x0F => MsgPlayer::AllChat(r.string(op)?),
+                    0x10 => MsgPlayer::LivesRemain { lives: r.u8()? },
+                    0x11 => MsgPlayer::MatchTimeRemain { secs: r.u16()? },
+                    0x12 => MsgPlayer::Exploded { pos: r.pos()? },
+                    0x13 => {
+                        let mine = match r.u8()? {
+                            0 => false,
+                            1 => true,
+                            _ => return Err(MsgDecodeError::BadValue(op)),
+                        };
+                        MsgPlayer::Hint { pos: r.pos()?, mine }
+                    }
+                    0x14 => MsgPlayer::GameResult {
+                        rank: r.u8()?,
+                        tiles: r.u16()?,
+                        mines_hit: r.u8()?,
+                        lives: r.u8()?,
+                        eliminated_secs: Some(r.u16()?).filter(|secs| *secs != u16::MAX),
+                    },
+                    _ => return Err(MsgDecodeError::BadValue(op)),
+                };
+                self.push(Msg::Player {
+                    plid: PlayerId::from(b),
+                    status,
+                });
+            }
+            0b00000001 => {
+                self.push(Msg::Tremor);
+            }
+            0b00000010 => {
+                let pos = r.pos()?;
+                self.push(Msg::Smoke { pos });
+            }
+            0b00000011 => {
+                let pos = r.pos()?;
+                self.push(Msg::Unsmoke { pos });
+            }
+            0b00000100 => {
+                let cit = r.u8()?;
+                let money = r.u32()?;
+                if money & CITMONEY_HAS_INCOME == 0 {
+                    return Err(MsgDecodeError::Unsupported(op));
+                }
+                let income = r.u16()?;
+                let money = money & !CITMONEY_HAS_INCOME;
+                // `Msg::CitUpdate` is encoded as a MoneyInfo + ResInfo pair
+                if r.data.get(r.pos..(r.pos + 2)) == Some(&[0b00000110, cit]) {
+                    r.pos += 2;
+                    let res = r.u16()?;
+                    self.push(Msg::CitUpdate { cit, res, money, income });
+                } else {
+                    self.push(Msg::CitMoney { cit, money, income });
+                }
+            }
+            0b00000101 => {
+                let cit = r.u8()?;
+                let spent = r.u16()?;
+                self.push(Msg::CitSpend { cit, spent });
+            }
+            0b00000110 => {
+                let cit = r.u8()?;
+                let res = r.u16()?;
+                self.push(Msg::CitRes { cit, res });
+            }
+            0b00000111 => {
+                let cit = r.u8()?;
+                let export = r.u8()?;
+                let import = r.u8()?;
+                self.push(Msg::CitTrade { cit, export, import });
+            }
+            0b00001000 => {
+                let b = r.u8()?;
+                if b & 0xE0 != 0 {
+                    return Err(MsgDecodeError::BadValue(op));
+                }
+                let pos = r.pos()?;
+                self.push(Msg::Flag { pos, plid: PlayerId::from(b & 0x0F), question: b & 0x10 != 0 });
+            }
+            0b00010000..=0b00010111 => {
+                let n = (op & 0x07) as usize + 1;
+                for _ in 0..n {
+                    let pos = r.pos()?;
+                    self.push(Msg::TileOwner { pos, plid: PlayerId::Neutral });
+                }
+            }
+            0b00100000 => {
+                let pos = r.pos()?;
+                self.push(Msg::StructureGone { pos });
+            }
+            0b00100001..=0b00101111 => {
+                let pos = r.pos()?;
+                self.push(Msg::StructureHp { pos, hp: op & 0x0F });
+            }
+            0b00110000..=0b00111111 => {
+                let n = (op & 0x0F) as usize + 1;
+                for _ in 0..n {
+                    let pos = r.pos()?;
+                    self.push(Msg::Explode { pos });
+                }
+            }
+            0b01001111 => {
+                let pos = r.pos()?;
+                let current = r.u16()?;
+                let rate = r.u16()?;
+                self.push(Msg::Construction { pos, current, rate });
+            }
+            0b01000000..=0b01001110 => {
+                let kind = structure_kind_from_bits(op)?;
+                let pos = r.pos()?;
+                let pts = r.u16()?;
+                self.push(Msg::BuildNew { pos, kind, pts });
+            }
+            0b01011111 => {
+                return Err(MsgDecodeError::Reserved(op));
+            }
+            0b01010000..=0b01011110 => {
+                let kind = structure_kind_from_bits(op)?;
+                let pos = r.pos()?;
+                self.push(Msg::RevealStructure { pos, kind });
+            }
+            0b01100000..=0b01101111 => {
+                let pos = r.pos()?;
+                self.push(Msg::Digit { pos, digit: op & 0x0F });
+            }
+            0b01110000..=0b01110111 => {
+                let item = match op & 0x07 {
+                    0 => MsgItem::None,
+                    1 => MsgItem::Decoy,
+                    2 => MsgItem::Mine,
+                    3 => MsgItem::Flash,
+                    _ => return Err(MsgDecodeError::BadValue(op)),
+                };
+                let pos = r.pos()?;
+                self.push(Msg::RevealItem { pos, item });
+            }
+            0b01111000..=0b01111111 => {
+                let kind = match op & 0x07 {
+                    0b000 => MsgTileKind::Water,
+                    0b001 => MsgTileKind::Foundation,
+                    0b010 => MsgTileKind::Mountain,
+                    0b011 => MsgTileKind::Forest,
+                    0b100 => MsgTileKind::Destroyed,
+                    0b110 => MsgTileKind::Regular,
+                    0b111 => MsgTileKind::Fertile,
+                    _ => return Err(MsgDecodeError::BadValue(op)),
+                };
+                let pos = r.pos()?;
+                self.push(Msg::TileKind { pos, kind });
+            }
+            0b10000000..=0b10000111 => {
+                let n = (op & 0x07) as usize + 1;
+                let start = self.pending.len();
+                for _ in 0..n {
+                    let pos = r.pos()?;
+                    self.push(Msg::Capture { pos, digit: 0 });
+                }
+                for i in (0..n).step_by(2) {
+                    let b = r.u8()?;
+                    let i0 = start + i;
+                    if let Msg::Capture { digit, .. } = &mut self.pending[i0] {
+                        *digit = b >> 4;
+                    }
+                    if i0 + 1 < self.pending.len() {
+                        if let Msg::Capture { digit, .. } = &mut self.pending[i0 + 1] {
+                            *digit = b & 0x0F;
+                        }
+                    }
+                }
+            }
+            0b10001000..=0b11111111 => {
+                let plid = PlayerId::from((op >> 3) & 0x0F);
+                let n = (op & 0x07) as usize + 1;
+                for _ in 0..n {
+                    let pos = r.pos()?;
+                    self.push(Msg::TileOwner { pos, plid });
+                }
+            }
+            _ => {
+                return Err(MsgDecodeError::Reserved(op));
+            }
+        }
+        self.offset += r.pos;
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for MsgDecoder<'a> {
+    type Item = Result<Msg, MsgDecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Some(Ok(msg));
+        }
+        if self.failed || self.offset >= self.data.len() {
+            return None;
+        }
+        if let Err(e) = self.decode_next() {
+            self.failed = true;
+            self.pending.clear();
+            return Some(Err(e));
+        }
+        self.next()
+    }
+}
+
+impl<'a> std::iter::FusedIterator for MsgDecoder<'a> {}
+
+/// Encode the first message, and any following messages that can be grouped with it
+///
+/// Returns how many messages were consumed.
+pub(crate) fn encode_one(msgs: &[Msg], out: &mut Vec<u8>) -> Result<usize, MsgEncodeError> {
+    match msgs[0] {
+        Msg::Player { plid, ref status } => {
+            let b = u8::from(plid);
+            if b > 0x0F {
+                return Err(MsgEncodeError::BadPlid(b));
+            }
+            out.extend_from_slice(&[0b00000000, b, status.kind()]);
+            match status {
+                MsgPlayer::Ping { millis } |
+                MsgPlayer::Stunned { millis } |
+                MsgPlayer::Blinded { millis } => {
+                    out.extend_from_slice(&millis.to_be_bytes());
+                }
+                MsgPlayer::FriendlyChat(text) |
+                MsgPlayer::AllChat(text) => {
+                    let Ok(len) = u8::try_from(text.len()) else {
+                        return Err(MsgEncodeError::ChatTooLong(text.len()));
+                    };
+                    out.push(len);
+                    out.extend_from_slice(text.as_bytes());
+                }
+                MsgPlayer::LivesRemain { lives } => {
+                    out.push(*lives);
+                }
+                MsgPlayer::MatchTimeRemain { secs } => {
+                    out.extend_from_slice(&secs.to_be_bytes());
+                }
+                MsgPlayer::Exploded { pos } => {
+                    push_pos(out, *pos);
+                }
+                MsgPlayer::Hint { pos, mine } => {
+                    out.push(*mine as u8);
+                    push_pos(out, *pos);
+                }
+                MsgPlayer::GameResult { rank, tiles, mines_hit, lives, eliminated_secs } => {
+                    out.push(*rank);
+                    out.extend_from_slice(&tiles.to_be_bytes());
+                    out.push(*mines_hit);
+                    out.push(*lives);
+                    let secs = eliminated_secs.map(|secs| secs.min(u16::MAX - 1)).unwrap_or(u16::MAX);
+                    out.extend_from_slice(&secs.to_be_bytes());
+                }
+                _ => {}
+            }
+            Ok(1)
+        }
+        Msg::Capture { .. } => {
+            let n = msgs.iter()
+                .take(MAX_DIGITS_MULTI)
+                .take_while(|msg| matches!(msg, Msg::Capture { .. }))
+                .count();
+            out.push(0b10000000 | (n - 1) as u8);
+            let mut digits = [0u8; MAX_DIGITS_MULTI];
+            for (i, msg) in msgs[..n].iter().enumerate() {
+                if let Msg::Capture { pos, digit } = *msg {
+                    if digit > 0x0F {
+                        return Err(MsgEncodeError::BadDigit(digit));
+                    }
+                    push_pos(out, pos);
+                    digits[i] = digit;
+                }
+            }
+            for pair in digits[..n].chunks(2) {
+                let lo = pair.get(1).copied().unwrap_or(0);
+                out.push(pair[0] << 4 | lo);
+            }
+            Ok(n)
+        }
+        Msg::TileOwner { plid: PlayerId::Neutral, .. } => {
+            let n = msgs.iter()
+                .take(MAX_OWNERSHIP_LOST)
+                .take_while(|msg| matches!(msg, Msg::TileOwner { plid: PlayerId::Neutral, .. }))
+                .count();
+            out.push(0b00010000 | (n - 1) as u8);
+            for msg in &msgs[..n] {
+                if let Msg::TileOwner { pos, .. } = *msg {
+                    push_pos(out, pos);
+                }
+            }
+            Ok(n)
+        }
+        Msg::TileOwner { plid, .. } => {
+            let b = u8::from(plid);
+            if b > 0x0F {
+                return Err(MsgEncodeError::BadPlid(b));
+            }
+            let n = msgs.iter()
+                .take(MAX_OWNERSHIP)
+                .take_while(|msg| matches!(msg, Msg::TileOwner { plid: p, .. } if *p == plid))
+                .count();
+            out.push(0b10000000 | b << 3 | (n - 1) as u8);
+            for msg in &msgs[..n] {
+                if let Msg::TileOwner { pos, .. } = *msg {
+                    push_pos(out, pos);
+                }
+            }
+            Ok(n)
+        }
+        Msg::Digit { pos, digit } => {
+            if digit > 0x0F {
+                return Err(MsgEncodeError::BadDigit(digit));
+            }
+            out.push(0b01100000 | digit);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::CitUpdate { cit, res, money, income } => {
+            if money & CITMONEY_HAS_INCOME != 0 {
+                return Err(MsgEncodeError::BadMoney(money));
+            }
+            out.push(0b00000100);
+            out.push(cit);
+            out.extend_from_slice(&(money | CITMONEY_HAS_INCOME).to_be_bytes());
+            out.extend_from_slice(&income.to_be_bytes());
+            out.push(0b00000110);
+            out.push(cit);
+            out.extend_from_slice(&res.to_be_bytes());
+            Ok(1)
+        }
+        Msg::CitMoney { cit, money, income } => {
+            if money & CITMONEY_HAS_INCOME != 0 {
+                return Err(MsgEncodeError::BadMoney(money));
+            }
+            out.push(0b00000100);
+            out.push(cit);
+            out.extend_from_slice(&(money | CITMONEY_HAS_INCOME).to_be_bytes());
+            out.extend_from_slice(&income.to_be_bytes());
+            Ok(1)
+        }
+        Msg::CitRes { cit, res } => {
+            out.push(0b00000110);
+            out.push(cit);
+            out.extend_from_slice(&res.to_be_bytes());
+            Ok(1)
+        }
+        Msg::CitSpend { cit, spent } => {
+            out.push(0b00000101);
+            out.push(cit);
+            out.extend_from_slice(&spent.to_be_bytes());
+            Ok(1)
+        }
+        Msg::CitTrade { cit, export, import } => {
+            out.extend_from_slice(&[0b00000111, cit, export, import]);
+            Ok(1)
+        }
+        Msg::RevealStructure { pos, kind } => {
+            out.push(0b01010000 | kind as u8);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::StructureGone { pos } => {
+            out.push(0b00100000);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::StructureHp { pos, hp } => {
+            if hp == 0 || hp > 0x0F {
+                return Err(MsgEncodeError::BadHp(hp));
+            }
+            out.push(0b00100000 | hp);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::BuildNew { pos, kind, pts } => {
+            out.push(0b01000000 | kind as u8);
+            push_pos(out, pos);
+            out.extend_from_slice(&pts.to_be_bytes());
+            Ok(1)
+        }
+        Msg::Construction { pos, current, rate } => {
+            out.push(0b01001111);
+            push_pos(out, pos);
+            out.extend_from_slice(&current.to_be_bytes());
+            out.extend_from_slice(&rate.to_be_bytes());
+            Ok(1)
+        }
+        Msg::TileKind { pos, kind } => {
+            out.push(0b01111000 | kind as u8);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::Flag { pos, plid, question } => {
+            let b = u8::from(plid);
+            if b > 0x0F {
+                return Err(MsgEncodeError::BadPlid(b));
+            }
+            out.push(0b00001000);
+            out.push(b | if question { 0x10 } else { 0 });
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::RevealItem { pos, item } => {
+            out.push(0b01110000 | item as u8);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::Explode { .. } => {
+            let n = msgs.iter()
+                .take(MAX_EXPLOSIONS)
+                .take_while(|msg| matches!(msg, Msg::Explode { .. }))
+                .count();
+            out.push(0b00110000 | (n - 1) as u8);
+            for msg in &msgs[..n] {
+                if let Msg::Explode { pos } = *msg {
+                    push_pos(out, pos);
+                }
+            }
+            Ok(n)
+        }
+        Msg::Smoke { pos } => {
+            out.push(0b00000010);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::Unsmoke { pos } => {
+            out.push(0b00000011);
+            push_pos(out, pos);
+            Ok(1)
+        }
+        Msg::Tremor => {
+            out.push(0b00000001);
+            Ok(1)
+        }
+        Msg::Nop => {
+            Ok(1)
+        }
+    }
+}
+
+fn structure_kind_from_bits(op: u8) -> Result<MsgStructureKind, MsgDecodeError> {
+    match op & 0x0F {
+        0 => Ok(MsgStructureKind::Road),
+        1 => Ok(MsgStructureKind::Bridge),
+        2 => Ok(MsgStructureKind::Wall),
+        3 => Ok(MsgStructureKind::Tower),
+        _ => Err(MsgDecodeError::BadValue(op)),
+    }
+}
+
+fn push_pos(out: &mut Vec<u8>, pos: Pos) {
+    let (y, x): (u8, u8) = pos.into();
+    out.extend_from_slice(&[y, x]);
+}
+
+/// Helper for reading big-endian values from a byte slice
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8, MsgDecodeError> {
+        let b = *self.data.get(self.pos).ok_or(MsgDecodeError::Truncated)?;
+        self.pos += 1;
+        Ok(b)
+    }
+    fn u16(&mut self) -> Result<u16, MsgDecodeError> {
+        Ok(u16::from_be_bytes([self.u8()?, self.u8()?]))
+    }
+    fn u32(&mut self) -> Result<u32, MsgDecodeError> {
+        Ok(u32::from_be_bytes([self.u8()?, self.u8()?, self.u8()?, self.u8()?]))
+    }
+    fn pos(&mut self) -> Result<Pos, MsgDecodeError> {
+        let y = self.u8()?;
+        let x = self.u8()?;
+        Ok(Pos((y as i16 - 128) as i8, (x as i16 - 128) as i8))
+    }
+    /// String prefixed by a `u8` length; `op` is reported if it is invalid
+    fn string(&mut self, op: u8) -> Result<String, MsgDecodeError> {
+        let len = self.u8()? as usize;
+        let bytes = self.data.get(self.pos..(self.pos + len)).ok_or(MsgDecodeError::Truncated)?;
+        self.pos += len;
+        let s = std::str::from_utf8(bytes).map_err(|_| MsgDecodeError::BadValue(op))?;
+        Ok(s.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mw_common::prelude::*;
+
+    use crate::asm::Assembly;
+    use super::*;
+
+    fn roundtrip(msgs: &[Msg]) {
+        let mut bytes = vec![];
+        encode_msgs(msgs, &mut bytes).expect("encode unsuccessful");
+        let decoded: Vec<Msg> = decode_msgs(&bytes)
+            .collect::<Result<_, _>>()
+            .expect("decode unsuccessful");
+        let mut expected: Vec<Msg> = msgs.iter()
+            .filter(|msg| **msg != Msg::Nop)
+            .cloned()
+            .collect();
+        // a MoneyInfo + ResInfo pair for the same city decodes as one message
+        let mut i = 0;
+        while i + 1 < expected.len() {
+            if let (
+                Msg::CitMoney { cit, money, income },
+                Msg::CitRes { cit: cit2, res },
+            ) = (&expected[i], &expected[i + 1]) {
+                if cit == cit2 {
+                    expected[i] = Msg::CitUpdate { cit: *cit, res: *res, money: *money, income: *income };
+                    expected.remove(i + 1);
+                }
+            }
+            i += 1;
+        }
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_roundtrip_asm() {
+        let source = "
+            nop
+            SHAKE ; comment
+            DIGIT 4/0,0
+            DIGIT 2*/-1,3
+            OWNER 3 4,5 7,8 ; varargs
+            OWNER 1 -4,5
+            DIGITS 1/1,1 0/-1,-2 3/6,7
+            DIGITS 1*/2,2 0/-3,-2 3/6,7 4/1,0 5/0,0 6/1,1 2/2,2 1/3,3 5*/4,4
+            item 3,-4 None
+            ITEM 3,-3 Flash
+            ITEM 3,-2 mine
+            ITEM 3,-1 DECOY
+            Explode 3,-1
+            EXPLODE 7,8 8,9 -1,-2
+            CIT 0 200 1503 111
+            STRUCT 10,11 tower
+            STRUCT -10,31 ROAD
+            STRUCT 15,-31 Wall
+            BUILDNEW 0,1 bridge 420
+            StructHp 10,11 5
+            DECONSTRUCT 0,1
+            PLAYER 5 joined
+            PLAYER 2 ping 120
+            PLAYER 1 allchat gg wp
+            PLAYER 4 exploded 3,-3
+            BUILD 0,1 123 42
+            SMOKE 0,0
+            SMOKE -128,127
+            UNSMOKE 0,0
+            CITMONEY 3 1000 50
+            CITRES 3 40
+            CITRES 2 40
+            CITSPEND 2 100
+            CITTRADE 2 10 0
+            TILE 4,4 forest
+            FLAG 7 4,4
+            FLAG 0 4,4
+            OWNER 0 1,1 2,2 3,3
+        ";
+        let mut buffer = vec![Msg::Nop; 64];
+        let len = Msg::asm_all(source, &mut buffer)
+            .expect("asm unsuccessful");
+        roundtrip(&buffer[..len]);
+    }
+
+    #[test]
+    fn test_roundtrip_random() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1503);
+        for _ in 0..256 {
+            let len = rng.gen_range(0..64);
+            let msgs: Vec<Msg> = (0..len).map(|_| random_msg(&mut rng)).collect();
+            roundtrip(&msgs);
+        }
+    }
+
+    fn random_msg(rng: &mut impl Rng) -> Msg {
+        let pos = Pos(rng.gen(), rng.gen());
+        let kind = match rng.gen_range(0..4) {
+            0 => MsgStructureKind::Road,
+            1 => MsgStructureKind::Bridge,
+            2 => MsgStructureKind::Wall,
+            _ => MsgStructureKind::Tower,
+        };
+        match rng.gen_range(0..22) {
+            0 => Msg::Player {
+                plid: PlayerId::from(rng.gen_range(0..16)),
+                status: random_status(rng),
+            },
+            // make runs likely, to exercise grouping
+            1 | 2 => Msg::Capture { pos, digit: rng.gen_range(0..16) },
+            3 | 4 => Msg::TileOwner { pos, plid: PlayerId::from(rng.gen_range(0..3)) },
+            5 => Msg::Digit { pos, digit: rng.gen_range(0..16) },
+            6 => Msg::CitUpdate {
+                cit: rng.gen(),
+                res: rng.gen(),
+                money: rng.gen_range(0..(1 << 31)),
+                income: rng.gen(),
+            },
+            7 => Msg::RevealStructure { pos, kind },
+            8 => Msg::StructureGone { pos },
+            9 => Msg::StructureHp { pos, hp: rng.gen_range(1..16) },
+            10 => Msg::BuildNew { pos, kind, pts: rng.gen() },
+            11 => Msg::Construction { pos, current: rng.gen(), rate: rng.gen() },
+            12 => Msg::RevealItem {
+                pos,
+                item: match rng.gen_range(0..4) {
+                    0 => MsgItem::None,
+                    1 => MsgItem::Decoy,
+                    2 => MsgItem::Mine,
+                    _ => MsgItem::Flash,
+                },
+            },
+            13 => Msg::Explode { pos },
+            14 => Msg::CitMoney { cit: rng.gen(), money: rng.gen_range(0..(1 << 31)), income: rng.gen() },
+            15 => Msg::CitRes { cit: rng.gen(), res: rng.gen() },
+            16 => Msg::CitSpend { cit: rng.gen(), spent: rng.gen() },
+            17 => Msg::CitTrade { cit: rng.gen(), export: rng.gen(), import: rng.gen() },
+            18 => Msg::TileKind {
+                pos,
+                kind: *[
+                    MsgTileKind::Water, MsgTileKind::Foundation, MsgTileKind::Mountain,
+                    MsgTileKind::Forest, MsgTileKind::Destroyed, MsgTileKind::Regular,
+                    MsgTileKind::Fertile,
+                ].choose(rng).unwrap(),
+            },
+            19 => {
+                let plid = PlayerId::from(rng.gen_range(0..16));
+                Msg::Flag { pos, plid, question: plid != PlayerId::Neutral && rng.gen() }
+            }
+            20 => Msg::Unsmoke { pos },
+            _ => if rng.gen_bool(0.5) { Msg::Smoke { pos } } else { Msg::Tremor },
+        }
+    }
+
+    fn random_status(rng: &mut impl Rng) -> MsgPlayer {
+        match rng.gen_range(0..19) {
+            0 => MsgPlayer::Joined,
+            1 => MsgPlayer::Ping { millis: rng.gen() },
+            2 => MsgPlayer::Stunned { millis: rng.gen() },
+            3 => MsgPlayer::Unstunned,
+            4 => MsgPlayer::Blinded { millis: rng.gen() },
+            5 => MsgPlayer::Unblinded,
+            6 => MsgPlayer::Protected,
+            7 => MsgPlayer::Unprotected,
+            8 => MsgPlayer::Eliminated,
+            9 => MsgPlayer::Surrendered,
+            10 => MsgPlayer::Disconnected,
+            11 => MsgPlayer::Kicked,
+            12 => MsgPlayer::FriendlyChat("hi".into()),
+            13 => MsgPlayer::AllChat("ünïcödé".into()),
+            14 => MsgPlayer::LivesRemain { lives: rng.gen() },
+            15 => MsgPlayer::MatchTimeRemain { secs: rng.gen() },
+            16 => MsgPlayer::Hint { pos: Pos(rng.gen(), rng.gen()), mine: rng.gen() },
+            17 => MsgPlayer::GameResult {
+                rank: rng.gen(),
+                tiles: rng.gen(),
+                mines_hit: rng.gen(),
+                lives: rng.gen(),
+                eliminated_secs: rng.gen_bool(0.5).then(|| rng.gen_range(0..u16::MAX)),
+            },
+            _ => MsgPlayer::Exploded { pos: Pos(rng.gen(), rng.gen()) },
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let cases: &[(&[Msg], &[u8])] = &[
+            (&[Msg::Player { plid: 5.into(), status: MsgPlayer::Eliminated }], &[0x00, 0x05, 0x08]),
+            (
+                &[Msg::Player { plid: 1.into(), status: MsgPlayer::Stunned { millis: 0x0102 } }],
+                &[0x00, 0x01, 0x02, 0x01, 0x02],
+            ),
+            (
+                &[Msg::Player { plid: 2.into(), status: MsgPlayer::FriendlyChat("gg".into()) }],
+                &[0x00, 0x02, 0x0E, 0x02, b'g', b'g'],
+            ),
+            (
+                &[Msg::Player { plid: 3.into(), status: MsgPlayer::Exploded { pos: Pos(1, -1) } }],
+                &[0x00, 0x03, 0x12, 0x81, 0x7F],
+            ),
+            (
+                &[Msg::Player { plid: 1.into(), status: MsgPlayer::Hint { pos: Pos(2, 0), mine: true } }],
+                &[0x00, 0x01, 0x13, 0x01, 0x82, 0x80],
+            ),
+            (
+                &[Msg::Player {
+                    plid: 2.into(),
+                    status: MsgPlayer::GameResult { rank: 1, tiles: 0x0102, mines_hit: 3, lives: 0, eliminated_secs: Some(0x0405) },
+                }],
+                &[0x00, 0x02, 0x14, 0x01, 0x01, 0x02, 0x03, 0x00, 0x04, 0x05],
+            ),
+            (
+                &[Msg::Player {
+                    plid: 2.into(),
+                    status: MsgPlayer::GameResult { rank: 2, tiles: 7, mines_hit: 0, lives: 1, eliminated_secs: None },
+                }],
+                &[0x00, 0x02, 0x14, 0x02, 0x00, 0x07, 0x00, 0x01, 0xFF, 0xFF],
+            ),
+            (&[Msg::Unsmoke { pos: Pos(0, -1) }], &[0x03, 0x80, 0x7F]),
+            (
+                &[Msg::CitMoney { cit: 2, money: 0x03040506, income: 0x0708 }],
+                &[0x04, 0x02, 0x83, 0x04, 0x05, 0x06, 0x07, 0x08],
+            ),
+            (&[Msg::CitSpend { cit: 2, spent: 0x0102 }], &[0x05, 0x02, 0x01, 0x02]),
+            (&[Msg::CitRes { cit: 2, res: 0x0102 }], &[0x06, 0x02, 0x01, 0x02]),
+            (&[Msg::CitTrade { cit: 2, export: 3, import: 4 }], &[0x07, 0x02, 0x03, 0x04]),
+            (&[Msg::Flag { pos: Pos(0, 0), plid: 3.into(), question: false }], &[0x08, 0x03, 0x80, 0x80]),
+            (&[Msg::Flag { pos: Pos(0, 0), plid: 3.into(), question: true }], &[0x08, 0x13, 0x80, 0x80]),
+            (
+                &[
+                    Msg::TileOwner { pos: Pos(0, 0), plid: PlayerId::Neutral },
+                    Msg::TileOwner { pos: Pos(1, 1), plid: PlayerId::Neutral },
+                ],
+                &[0x11, 0x80, 0x80, 0x81, 0x81],
+            ),
+            (&[Msg::TileKind { pos: Pos(0, 0), kind: MsgTileKind::Fertile }], &[0x7F, 0x80, 0x80]),
+            (&[Msg::Tremor], &[0x01]),
+            (&[Msg::Smoke { pos: Pos(0, -1) }], &[0x02, 0x80, 0x7F]),
+            (
+                &[Msg::CitUpdate { cit: 2, res: 0x0102, money: 0x03040506, income: 0x0708 }],
+                &[0x04, 0x02, 0x83, 0x04, 0x05, 0x06, 0x07, 0x08, 0x06, 0x02, 0x01, 0x02],
+            ),
+            (&[Msg::StructureGone { pos: Pos(1, 2) }], &[0x20, 0x81, 0x82]),
+            (&[Msg::StructureHp { pos: Pos(1, 2) , hp: 9 }], &[0x29, 0x81, 0x82]),
+            (
+                &[Msg::Explode { pos: Pos(0, 0) }, Msg::Explode { pos: Pos(1, 1) }],
+                &[0x31, 0x80, 0x80, 0x81, 0x81],
+            ),
+            (
+                &[Msg::BuildNew { pos: Pos(0, 0), kind: MsgStructureKind::Wall, pts: 420 }],
+                &[0x42, 0x80, 0x80, 0x01, 0xA4],
+            ),
+            (
+                &[Msg::Construction { pos: Pos(0, 0), current: 1, rate: 2 }],
+                &[0x4F, 0x80, 0x80, 0x00, 0x01, 0x00, 0x02],
+            ),
+            (
+                &[Msg::RevealStructure { pos: Pos(0, 0), kind: MsgStructureKind::Tower }],
+                &[0x53, 0x80, 0x80],
+            ),
+            (&[Msg::Digit { pos: Pos(0, 0), digit: 0x08 | 3 }], &[0x6B, 0x80, 0x80]),
+            (
+                &[Msg::RevealItem { pos: Pos(0, 0), item: MsgItem::Mine }],
+                &[0x72, 0x80, 0x80],
+            ),
+            (
+                &[
+                    Msg::Capture { pos: Pos(0, 0), digit: 0x08 | 1 },
+                    Msg::Capture { pos: Pos(0, 1), digit: 2 },
+                    Msg::Capture { pos: Pos(0, 2), digit: 3 },
+                ],
+                &[0x82, 0x80, 0x80, 0x80, 0x81, 0x80, 0x82, 0x92, 0x30],
+            ),
+            (
+                &[
+                    Msg::TileOwner { pos: Pos(0, 0), plid: 1.into() },
+                    Msg::TileOwner { pos: Pos(0, 1), plid: 1.into() },
+                    Msg::TileOwner { pos: Pos(0, 2), plid: 2.into() },
+                ],
+                &[0x89, 0x80, 0x80, 0x80, 0x81, 0x90, 0x80, 0x82],
+            ),
+            (&[Msg::Nop], &[]),
+        ];
+        for (msgs, bytes) in cases {
+            let mut out = vec![];
+            encode_msgs(msgs, &mut out).expect("encode unsuccessful");
+            assert_eq!(&out, bytes, "{:?}", msgs);
+        }
+    }
+
+    #[test]
+    fn test_grouping_limits() {
+        let msgs: Vec<Msg> = (0..20).map(|i| Msg::Explode { pos: Pos(i, 0) })
+            .chain((0..10).map(|i| Msg::Capture { pos: Pos(i, 0), digit: 1 }))
+            .chain((0..10).map(|i| Msg::TileOwner { pos: Pos(i, 0), plid: 7.into() }))
+            .collect();
+        let mut out = vec![];
+        encode_msgs(&msgs, &mut out).unwrap();
+        // (opcode, encoded length of the whole message)
+        let mut i = 0;
+        for (op, len) in [
+            (0x3F, 1 + 16 * 2),
+            (0x33, 1 + 4 * 2),
+            (0x87, 1 + 8 * 2 + 4),
+            (0x81, 1 + 2 * 2 + 1),
+            (0xBF, 1 + 8 * 2),
+            (0xB9, 1 + 2 * 2),
+        ] {
+            assert_eq!(out[i], op);
+            i += len;
+        }
+        assert_eq!(i, out.len());
+        roundtrip(&msgs);
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        let mut out = vec![];
+        assert!(matches!(
+            encode_msgs(&[Msg::TileOwner { pos: Pos(0, 0), plid: PlayerId::from(16) }], &mut out),
+            Err(MsgEncodeError::BadPlid(16))
+        ));
+        assert!(matches!(
+            encode_msgs(&[Msg::StructureHp { pos: Pos(0, 0), hp: 0 }], &mut out),
+            Err(MsgEncodeError::BadHp(0))
+        ));
+        assert!(matches!(
+            encode_msgs(&[Msg::Digit { pos: Pos(0, 0), digit: 16 }], &mut out),
+            Err(MsgEncodeError::BadDigit(16))
+        ));
+        assert!(matches!(
+            encode_msgs(&[Msg::Player { plid: 1.into(), status: MsgPlayer::AllChat("a".repeat(256)) }], &mut out),
+            Err(MsgEncodeError::ChatTooLong(256))
+        ));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut dec = decode_msgs(&[0x01, 0x20, 0x80]);
+        assert!(matches!(dec.next(), Some(Ok(Msg::Tremor))));
+        assert_eq!(dec.offset(), 1);
+        assert!(matches!(dec.next(), Some(Err(MsgDecodeError::Truncated))));
+        assert_eq!(dec.offset(), 1);
+        assert!(dec.next().is_none());
+
+        let dec = decode_msgs(&[0x09]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::Reserved(0x09)))));
+        let dec = decode_msgs(&[0x00, 0x01, 0x15]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::BadValue(0x00)))));
+        let dec = decode_msgs(&[0x00, 0x01, 0x13, 0x02, 0x80, 0x80]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::BadValue(0x00)))));
+        let dec = decode_msgs(&[0x7D, 0x80, 0x80]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::BadValue(0x7D)))));
+        let dec = decode_msgs(&[0x08, 0x20, 0x80, 0x80]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::BadValue(0x08)))));
+        let dec = decode_msgs(&[0x5F, 0x80, 0x80]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::Reserved(0x5F)))));
+        let dec = decode_msgs(&[0x44, 0x80, 0x80, 0x00, 0x00]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::BadValue(0x44)))));
+        let dec = decode_msgs(&[0x74, 0x80, 0x80]);
+        assert!(matches!(dec.last(), Some(Err(MsgDecodeError::BadValue(0x74)))));
+    }
+}
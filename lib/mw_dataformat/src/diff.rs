@@ -0,0 +1,352 @@
+//! Structural comparison of frame streams
+//!
+//! Useful for seeing what actually changed in the output of the game
+//! or the optimizer.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use mw_common::grid::Pos;
+use mw_common::plid::PlayerId;
+use mw_common::prelude::HashMap;
+
+use crate::frame::Frame;
+use crate::msg::Msg;
+use crate::state::StreamState;
+
+/// Max size of the table for computing the longest common subsequence
+///
+/// If both sequences are longer than this, we don't bother with finding
+/// the optimal alignment, and compare them as sets instead.
+const MAX_LCS_TABLE: usize = 1 << 22;
+
+/// What to compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffMode {
+    /// Compare the messages of each view at each point in time
+    Raw,
+    /// Compare the state of each view after each point in time
+    ///
+    /// Messages that are reordered, merged, or redundant do not count as
+    /// differences, as long as the result is the same. Transient things
+    /// (like explosions) are ignored. Every divergence is reported only once,
+    /// when it first appears.
+    Semantic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Only in the old stream
+    Removed(Msg),
+    /// Only in the new stream
+    Added(Msg),
+    /// Different data about the same thing
+    Changed(Msg, Msg),
+}
+
+/// A difference between two streams
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub time: Duration,
+    pub plid: PlayerId,
+    pub change: Change,
+}
+
+/// What a message is about
+///
+/// Messages with the same target are reported as `Change::Changed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Target {
+    Player(PlayerId, u8),
+    Tile(u8, Pos),
+    Cit(u8, u8),
+    Other(u8),
+}
+
+fn target(msg: &Msg) -> Target {
+    match *msg {
+        Msg::Player { plid, ref status } => Target::Player(plid, status.kind()),
+        Msg::Capture { pos, .. } | Msg::TileOwner { pos, .. } => Target::Tile(0, pos),
+        Msg::Digit { pos, .. } => Target::Tile(1, pos),
+        Msg::CitUpdate { cit, .. } | Msg::CitMoney { cit, .. } => Target::Cit(0, cit),
+        Msg::CitRes { cit, .. } => Target::Cit(1, cit),
+        Msg::CitSpend { cit, .. } => Target::Cit(2, cit),
+        Msg::CitTrade { cit, .. } => Target::Cit(3, cit),
+        Msg::RevealStructure { pos, .. } => Target::Tile(2, pos),
+        Msg::StructureGone { pos } => Target::Tile(3, pos),
+        Msg::StructureHp { pos, .. } => Target::Tile(4, pos),
+        Msg::BuildNew { pos, .. } => Target::Tile(5, pos),
+        Msg::Construction { pos, .. } => Target::Tile(6, pos),
+        Msg::TileKind { pos, .. } => Target::Tile(7, pos),
+        Msg::Flag { pos, .. } => Target::Tile(8, pos),
+        Msg::RevealItem { pos, .. } => Target::Tile(9, pos),
+        Msg::Explode { pos } => Target::Tile(10, pos),
+        Msg::Smoke { pos } | Msg::Unsmoke { pos } => Target::Tile(11, pos),
+        Msg::Tremor => Target::Other(0),
+        Msg::Nop => Target::Other(1),
+    }
+}
+
+/// Compare two streams of frames
+///
+/// The frames are aligned by time and plid.
+pub fn diff_frames(
+    old: impl IntoIterator<Item = Frame>,
+    new: impl IntoIterator<Item = Frame>,
+    mode: DiffMode,
+) -> Vec<Difference> {
+    match mode {
+        DiffMode::Raw => diff_raw(old, new),
+        DiffMode::Semantic => diff_semantic(old, new),
+    }
+}
+
+fn diff_raw(old: impl IntoIterator<Item = Frame>, new: impl IntoIterator<Item = Frame>) -> Vec<Difference> {
+    fn collect(frames: impl IntoIterator<Item = Frame>) -> BTreeMap<(Duration, PlayerId), Vec<Msg>> {
+        let mut views: BTreeMap<_, Vec<Msg>> = BTreeMap::new();
+        for frame in frames {
+            for (plid, msgs) in frame.views {
+                views.entry((frame.time, plid)).or_default().extend(msgs);
+            }
+        }
+        views
+    }
+    let old = collect(old);
+    let new = collect(new);
+    let keys: BTreeSet<_> = old.keys().chain(new.keys()).copied().collect();
+
+    let mut out = vec![];
+    for (time, plid) in keys {
+        let a = old.get(&(time, plid)).map(|v| &v[..]).unwrap_or(&[]);
+        let b = new.get(&(time, plid)).map(|v| &v[..]).unwrap_or(&[]);
+        out.extend(diff_msgs(a, b).into_iter().map(|change| Difference { time, plid, change }));
+    }
+    out
+}
+
+/// Compare two sequences of messages
+fn diff_msgs(a: &[Msg], b: &[Msg]) -> Vec<Change> {
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[..(a.len() - suffix)], &b[..(b.len() - suffix)]);
+
+    // (removed?, msg)
+    let mut ops: Vec<(bool, &Msg)> = vec![];
+    if (a.len() + 1) * (b.len() + 1) <= MAX_LCS_TABLE {
+        // lcs[i][j]: length of the LCS of a[i..] and b[j..]
+        let w = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * w];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * w + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * w + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * w + j].max(lcs[i * w + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                i += 1;
+                j += 1;
+            } else if j == b.len() || (i < a.len() && lcs[(i + 1) * w + j] >= lcs[i * w + j + 1]) {
+                ops.push((true, &a[i]));
+                i += 1;
+            } else {
+                ops.push((false, &b[j]));
+                j += 1;
+            }
+        }
+    } else {
+        let mut count: HashMap<&Msg, isize> = HashMap::new();
+        for msg in a {
+            *count.entry(msg).or_default() += 1;
+        }
+        for msg in b {
+            *count.entry(msg).or_default() -= 1;
+        }
+        for msg in a {
+            let n = count.get_mut(msg).unwrap();
+            if *n > 0 {
+                *n -= 1;
+                ops.push((true, msg));
+            }
+        }
+        for msg in b {
+            let n = count.get_mut(msg).unwrap();
+            if *n < 0 {
+                *n += 1;
+                ops.push((false, msg));
+            }
+        }
+    }
+
+    // pair up removals and additions about the same thing
+    let mut paired = vec![None; ops.len()];
+    for i in 0..ops.len() {
+        if !ops[i].0 || paired[i].is_some() {
+            continue;
+        }
+        let t = target(ops[i].1);
+        let found = (0..ops.len())
+            .find(|&j| !ops[j].0 && paired[j].is_none() && target(ops[j].1) == t);
+        if let Some(j) = found {
+            paired[i] = Some(j);
+            paired[j] = Some(i);
+        }
+    }
+    let mut changes = vec![];
+    for (i, (removed, msg)) in ops.iter().enumerate() {
+        match (removed, paired[i]) {
+            (true, Some(j)) => changes.push(Change::Changed((*msg).clone(), ops[j].1.clone())),
+            (true, None) => changes.push(Change::Removed((*msg).clone())),
+            (false, None) => changes.push(Change::Added((*msg).clone())),
+            (false, Some(_)) => {}
+        }
+    }
+    changes
+}
+
+fn diff_semantic(old: impl IntoIterator<Item = Frame>, new: impl IntoIterator<Item = Frame>) -> Vec<Difference> {
+    fn by_time(frames: impl IntoIterator<Item = Frame>) -> BTreeMap<Duration, Vec<Frame>> {
+        let mut r: BTreeMap<_, Vec<Frame>> = BTreeMap::new();
+        for frame in frames {
+            r.entry(frame.time).or_default().push(frame);
+        }
+        r
+    }
+    fn state_msgs(state: &StreamState, plid: PlayerId) -> BTreeMap<Target, Msg> {
+        state.view(plid)
+            .map(|view| view.msgs(plid).into_iter().map(|msg| (target(&msg), msg)).collect())
+            .unwrap_or_default()
+    }
+    let old = by_time(old);
+    let new = by_time(new);
+    let times: BTreeSet<Duration> = old.keys().chain(new.keys()).copied().collect();
+
+    let mut state_old = StreamState::default();
+    let mut state_new = StreamState::default();
+    let mut reported: HashMap<(PlayerId, Target), Change> = HashMap::new();
+    let mut out = vec![];
+    for time in times {
+        let mut touched = BTreeSet::new();
+        for frame in old.get(&time).into_iter().flatten() {
+            touched.extend(frame.views.iter().map(|(plid, _)| *plid));
+            state_old.apply(frame);
+        }
+        for frame in new.get(&time).into_iter().flatten() {
+            touched.extend(frame.views.iter().map(|(plid, _)| *plid));
+            state_new.apply(frame);
+        }
+        for plid in touched {
+            let mut a = state_msgs(&state_old, plid);
+            let mut b = state_msgs(&state_new, plid);
+            let targets: BTreeSet<Target> = a.keys().chain(b.keys()).copied().collect();
+            for t in targets {
+                let change = match (a.remove(&t), b.remove(&t)) {
+                    (Some(x), Some(y)) if x == y => None,
+                    (Some(x), Some(y)) => Some(Change::Changed(x, y)),
+                    (Some(x), None) => Some(Change::Removed(x)),
+                    (None, Some(y)) => Some(Change::Added(y)),
+                    (None, None) => None,
+                };
+                let Some(change) = change else {
+                    reported.remove(&(plid, t));
+                    continue;
+                };
+                if reported.get(&(plid, t)) != Some(&change) {
+                    reported.insert((plid, t), change.clone());
+                    out.push(Difference { time, plid, change });
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use mw_common::plid::Plids;
+
+    use crate::opt::{optimize_mwseq_lossy, OptimizerState};
+
+    use super::*;
+
+    fn frame(ms: u64, plid: u8, msgs: Vec<Msg>) -> Frame {
+        let plid = PlayerId::from(plid);
+        Frame {
+            time: Duration::from_millis(ms),
+            plids: Plids::from(plid),
+            views: vec![(plid, msgs)],
+        }
+    }
+
+    #[test]
+    fn raw() {
+        let old = vec![
+            frame(1, 1, vec![Msg::Tremor, Msg::Digit { pos: Pos(0, 0), digit: 1 }, Msg::Smoke { pos: Pos(1, 1) }]),
+            frame(2, 2, vec![Msg::Tremor]),
+        ];
+        let new = vec![
+            frame(1, 1, vec![Msg::Tremor, Msg::Digit { pos: Pos(0, 0), digit: 2 }, Msg::Explode { pos: Pos(2, 2) }]),
+            frame(3, 2, vec![Msg::Tremor]),
+        ];
+        let changes: Vec<_> = diff_frames(old, new, DiffMode::Raw).into_iter()
+            .map(|d| (d.time.as_millis(), u8::from(d.plid), d.change))
+            .collect();
+        assert_eq!(changes, vec![
+            (1, 1, Change::Changed(Msg::Digit { pos: Pos(0, 0), digit: 1 }, Msg::Digit { pos: Pos(0, 0), digit: 2 })),
+            (1, 1, Change::Removed(Msg::Smoke { pos: Pos(1, 1) })),
+            (1, 1, Change::Added(Msg::Explode { pos: Pos(2, 2) })),
+            (2, 2, Change::Removed(Msg::Tremor)),
+            (3, 2, Change::Added(Msg::Tremor)),
+        ]);
+    }
+
+    #[test]
+    fn semantic_ignores_optimizer() {
+        let me = PlayerId::from(1);
+        let seqs = vec![
+            vec![
+                Msg::TileOwner { pos: Pos(0, 0), plid: me },
+                Msg::Digit { pos: Pos(0, 0), digit: 2 },
+                Msg::Digit { pos: Pos(1, 0), digit: 1 },
+                Msg::Digit { pos: Pos(1, 0), digit: 3 },
+                Msg::CitUpdate { cit: 0, res: 1, money: 10, income: 1 },
+            ],
+            vec![
+                Msg::Digit { pos: Pos(0, 0), digit: 2 },
+                Msg::CitUpdate { cit: 0, res: 2, money: 10, income: 1 },
+            ],
+        ];
+        let mut state = OptimizerState::default();
+        let mut old = vec![];
+        let mut new = vec![];
+        for (i, seq) in seqs.into_iter().enumerate() {
+            let mut opt = seq.clone();
+            optimize_mwseq_lossy(&mut state, me, &mut opt);
+            old.push(frame(i as u64, 1, seq));
+            new.push(frame(i as u64, 1, opt));
+        }
+        assert!(!diff_frames(old.clone(), new.clone(), DiffMode::Raw).is_empty());
+        assert!(diff_frames(old.clone(), new, DiffMode::Semantic).is_empty());
+
+        // a real difference is reported only once
+        let new = vec![
+            frame(0, 1, vec![Msg::Capture { pos: Pos(0, 0), digit: 3 }]),
+            frame(1, 1, vec![Msg::Tremor]),
+        ];
+        let diffs = diff_frames(old, new, DiffMode::Semantic);
+        assert_eq!(diffs[0], Difference {
+            time: Duration::ZERO,
+            plid: me,
+            change: Change::Changed(
+                Msg::Capture { pos: Pos(0, 0), digit: 2 },
+                Msg::Capture { pos: Pos(0, 0), digit: 3 },
+            ),
+        });
+        assert_eq!(diffs.iter().filter(|d| matches!(d.change, Change::Changed(..))).count(), 1);
+    }
+}
@@ -0,0 +1,362 @@
+//! Conversion between game events and protocol messages
+//!
+//! Every `MwEv` has exactly one `Msg` representation, so the output of a game
+//! can be encoded without loss. The exception is digits above 7 (possible on
+//! `Sq` maps), which the protocol cannot represent and are rejected with
+//! `MsgEncodeError::BadDigit`. In the other direction, some messages expand
+//! into multiple events (`Capture`, `CitUpdate`), and some have no event
+//! equivalent at all (`Nop`, ping info).
+
+use mw_common::game::event::{BackgroundEv, CitEv, GameEvent, MapEv, MwEv, PlayerEv};
+use mw_common::game::{ItemKind, StructureKind, TileKind};
+use mw_common::plid::PlayerId;
+
+use crate::codec::MsgEncodeError;
+use crate::msg::{Msg, MsgItem, MsgPlayer, MsgStructureKind, MsgTileKind};
+
+impl TryFrom<MwEv> for Msg {
+    type Error = MsgEncodeError;
+
+    fn try_from(ev: MwEv) -> Result<Msg, MsgEncodeError> {
+        Ok(match ev {
+            MwEv::Player { plid, ev } => Msg::Player {
+                plid,
+                status: ev.into(),
+            },
+            MwEv::Map { pos, ev } => match ev {
+                MapEv::Tile { kind } => Msg::TileKind { pos, kind: kind.into() },
+                MapEv::Owner { plid } => Msg::TileOwner { pos, plid },
+                MapEv::Digit { digit, asterisk } => {
+                    if digit > 0x07 {
+                        return Err(MsgEncodeError::BadDigit(digit));
+                    }
+                    Msg::Digit {
+                        pos,
+                        digit: digit | if asterisk { 0x08 } else { 0 },
+                    }
+                }
+                MapEv::Item { kind } => Msg::RevealItem { pos, item: kind.into() },
+                MapEv::Flag { plid, question } => Msg::Flag { pos, plid, question },
+                MapEv::Explode => Msg::Explode { pos },
+                MapEv::Smoke { state: true } => Msg::Smoke { pos },
+                MapEv::Smoke { state: false } => Msg::Unsmoke { pos },
+                MapEv::StructureBegin { kind, pts } => Msg::BuildNew { pos, kind: kind.into(), pts },
+                MapEv::StructureReveal { kind } => Msg::RevealStructure { pos, kind: kind.into() },
+                MapEv::StructureHp { hp } => Msg::StructureHp { pos, hp },
+                MapEv::StructureProgress { current, rate } => Msg::Construction { pos, current, rate },
+                MapEv::StructureGone => Msg::StructureGone { pos },
+            },
+            MwEv::Cit { cit, ev } => match ev {
+                CitEv::Money { current, income } => Msg::CitMoney { cit, money: current, income },
+                CitEv::Spent { amount } => Msg::CitSpend { cit, spent: amount },
+                CitEv::ResAvailable { res } => Msg::CitRes { cit, res },
+                CitEv::TradePolicy { import, export } => Msg::CitTrade { cit, export, import },
+            },
+            MwEv::Background(BackgroundEv::Tremor) => Msg::Tremor,
+        })
+    }
+}
+
+/// The view (`plid` field) is discarded; it determines which stream
+/// the message should be sent to.
+impl TryFrom<GameEvent> for Msg {
+    type Error = MsgEncodeError;
+
+    fn try_from(ev: GameEvent) -> Result<Msg, MsgEncodeError> {
+        ev.ev.try_into()
+    }
+}
+
+impl Msg {
+    /// Convert into the equivalent game events, appending them to `out`
+    ///
+    /// `me` is the plid of the view that the message was addressed to. It is
+    /// the new owner of tiles in `Capture` messages. For the spectator view,
+    /// pass the plid of the player who made the capture instead.
+    pub fn to_events(&self, me: PlayerId, out: &mut Vec<MwEv>) {
+        let map = |pos, ev| MwEv::Map { pos, ev };
+        match *self {
+            Msg::Player { plid, ref status } => {
+                let ev = match *status {
+                    MsgPlayer::Joined => PlayerEv::Joined,
+                    // network diagnostics, not a game event
+                    MsgPlayer::Ping { .. } => return,
+                    MsgPlayer::Stunned { millis } => PlayerEv::Timeout { millis },
+                    MsgPlayer::Unstunned => PlayerEv::TimeoutFinished,
+                    MsgPlayer::Blinded { millis } => PlayerEv::Flash { millis },
+                    MsgPlayer::Unblinded => PlayerEv::FlashFinished,
+                    MsgPlayer::Protected => PlayerEv::Protected,
+                    MsgPlayer::Unprotected => PlayerEv::Unprotected,
+                    MsgPlayer::Eliminated => PlayerEv::Eliminated,
+                    MsgPlayer::Surrendered => PlayerEv::Surrendered,
+                    MsgPlayer::Disconnected => PlayerEv::Disconnected,
+                    MsgPlayer::Kicked => PlayerEv::Kicked,
+                    MsgPlayer::FriendlyChat(ref text) => PlayerEv::FriendlyChat(text.clone()),
+                    MsgPlayer::AllChat(ref text) => PlayerEv::AllChat(text.clone()),
+                    MsgPlayer::LivesRemain { lives } => PlayerEv::LivesRemain { lives },
+                    MsgPlayer::MatchTimeRemain { secs } => PlayerEv::MatchTimeRemain { secs },
+                    MsgPlayer::Exploded { pos } => PlayerEv::Exploded { pos },
+                    MsgPlayer::Hint { pos, mine } => PlayerEv::Hint { pos, mine },
+                    MsgPlayer::GameResult { rank, tiles, mines_hit, lives, eliminated_secs } => {
+                        PlayerEv::GameResult { rank, tiles, mines_hit, lives, eliminated_secs }
+                    }
+                };
+                out.push(MwEv::Player { plid, ev });
+            }
+            Msg::Capture { pos, digit } => {
+                out.push(map(pos, MapEv::Owner { plid: me }));
+                out.push(map(pos, digit_ev(digit)));
+            }
+            Msg::TileOwner { pos, plid } => out.push(map(pos, MapEv::Owner { plid })),
+            Msg::Digit { pos, digit } => out.push(map(pos, digit_ev(digit))),
+            Msg::CitUpdate { cit, res, money, income } => {
+                out.push(MwEv::Cit { cit, ev: CitEv::Money { current: money, income } });
+                out.push(MwEv::Cit { cit, ev: CitEv::ResAvailable { res } });
+            }
+            Msg::CitMoney { cit, money, income } => {
+                out.push(MwEv::Cit { cit, ev: CitEv::Money { current: money, income } });
+            }
+            Msg::CitRes { cit, res } => out.push(MwEv::Cit { cit, ev: CitEv::ResAvailable { res } }),
+            Msg::CitSpend { cit, spent } => out.push(MwEv::Cit { cit, ev: CitEv::Spent { amount: spent } }),
+            Msg::CitTrade { cit, export, import } => {
+                out.push(MwEv::Cit { cit, ev: CitEv::TradePolicy { import, export } });
+            }
+            Msg::RevealStructure { pos, kind } => {
+                out.push(map(pos, MapEv::StructureReveal { kind: kind.into() }));
+            }
+            Msg::StructureGone { pos } => out.push(map(pos, MapEv::StructureGone)),
+            Msg::StructureHp { pos, hp } => out.push(map(pos, MapEv::StructureHp { hp })),
+            Msg::BuildNew { pos, kind, pts } => {
+                out.push(map(pos, MapEv::StructureBegin { kind: kind.into(), pts }));
+            }
+            Msg::Construction { pos, current, rate } => {
+                out.push(map(pos, MapEv::StructureProgress { current, rate }));
+            }
+            Msg::TileKind { pos, kind } => out.push(map(pos, MapEv::Tile { kind: kind.into() })),
+            Msg::Flag { pos, plid, question } => out.push(map(pos, MapEv::Flag { plid, question })),
+            Msg::RevealItem { pos, item } => out.push(map(pos, MapEv::Item { kind: item.into() })),
+            Msg::Explode { pos } => out.push(map(pos, MapEv::Explode)),
+            Msg::Smoke { pos } => out.push(map(pos, MapEv::Smoke { state: true })),
+            Msg::Unsmoke { pos } => out.push(map(pos, MapEv::Smoke { state: false })),
+            Msg::Tremor => out.push(MwEv::Background(BackgroundEv::Tremor)),
+            Msg::Nop => {}
+        }
+    }
+
+    /// Like `to_events`, but produces `GameEvent`s for the view `me`
+    pub fn to_game_events(&self, me: PlayerId, out: &mut Vec<GameEvent>) {
+        let mut evs = vec![];
+        self.to_events(me, &mut evs);
+        out.extend(evs.into_iter().map(|ev| GameEvent { plid: me, ev }));
+    }
+}
+
+fn digit_ev(digit: u8) -> MapEv {
+    MapEv::Digit {
+        digit: digit & 0x07,
+        asterisk: digit & 0x08 != 0,
+    }
+}
+
+impl From<PlayerEv> for MsgPlayer {
+    fn from(ev: PlayerEv) -> MsgPlayer {
+        match ev {
+            PlayerEv::Joined => MsgPlayer::Joined,
+            PlayerEv::Disconnected => MsgPlayer::Disconnected,
+            PlayerEv::Eliminated => MsgPlayer::Eliminated,
+            PlayerEv::Surrendered => MsgPlayer::Surrendered,
+            PlayerEv::Protected => MsgPlayer::Protected,
+            PlayerEv::Unprotected => MsgPlayer::Unprotected,
+            PlayerEv::Kicked => MsgPlayer::Kicked,
+            PlayerEv::Exploded { pos } => MsgPlayer::Exploded { pos },
+            PlayerEv::Timeout { millis } => MsgPlayer::Stunned { millis },
+            PlayerEv::TimeoutFinished => MsgPlayer::Unstunned,
+            PlayerEv::Flash { millis } => MsgPlayer::Blinded { millis },
+            PlayerEv::FlashFinished => MsgPlayer::Unblinded,
+            PlayerEv::LivesRemain { lives } => MsgPlayer::LivesRemain { lives },
+            PlayerEv::MatchTimeRemain { secs } => MsgPlayer::MatchTimeRemain { secs },
+            PlayerEv::Hint { pos, mine } => MsgPlayer::Hint { pos, mine },
+            PlayerEv::GameResult { rank, tiles, mines_hit, lives, eliminated_secs } => {
+                MsgPlayer::GameResult { rank, tiles, mines_hit, lives, eliminated_secs }
+            }
+            PlayerEv::FriendlyChat(text) => MsgPlayer::FriendlyChat(text),
+            PlayerEv::AllChat(text) => MsgPlayer::AllChat(text),
+        }
+    }
+}
+
+impl From<TileKind> for MsgTileKind {
+    fn from(kind: TileKind) -> MsgTileKind {
+        match kind {
+            TileKind::Water => MsgTileKind::Water,
+            TileKind::Foundation => MsgTileKind::Foundation,
+            TileKind::Regular => MsgTileKind::Regular,
+            TileKind::Fertile => MsgTileKind::Fertile,
+            TileKind::Forest => MsgTileKind::Forest,
+            TileKind::Mountain => MsgTileKind::Mountain,
+            TileKind::Destroyed => MsgTileKind::Destroyed,
+        }
+    }
+}
+
+impl From<MsgTileKind> for TileKind {
+    fn from(kind: MsgTileKind) -> TileKind {
+        match kind {
+            MsgTileKind::Water => TileKind::Water,
+            MsgTileKind::Foundation => TileKind::Foundation,
+            MsgTileKind::Regular => TileKind::Regular,
+            MsgTileKind::Fertile => TileKind::Fertile,
+            MsgTileKind::Forest => TileKind::Forest,
+            MsgTileKind::Mountain => TileKind::Mountain,
+            MsgTileKind::Destroyed => TileKind::Destroyed,
+        }
+    }
+}
+
+impl From<ItemKind> for MsgItem {
+    fn from(kind: ItemKind) -> MsgItem {
+        match kind {
+            ItemKind::Safe => MsgItem::None,
+            ItemKind::Mine => MsgItem::Mine,
+            ItemKind::Decoy => MsgItem::Decoy,
+            ItemKind::Flashbang => MsgItem::Flash,
+        }
+    }
+}
+
+impl From<MsgItem> for ItemKind {
+    fn from(item: MsgItem) -> ItemKind {
+        match item {
+            MsgItem::None => ItemKind::Safe,
+            MsgItem::Mine => ItemKind::Mine,
+            MsgItem::Decoy => ItemKind::Decoy,
+            MsgItem::Flash => ItemKind::Flashbang,
+        }
+    }
+}
+
+impl From<StructureKind> for MsgStructureKind {
+    fn from(kind: StructureKind) -> MsgStructureKind {
+        match kind {
+            StructureKind::Road => MsgStructureKind::Road,
+            StructureKind::Barricade => MsgStructureKind::Wall,
+            StructureKind::WatchTower => MsgStructureKind::Tower,
+            StructureKind::Bridge => MsgStructureKind::Bridge,
+        }
+    }
+}
+
+impl From<MsgStructureKind> for StructureKind {
+    fn from(kind: MsgStructureKind) -> StructureKind {
+        match kind {
+            MsgStructureKind::Road => StructureKind::Road,
+            MsgStructureKind::Wall => StructureKind::Barricade,
+            MsgStructureKind::Tower => StructureKind::WatchTower,
+            MsgStructureKind::Bridge => StructureKind::Bridge,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mw_common::grid::Pos;
+
+    use crate::codec::{decode_msgs, encode_msgs};
+
+    use super::*;
+
+    fn all_events() -> Vec<MwEv> {
+        let pos = Pos(3, -2);
+        let plid = PlayerId::from(2);
+        let player = |ev| MwEv::Player { plid, ev };
+        let map = |ev| MwEv::Map { pos, ev };
+        let cit = |ev| MwEv::Cit { cit: 1, ev };
+        vec![
+            player(PlayerEv::Joined),
+            player(PlayerEv::Disconnected),
+            player(PlayerEv::Eliminated),
+            player(PlayerEv::Surrendered),
+            player(PlayerEv::Protected),
+            player(PlayerEv::Unprotected),
+            player(PlayerEv::Kicked),
+            player(PlayerEv::Exploded { pos }),
+            player(PlayerEv::Timeout { millis: 3000 }),
+            player(PlayerEv::TimeoutFinished),
+            player(PlayerEv::Flash { millis: 500 }),
+            player(PlayerEv::FlashFinished),
+            player(PlayerEv::LivesRemain { lives: 2 }),
+            player(PlayerEv::MatchTimeRemain { secs: 600 }),
+            player(PlayerEv::Hint { pos, mine: false }),
+            player(PlayerEv::GameResult { rank: 1, tiles: 300, mines_hit: 2, lives: 0, eliminated_secs: Some(61) }),
+            player(PlayerEv::GameResult { rank: 2, tiles: 12, mines_hit: 0, lives: 3, eliminated_secs: None }),
+            player(PlayerEv::FriendlyChat("push left".into())),
+            player(PlayerEv::AllChat("gg".into())),
+            map(MapEv::Tile { kind: TileKind::Destroyed }),
+            map(MapEv::Owner { plid }),
+            map(MapEv::Owner { plid: PlayerId::Neutral }),
+            map(MapEv::Digit { digit: 3, asterisk: true }),
+            map(MapEv::Item { kind: ItemKind::Flashbang }),
+            map(MapEv::Flag { plid, question: false }),
+            map(MapEv::Flag { plid, question: true }),
+            map(MapEv::Flag { plid: PlayerId::Neutral, question: false }),
+            map(MapEv::Explode),
+            map(MapEv::Smoke { state: true }),
+            map(MapEv::Smoke { state: false }),
+            map(MapEv::StructureBegin { kind: StructureKind::Barricade, pts: 300 }),
+            map(MapEv::StructureReveal { kind: StructureKind::WatchTower }),
+            map(MapEv::StructureHp { hp: 4 }),
+            map(MapEv::StructureProgress { current: 100, rate: 7 }),
+            map(MapEv::StructureGone),
+            cit(CitEv::Money { current: 1503, income: 12 }),
+            cit(CitEv::Spent { amount: 250 }),
+            cit(CitEv::ResAvailable { res: 40 }),
+            cit(CitEv::TradePolicy { import: 10, export: 20 }),
+            MwEv::Background(BackgroundEv::Tremor),
+        ]
+    }
+
+    #[test]
+    fn roundtrip_events() {
+        for ev in all_events() {
+            let mut bytes = vec![];
+            encode_msgs(&[Msg::try_from(ev.clone()).unwrap()], &mut bytes).unwrap();
+            let decoded: Vec<Msg> = decode_msgs(&bytes).collect::<Result<_, _>>().unwrap();
+            let mut evs = vec![];
+            for msg in decoded {
+                msg.to_events(PlayerId::from(1), &mut evs);
+            }
+            assert_eq!(evs, vec![ev]);
+        }
+    }
+
+    #[test]
+    fn digit_range() {
+        let pos = Pos(0, 0);
+        let msg = Msg::try_from(MwEv::Map { pos, ev: MapEv::Digit { digit: 7, asterisk: false } }).unwrap();
+        let mut evs = vec![];
+        msg.to_events(PlayerId::from(1), &mut evs);
+        assert_eq!(evs, vec![MwEv::Map { pos, ev: MapEv::Digit { digit: 7, asterisk: false } }]);
+        for asterisk in [false, true] {
+            let ev = MwEv::Map { pos, ev: MapEv::Digit { digit: 8, asterisk } };
+            assert!(matches!(Msg::try_from(ev), Err(MsgEncodeError::BadDigit(8))));
+        }
+    }
+
+    #[test]
+    fn expand() {
+        let me = PlayerId::from(3);
+        let pos = Pos(0, 0);
+        let mut evs = vec![];
+        Msg::Capture { pos, digit: 0x08 | 2 }.to_game_events(me, &mut evs);
+        Msg::CitUpdate { cit: 0, res: 5, money: 10, income: 1 }.to_game_events(me, &mut evs);
+        Msg::Player { plid: me, status: MsgPlayer::Ping { millis: 30 } }.to_game_events(me, &mut evs);
+        Msg::Nop.to_game_events(me, &mut evs);
+        let expected: Vec<GameEvent> = vec![
+            MwEv::Map { pos, ev: MapEv::Owner { plid: me } },
+            MwEv::Map { pos, ev: MapEv::Digit { digit: 2, asterisk: true } },
+            MwEv::Cit { cit: 0, ev: CitEv::Money { current: 10, income: 1 } },
+            MwEv::Cit { cit: 0, ev: CitEv::ResAvailable { res: 5 } },
+        ].into_iter().map(|ev| (me, ev).into()).collect();
+        assert_eq!(evs, expected);
+    }
+}
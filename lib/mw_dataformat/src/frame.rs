@@ -0,0 +1,590 @@
+//! Spectator/Replay frames
+//!
+//! The container format that multiplexes the player streams of all plids
+//! (+ the global spectator view) together.
+//! See the "Spectator/Replay Stream Format" documentation.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use mw_common::plid::{PlayerId, Plids};
+use thiserror::Error;
+
+use crate::codec::{decode_msgs, encode_msgs_chunked, encode_one, MsgDecodeError, MsgEncodeError};
+use crate::msg::Msg;
+
+/// Frame header bit: frame is Homogenous
+const HOMOGENOUS: u16 = 1 << 15;
+/// Frame header time delta value reserved for Keepalive Frames
+const KEEPALIVE: u16 = 0x7FFF;
+/// Max payload length of a Homogenous Frame
+const MAX_HOMOGENOUS_LEN: usize = 256;
+/// Max length of each view's data in a Heterogenous Frame
+const MAX_HETEROGENOUS_LEN: usize = 255;
+
+#[derive(Debug, Error)]
+pub enum FrameEncodeError {
+    #[error("Message encoding: {0}")]
+    Msg(#[from] MsgEncodeError),
+    #[error("Time must not go backwards")]
+    TimeBackwards,
+    #[error("PlayerId {0} does not fit in the participation mask")]
+    BadPlid(u8),
+    #[error("Message too long to fit in a frame ({0} bytes, max {MAX_HETEROGENOUS_LEN})")]
+    MsgTooLong(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum FrameDecodeError {
+    #[error("Unexpected end of data")]
+    Truncated,
+    #[error("Message decoding (at offset {offset}): {error}")]
+    Msg {
+        offset: usize,
+        error: MsgDecodeError,
+    },
+}
+
+/// A decoded frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Absolute time since the start of the stream
+    pub time: Duration,
+    /// The participation mask of the frame
+    pub plids: Plids,
+    /// Messages for each view that participates in the frame
+    pub views: Vec<(PlayerId, Vec<Msg>)>,
+}
+
+/// Size of the participation mask needed for the given max player id
+fn mask_len(max_plid: u8) -> usize {
+    if max_plid < 8 { 1 } else { 2 }
+}
+
+fn push_mask(out: &mut Vec<u8>, mask_len: usize, plids: Plids) {
+    if mask_len == 1 {
+        out.push(plids.0 as u8);
+    } else {
+        out.extend_from_slice(&plids.0.to_be_bytes());
+    }
+}
+
+/// Encodes gameplay messages into frames
+///
+/// Feed it batches of messages using [`FrameWriter::push`]. All batches
+/// with the same timestamp are combined into as few frames as possible,
+/// choosing between Homogenous and Heterogenous encoding, whichever is
+/// smaller.
+///
+/// Batches for the spectator view that contain digits must have the tile owner
+/// inferred from the participation mask. Such batches are never combined
+/// with others, and are encoded with exactly the plids they were given.
+pub struct FrameWriter {
+    max_plid: u8,
+    out: Vec<u8>,
+    /// Time of the last encoded frame, in milliseconds
+    last_time: u64,
+    /// Time of the pending batches, in milliseconds
+    cur_time: u64,
+    pending: Vec<(Plids, Vec<Msg>)>,
+}
+
+impl FrameWriter {
+    /// Create a new writer
+    ///
+    /// `max_plid` determines the size of the participation mask.
+    pub fn new(max_plid: u8) -> Self {
+        FrameWriter {
+            max_plid,
+            out: vec![],
+            last_time: 0,
+            cur_time: 0,
+            pending: vec![],
+        }
+    }
+
+    /// Add a batch of messages, to be sent to all the given plids
+    ///
+    /// `time` is the absolute time since the start of the stream.
+    ///
+    /// Messages cannot be split across frames, so each one (such as a long
+    /// chat message) must fit in the data of a single view in a frame.
+    pub fn push(&mut self, time: Duration, plids: Plids, msgs: Vec<Msg>) -> Result<(), FrameEncodeError> {
+        let time = time.as_millis() as u64;
+        if time < self.cur_time {
+            return Err(FrameEncodeError::TimeBackwards);
+        }
+        if let Some(plid) = plids.iter(None).find(|plid| u8::from(*plid) > self.max_plid) {
+            return Err(FrameEncodeError::BadPlid(plid.into()));
+        }
+        let mut unit = vec![];
+        let mut i = 0;
+        while i < msgs.len() {
+            unit.clear();
+            i += encode_one(&msgs[i..], &mut unit)?;
+            if unit.len() > MAX_HETEROGENOUS_LEN {
+                return Err(FrameEncodeError::MsgTooLong(unit.len()));
+            }
+        }
+        if time != self.cur_time {
+            self.flush()?;
+            self.cur_time = time;
+        }
+        if !msgs.is_empty() && plids != Plids::default() {
+            self.pending.push((plids, msgs));
+        }
+        Ok(())
+    }
+
+    /// Add a decoded frame, for re-encoding an existing stream
+    ///
+    /// Views with identical data are pushed as one batch, so that spectator
+    /// digits keep the participation mask that their owner is inferred from.
+    pub fn push_frame(&mut self, frame: Frame) -> Result<(), FrameEncodeError> {
+        let mut groups: Vec<(Plids, Vec<Msg>)> = vec![];
+        for (plid, msgs) in frame.views {
+            if let Some(group) = groups.iter_mut().find(|(_, m)| *m == msgs) {
+                group.0 += plid;
+            } else {
+                groups.push((Plids::from(plid), msgs));
+            }
+        }
+        for (plids, msgs) in groups {
+            self.push(frame.time, plids, msgs)?;
+        }
+        Ok(())
+    }
+
+    /// Encode all pending batches
+    pub fn flush(&mut self) -> Result<(), FrameEncodeError> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut views: BTreeMap<u8, Vec<Msg>> = BTreeMap::new();
+        for (plids, msgs) in pending {
+            let pinned = plids.contains(PlayerId::Neutral) && msgs.iter()
+                .any(|msg| matches!(msg, Msg::Capture { .. } | Msg::Digit { .. }));
+            if pinned {
+                self.write_views(std::mem::take(&mut views))?;
+                self.write_homogenous(plids, &msgs)?;
+            } else {
+                for plid in plids.iter(Some(self.max_plid)) {
+                    views.entry(plid.into()).or_default().extend_from_slice(&msgs);
+                }
+            }
+        }
+        self.write_views(views)
+    }
+
+    /// Flush and return the encoded data
+    pub fn finish(mut self) -> Result<Vec<u8>, FrameEncodeError> {
+        self.flush()?;
+        Ok(self.out)
+    }
+
+    /// The data encoded so far
+    ///
+    /// Does not include any pending batches that have not been flushed.
+    pub fn data(&self) -> &[u8] {
+        &self.out
+    }
+
+    /// Write the header of a new frame, including any keepalives needed before it
+    fn write_header(out: &mut Vec<u8>, last_time: &mut u64, cur_time: u64, homogenous: bool) {
+        let mut delta = cur_time - *last_time;
+        while delta >= KEEPALIVE as u64 {
+            out.extend_from_slice(&KEEPALIVE.to_be_bytes());
+            delta -= KEEPALIVE as u64;
+        }
+        let header = delta as u16 | if homogenous { HOMOGENOUS } else { 0 };
+        out.extend_from_slice(&header.to_be_bytes());
+        *last_time = cur_time;
+    }
+
+    fn write_homogenous(&mut self, plids: Plids, msgs: &[Msg]) -> Result<(), FrameEncodeError> {
+        let mask_len = mask_len(self.max_plid);
+        for chunk in encode_msgs_chunked(msgs, MAX_HOMOGENOUS_LEN)? {
+            Self::write_header(&mut self.out, &mut self.last_time, self.cur_time, true);
+            push_mask(&mut self.out, mask_len, plids);
+            self.out.push((chunk.len() - 1) as u8);
+            self.out.extend_from_slice(&chunk);
+        }
+        Ok(())
+    }
+
+    /// Encode the data for the given views, using whichever encoding is smaller
+    fn write_views(&mut self, views: BTreeMap<u8, Vec<Msg>>) -> Result<(), FrameEncodeError> {
+        if views.is_empty() {
+            return Ok(());
+        }
+        let mask_len = mask_len(self.max_plid);
+
+        // Homogenous: one set of frames for each group of views with identical data
+        let mut groups: Vec<(Plids, &[Msg])> = vec![];
+        for (plid, msgs) in views.iter() {
+            let plid = PlayerId::from(*plid);
+            if let Some(group) = groups.iter_mut().find(|(_, m)| *m == &msgs[..]) {
+                group.0 += plid;
+            } else {
+                groups.push((Plids::from(plid), msgs));
+            }
+        }
+        let mut out_homo = vec![];
+        let mut last_time_homo = self.last_time;
+        for (plids, msgs) in groups {
+            for chunk in encode_msgs_chunked(msgs, MAX_HOMOGENOUS_LEN)? {
+                Self::write_header(&mut out_homo, &mut last_time_homo, self.cur_time, true);
+                push_mask(&mut out_homo, mask_len, plids);
+                out_homo.push((chunk.len() - 1) as u8);
+                out_homo.extend_from_slice(&chunk);
+            }
+        }
+
+        // Heterogenous: everything together, split into as many frames as needed
+        let mut out_hetero = vec![];
+        let mut last_time_hetero = self.last_time;
+        let mut chunked = vec![];
+        for (plid, msgs) in views.iter() {
+            let chunks = encode_msgs_chunked(msgs, MAX_HETEROGENOUS_LEN)?;
+            chunked.push((PlayerId::from(*plid), chunks.into_iter()));
+        }
+        loop {
+            let frame: Vec<(PlayerId, Vec<u8>)> = chunked.iter_mut()
+                .filter_map(|(plid, chunks)| Some((*plid, chunks.next()?)))
+                .collect();
+            if frame.is_empty() {
+                break;
+            }
+            let plids = frame.iter().fold(Plids::default(), |plids, (plid, _)| plids + *plid);
+            Self::write_header(&mut out_hetero, &mut last_time_hetero, self.cur_time, false);
+            push_mask(&mut out_hetero, mask_len, plids);
+            for (_, chunk) in frame.iter() {
+                out_hetero.push(chunk.len() as u8);
+            }
+            for (_, chunk) in frame.iter() {
+                out_hetero.extend_from_slice(chunk);
+            }
+        }
+
+        if out_homo.len() <= out_hetero.len() {
+            self.out.extend_from_slice(&out_homo);
+        } else {
+            self.out.extend_from_slice(&out_hetero);
+        }
+        self.last_time = self.cur_time;
+        Ok(())
+    }
+}
+
+/// Decodes frames
+///
+/// Iterator that produces the frames in the data, with absolute timestamps.
+/// Keepalive frames are not produced; they just advance the time.
+/// The iterator stops after the first error.
+pub struct FrameReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    mask_len: usize,
+    time: u64,
+    failed: bool,
+}
+
+impl<'a> FrameReader<'a> {
+    /// Create a new reader
+    ///
+    /// `max_plid` determines the size of the participation mask.
+    pub fn new(data: &'a [u8], max_plid: u8) -> Self {
+        FrameReader {
+            data,
+            offset: 0,
+            mask_len: mask_len(max_plid),
+            time: 0,
+            failed: false,
+        }
+    }
+
+    /// Byte offset of the next frame to be decoded
+    ///
+    /// After an error, this is the offset of the frame that failed to decode.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn read_bytes(&self, pos: &mut usize, len: usize) -> Result<&'a [u8], FrameDecodeError> {
+        let data = self.data.get(*pos..(*pos + len)).ok_or(FrameDecodeError::Truncated)?;
+        *pos += len;
+        Ok(data)
+    }
+
+    fn decode_view(&self, data: &[u8], data_offset: usize) -> Result<Vec<Msg>, FrameDecodeError> {
+        let mut decoder = decode_msgs(data);
+        let mut msgs = vec![];
+        while let Some(r) = decoder.next() {
+            match r {
+                Ok(msg) => msgs.push(msg),
+                Err(error) => return Err(FrameDecodeError::Msg {
+                    offset: data_offset + decoder.offset(),
+                    error,
+                }),
+            }
+        }
+        Ok(msgs)
+    }
+
+    /// Decode the next frame; returns `None` for keepalives
+    fn decode_next(&mut self) -> Result<Option<Frame>, FrameDecodeError> {
+        let mut pos = self.offset;
+        let header = self.read_bytes(&mut pos, 2)?;
+        let header = u16::from_be_bytes([header[0], header[1]]);
+        let delta = header & !HOMOGENOUS;
+        if delta == KEEPALIVE {
+            self.time += KEEPALIVE as u64;
+            self.offset = pos;
+            return Ok(None);
+        }
+        let mask = self.read_bytes(&mut pos, self.mask_len)?;
+        let plids = Plids(if self.mask_len == 1 {
+            mask[0] as u16
+        } else {
+            u16::from_be_bytes([mask[0], mask[1]])
+        });
+        let mut views = vec![];
+        if header & HOMOGENOUS != 0 {
+            let len = self.read_bytes(&mut pos, 1)?[0] as usize + 1;
+            let data_offset = pos;
+            let msgs = self.decode_view(self.read_bytes(&mut pos, len)?, data_offset)?;
+            for plid in plids.iter(None) {
+                views.push((plid, msgs.clone()));
+            }
+        } else {
+            let n = plids.iter(None).count();
+            let lens = self.read_bytes(&mut pos, n)?;
+            for (plid, len) in plids.iter(None).zip(lens.iter()) {
+                let data_offset = pos;
+                let msgs = self.decode_view(self.read_bytes(&mut pos, *len as usize)?, data_offset)?;
+                views.push((plid, msgs));
+            }
+        }
+        self.time += delta as u64;
+        self.offset = pos;
+        Ok(Some(Frame {
+            time: Duration::from_millis(self.time),
+            plids,
+            views,
+        }))
+    }
+}
+
+impl<'a> Iterator for FrameReader<'a> {
+    type Item = Result<Frame, FrameDecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed && self.offset < self.data.len() {
+            match self.decode_next() {
+                Ok(Some(frame)) => return Some(Ok(frame)),
+                Ok(None) => continue,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<'a> std::iter::FusedIterator for FrameReader<'a> {}
+
+#[cfg(test)]
+mod test {
+    use mw_common::grid::Pos;
+    use mw_common::prelude::*;
+
+    use crate::msg::MsgPlayer;
+
+    use super::*;
+
+    type Views = BTreeMap<(Duration, u8), Vec<Msg>>;
+
+    fn read_all(data: &[u8], max_plid: u8) -> Views {
+        let mut r = Views::new();
+        for frame in FrameReader::new(data, max_plid) {
+            let frame = frame.unwrap();
+            for (plid, msgs) in frame.views {
+                r.entry((frame.time, plid.into())).or_default().extend(msgs);
+            }
+        }
+        r
+    }
+
+    fn expected(batches: &[(Duration, Plids, Vec<Msg>)], max_plid: u8) -> Views {
+        let mut r = Views::new();
+        for (time, plids, msgs) in batches {
+            for plid in plids.iter(Some(max_plid)) {
+                r.entry((*time, plid.into())).or_default().extend_from_slice(msgs);
+            }
+        }
+        r.retain(|_, msgs| !msgs.is_empty());
+        r
+    }
+
+    fn roundtrip(batches: &[(Duration, Plids, Vec<Msg>)], max_plid: u8) -> Vec<u8> {
+        let mut w = FrameWriter::new(max_plid);
+        for (time, plids, msgs) in batches {
+            w.push(*time, *plids, msgs.clone()).unwrap();
+        }
+        let data = w.finish().unwrap();
+        assert_eq!(read_all(&data, max_plid), expected(batches, max_plid));
+        data
+    }
+
+    #[test]
+    fn homogenous() {
+        let msgs = vec![Msg::Tremor, Msg::Explode { pos: Pos(1, 2) }];
+        let data = roundtrip(&[
+            (Duration::from_millis(5), Plids(0b0111), msgs.clone()),
+        ], 3);
+        assert_eq!(&data[..], &[0x80, 0x05, 0b0111, 3, 0x01, 0x30, 0x81, 0x82]);
+    }
+
+    #[test]
+    fn heterogenous() {
+        let data = roundtrip(&[
+            (Duration::from_millis(1), Plids(0b0011), vec![Msg::Tremor]),
+            (Duration::from_millis(1), Plids(0b0101), vec![Msg::Smoke { pos: Pos(0, 0) }]),
+        ], 3);
+        assert_eq!(&data[..], &[
+            0x00, 0x01, 0b0111, 4, 1, 3,
+            0x01, 0x02, 0x80, 0x80,
+            0x01,
+            0x02, 0x80, 0x80,
+        ]);
+    }
+
+    #[test]
+    fn wide_mask() {
+        let data = roundtrip(&[
+            (Duration::from_millis(1), Plids(0b1000_0000_0001), vec![Msg::Tremor]),
+        ], 12);
+        assert_eq!(&data[..], &[0x80, 0x01, 0b1000, 0b0000_0001, 0, 0x01]);
+    }
+
+    #[test]
+    fn keepalive() {
+        let data = roundtrip(&[
+            (Duration::from_millis(10), Plids(0b01), vec![Msg::Tremor]),
+            (Duration::from_millis(10 + 0x7FFF * 2 + 7), Plids(0b01), vec![Msg::Tremor]),
+        ], 1);
+        assert_eq!(&data[..], &[
+            0x80, 10, 1, 0, 0x01,
+            0x7F, 0xFF,
+            0x7F, 0xFF,
+            0x80, 7, 1, 0, 0x01,
+        ]);
+    }
+
+    #[test]
+    fn split_large() {
+        let msgs: Vec<Msg> = (0..200).map(|i| Msg::Digit { pos: Pos(i as i8, 0), digit: 3 }).collect();
+        let data = roundtrip(&[
+            (Duration::from_millis(3), Plids(0b110), msgs.clone()),
+            (Duration::from_millis(3), Plids(0b001), msgs[..150].to_vec()),
+            (Duration::from_millis(4), Plids(0b001), msgs.clone()),
+        ], 2);
+        assert!(FrameReader::new(&data, 2).count() > 3);
+    }
+
+    #[test]
+    fn long_chat() {
+        // the longest chat message that fits in a frame
+        let msgs = vec![Msg::Player {
+            plid: 1.into(),
+            status: MsgPlayer::AllChat("a".repeat(251)),
+        }];
+        roundtrip(&[
+            (Duration::from_millis(1), Plids(0b10), msgs.clone()),
+            (Duration::from_millis(2), Plids(0b10), msgs.clone()),
+            (Duration::from_millis(2), Plids(0b01), vec![Msg::Tremor]),
+        ], 3);
+    }
+
+    #[test]
+    fn spect_digits_pinned() {
+        let batches = [
+            (Duration::from_millis(3), Plids(0b011), vec![Msg::Capture { pos: Pos(0, 0), digit: 1 }]),
+            (Duration::from_millis(3), Plids(0b101), vec![Msg::Capture { pos: Pos(1, 0), digit: 2 }]),
+            (Duration::from_millis(3), Plids(0b111), vec![Msg::Tremor]),
+        ];
+        let data = roundtrip(&batches, 2);
+        let frames: Vec<Frame> = FrameReader::new(&data, 2).map(|f| f.unwrap()).collect();
+        assert_eq!(frames[0].plids, Plids(0b011));
+        assert_eq!(frames[1].plids, Plids(0b101));
+        assert_eq!(frames[1].time, Duration::from_millis(3));
+    }
+
+    #[test]
+    fn rewrite() {
+        let batches = [
+            (Duration::from_millis(3), Plids(0b011), vec![Msg::Capture { pos: Pos(0, 0), digit: 1 }]),
+            (Duration::from_millis(3), Plids(0b101), vec![Msg::Capture { pos: Pos(1, 0), digit: 2 }]),
+            (Duration::from_millis(3), Plids(0b111), vec![Msg::Tremor]),
+            (Duration::from_millis(9), Plids(0b110), vec![Msg::Tremor]),
+            (Duration::from_millis(9), Plids(0b010), vec![Msg::Explode { pos: Pos(1, 1) }]),
+        ];
+        let data = roundtrip(&batches, 2);
+        let mut w = FrameWriter::new(2);
+        for frame in FrameReader::new(&data, 2) {
+            w.push_frame(frame.unwrap()).unwrap();
+        }
+        assert_eq!(w.finish().unwrap(), data);
+    }
+
+    #[test]
+    fn random() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1503);
+        let mut batches = vec![];
+        let mut time = 0;
+        for _ in 0..500 {
+            if rng.gen_bool(0.3) {
+                time += rng.gen_range(0..50000);
+            }
+            let plids = Plids(rng.gen_range(0..(1 << 7)));
+            let n = rng.gen_range(0..40);
+            let msgs = (0..n).map(|_| match rng.gen_range(0..4) {
+                0 => Msg::Tremor,
+                1 => Msg::TileOwner { pos: Pos(rng.gen(), rng.gen()), plid: 2.into() },
+                2 => Msg::Explode { pos: Pos(rng.gen(), rng.gen()) },
+                _ => Msg::RevealItem { pos: Pos(rng.gen(), rng.gen()), item: crate::msg::MsgItem::Mine },
+            }).collect();
+            batches.push((Duration::from_millis(time), plids, msgs));
+        }
+        roundtrip(&batches, 6);
+    }
+
+    #[test]
+    fn errors() {
+        let mut w = FrameWriter::new(3);
+        assert!(matches!(
+            w.push(Duration::from_millis(1), Plids(0b10000), vec![Msg::Tremor]),
+            Err(FrameEncodeError::BadPlid(4))
+        ));
+        w.push(Duration::from_millis(5), Plids(1), vec![Msg::Tremor]).unwrap();
+        assert!(matches!(
+            w.push(Duration::from_millis(4), Plids(1), vec![Msg::Tremor]),
+            Err(FrameEncodeError::TimeBackwards)
+        ));
+
+        let chat = |len| vec![Msg::Player {
+            plid: 1.into(),
+            status: MsgPlayer::AllChat("a".repeat(len)),
+        }];
+        assert!(matches!(
+            w.push(Duration::from_millis(6), Plids(0b10), chat(255)),
+            Err(FrameEncodeError::MsgTooLong(259))
+        ));
+
+        let mut r = FrameReader::new(&[0x80, 0x01, 0x01, 0x00], 1);
+        assert!(matches!(r.next(), Some(Err(FrameDecodeError::Truncated))));
+        assert!(r.next().is_none());
+        let mut r = FrameReader::new(&[0x80, 0x01, 0x01, 0x00, 0x08], 1);
+        assert!(matches!(r.next(), Some(Err(FrameDecodeError::Msg { offset: 4, .. }))));
+    }
+}
@@ -0,0 +1,473 @@
+//! The Initialization Sequence
+//!
+//! Metadata about the game session + the map data, sent at the start of
+//! every player stream and spectator stream / replay file.
+
+use mw_common::game::{ItemKind, TileKind};
+use mw_common::grid::{Coord, MapData, Pos, Topology};
+use mw_common::grid::map::ring_order;
+use thiserror::Error;
+
+/// The only supported protocol version
+pub const PROTOCOL_VERSION: u8 = 0x01;
+
+/// Flags bit: game uses a square grid (hex otherwise)
+const FLAG_SQ: u8 = 0b00001000;
+/// Flags bit: max player id is 15 (7 otherwise)
+const FLAG_WIDE_PLIDS: u8 = 0b00010000;
+
+/// Length of the fixed part of the header, before the data payload
+const HEADER_LEN: usize = 10;
+
+/// Everything we know about a map tile at the start of the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InitTile {
+    pub kind: TileKind,
+    /// Only used for spectator streams and replay files. Should be `Safe` in player streams.
+    pub item: ItemKind,
+    /// City/region ID
+    pub region: u8,
+}
+
+/// The Initialization Sequence
+///
+/// The map is stored in concentric-ring order (as in the encoded format).
+/// Use [`InitSequence::mapdata`] to get a more convenient representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitSequence {
+    pub topology: Topology,
+    /// Map radius
+    pub size: u8,
+    pub n_players: u8,
+    /// The "max player id" bit: if set, the max player id is 15 instead of 7
+    ///
+    /// Determines the size of the participation masks in spectator frames.
+    pub wide_plids: bool,
+    /// Display names of each player. Empty for an anonymized stream.
+    pub names: Vec<String>,
+    pub cits: Vec<Pos>,
+    tiles: Vec<InitTile>,
+}
+
+#[derive(Debug, Error)]
+pub enum InitEncodeError {
+    #[error("Number of cities must be 1-16, got {0}")]
+    BadCitCount(usize),
+    #[error("Number of players must be 0-15, got {0}")]
+    BadPlayerCount(u8),
+    #[error("{0} players do not fit under the max player id")]
+    PlidsTooNarrow(u8),
+    #[error("Player names do not match the number of players")]
+    BadNames,
+    #[error("Player name too long: {0:?}")]
+    NameTooLong(String),
+    #[error("Map too big to be encoded")]
+    MapTooBig,
+}
+
+#[derive(Debug, Error)]
+pub enum InitDecodeError {
+    #[error("Unexpected end of data")]
+    Truncated,
+    #[error("Unsupported protocol version: {0}")]
+    BadVersion(u8),
+    #[error("Player names are not valid UTF-8")]
+    BadName,
+    #[error("Map data length does not match map size")]
+    BadMapLen,
+    #[error("Invalid tile byte: {0:#010b}")]
+    BadTile(u8),
+    #[error("LZ4: {0}")]
+    Lz4(#[from] lz4_flex::block::DecompressError),
+}
+
+impl InitSequence {
+    /// Create an Initialization Sequence from a map
+    ///
+    /// `f` provides the tile data for each map tile. The format only supports
+    /// radial maps; other shapes are padded with water.
+    pub fn new<C: Coord, D>(
+        mapdata: &MapData<C, D>,
+        cits: &[C],
+        n_players: u8,
+        names: Vec<String>,
+        f: impl Fn(&D) -> InitTile,
+    ) -> Self {
+        InitSequence {
+            topology: C::TOPOLOGY,
+            size: mapdata.size(),
+            n_players,
+            wide_plids: n_players > 7,
+            names,
+            cits: cits.iter().map(|c| (*c).into()).collect(),
+            tiles: ring_order::<C>(mapdata.size())
+                .into_iter()
+                .map(|c| mapdata.get(c).map(&f).unwrap_or(InitTile {
+                    kind: TileKind::Water,
+                    ..Default::default()
+                }))
+                .collect(),
+        }
+    }
+
+    /// The highest player id that can appear in the stream
+    pub fn max_plid(&self) -> u8 {
+        if self.wide_plids { 15 } else { 7 }
+    }
+
+    /// The map tiles, in concentric-ring order
+    pub fn tiles(&self) -> &[InitTile] {
+        &self.tiles
+    }
+
+    /// The map tiles, in concentric-ring order
+    pub fn tiles_mut(&mut self) -> &mut [InitTile] {
+        &mut self.tiles
+    }
+
+    /// Convert the map into `MapData`
+    ///
+    /// Returns `None` if `C` is the wrong topology.
+    pub fn mapdata<C: Coord>(&self) -> Option<MapData<C, InitTile>> {
+        if C::TOPOLOGY != self.topology {
+            return None;
+        }
+        let mut mapdata = MapData::new(self.size, InitTile::default());
+        for (c, tile) in ring_order::<C>(self.size).into_iter().zip(self.tiles.iter()) {
+            mapdata[c] = *tile;
+        }
+        Some(mapdata)
+    }
+
+    /// Encode into the binary format, appending to `out`
+    ///
+    /// If `compress` is true, the map data will be LZ4 compressed (but only if
+    /// that makes it smaller).
+    pub fn encode(&self, out: &mut Vec<u8>, compress: bool) -> Result<(), InitEncodeError> {
+        if self.cits.is_empty() || self.cits.len() > 16 {
+            return Err(InitEncodeError::BadCitCount(self.cits.len()));
+        }
+        if self.n_players > 15 {
+            return Err(InitEncodeError::BadPlayerCount(self.n_players));
+        }
+        if self.n_players > self.max_plid() {
+            return Err(InitEncodeError::PlidsTooNarrow(self.n_players));
+        }
+        if !self.names.is_empty() && self.names.len() != self.n_players as usize {
+            return Err(InitEncodeError::BadNames);
+        }
+
+        let mut names_data = vec![];
+        for name in self.names.iter() {
+            let Ok(len) = u8::try_from(name.len()) else {
+                return Err(InitEncodeError::NameTooLong(name.clone()));
+            };
+            names_data.push(len);
+            names_data.extend_from_slice(name.as_bytes());
+        }
+        let Ok(names_len) = u16::try_from(names_data.len()) else {
+            return Err(InitEncodeError::BadNames);
+        };
+
+        let mut map_data = Vec::with_capacity(self.tiles.len() * 2);
+        map_data.extend(self.tiles.iter().map(|t| encode_tile(*t)));
+        map_data.extend(self.tiles.iter().map(|t| t.region));
+        let Ok(len_raw) = u16::try_from(map_data.len()) else {
+            return Err(InitEncodeError::MapTooBig);
+        };
+        let mut len_compressed = len_raw;
+        if compress {
+            let compressed = lz4_flex::block::compress(&map_data);
+            if compressed.len() < map_data.len() {
+                map_data = compressed;
+                len_compressed = map_data.len() as u16;
+            }
+        }
+
+        let mut flags = match self.topology {
+            Topology::Hex => 0,
+            Topology::Sq => FLAG_SQ,
+        };
+        if self.wide_plids {
+            flags |= FLAG_WIDE_PLIDS;
+        }
+        out.push(PROTOCOL_VERSION);
+        out.push(flags);
+        out.push(self.size);
+        out.push(self.n_players << 4 | (self.cits.len() - 1) as u8);
+        out.extend_from_slice(&names_len.to_be_bytes());
+        out.extend_from_slice(&len_compressed.to_be_bytes());
+        out.extend_from_slice(&len_raw.to_be_bytes());
+        out.extend_from_slice(&names_data);
+        for cit in self.cits.iter() {
+            let (y, x): (u8, u8) = (*cit).into();
+            out.extend_from_slice(&[y, x]);
+        }
+        out.extend_from_slice(&map_data);
+
+        Ok(())
+    }
+
+    /// Decode from the binary format
+    ///
+    /// Returns the number of bytes that were consumed from `data`.
+    pub fn decode(data: &[u8]) -> Result<(Self, usize), InitDecodeError> {
+        let header = data.get(..HEADER_LEN).ok_or(InitDecodeError::Truncated)?;
+        if header[0] != PROTOCOL_VERSION {
+            return Err(InitDecodeError::BadVersion(header[0]));
+        }
+        let topology = if header[1] & FLAG_SQ != 0 {
+            Topology::Sq
+        } else {
+            Topology::Hex
+        };
+        let wide_plids = header[1] & FLAG_WIDE_PLIDS != 0;
+        let size = header[2];
+        let n_players = header[3] >> 4;
+        let n_cits = (header[3] & 0x0F) as usize + 1;
+        let names_len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let len_compressed = u16::from_be_bytes([header[6], header[7]]) as usize;
+        let len_raw = u16::from_be_bytes([header[8], header[9]]) as usize;
+
+        let area = match topology {
+            Topology::Hex => mw_common::grid::Hex::map_area(size),
+            Topology::Sq => mw_common::grid::Sq::map_area(size),
+        };
+        if len_raw != area * 2 || len_compressed > len_raw {
+            return Err(InitDecodeError::BadMapLen);
+        }
+
+        let mut offset = HEADER_LEN;
+        let mut take = |len: usize| -> Result<&[u8], InitDecodeError> {
+            let r = data.get(offset..(offset + len)).ok_or(InitDecodeError::Truncated)?;
+            offset += len;
+            Ok(r)
+        };
+
+        let mut names = vec![];
+        let mut names_data = take(names_len)?;
+        while let Some((&len, rem)) = names_data.split_first() {
+            let name = rem.get(..len as usize).ok_or(InitDecodeError::BadName)?;
+            let name = std::str::from_utf8(name).map_err(|_| InitDecodeError::BadName)?;
+            names.push(name.to_owned());
+            names_data = &rem[len as usize..];
+        }
+
+        let cits = take(n_cits * 2)?
+            .chunks(2)
+            .map(|yx| Pos((yx[0] as i16 - 128) as i8, (yx[1] as i16 - 128) as i8))
+            .collect();
+
+        let map_data = take(len_compressed)?;
+        let decompressed;
+        let map_data = if len_compressed < len_raw {
+            decompressed = lz4_flex::block::decompress(map_data, len_raw)?;
+            if decompressed.len() != len_raw {
+                return Err(InitDecodeError::BadMapLen);
+            }
+            &decompressed[..]
+        } else {
+            map_data
+        };
+        let (tile_bytes, region_bytes) = map_data.split_at(area);
+        let tiles = tile_bytes.iter()
+            .zip(region_bytes.iter())
+            .map(|(b, region)| decode_tile(*b, *region))
+            .collect::<Result<_, _>>()?;
+
+        Ok((InitSequence {
+            topology,
+            size,
+            n_players,
+            wide_plids,
+            names,
+            cits,
+            tiles,
+        }, offset))
+    }
+}
+
+/// Lengths of the header part and the data payload part of an encoded Initialization Sequence
+///
+/// The header part is everything before the cities and map data (incl. the player names).
+pub(crate) fn split_lens(data: &[u8]) -> Option<(usize, usize)> {
+    let header = data.get(..HEADER_LEN)?;
+    let n_cits = (header[3] & 0x0F) as usize + 1;
+    let names_len = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let len_compressed = u16::from_be_bytes([header[6], header[7]]) as usize;
+    Some((HEADER_LEN + names_len, n_cits * 2 + len_compressed))
+}
+
+fn encode_tile(tile: InitTile) -> u8 {
+    let kind = match tile.kind {
+        TileKind::Water => 0b000,
+        TileKind::Foundation => 0b001,
+        TileKind::Mountain => 0b010,
+        TileKind::Forest => 0b011,
+        TileKind::Destroyed => 0b100,
+        TileKind::Regular => 0b110,
+        TileKind::Fertile => 0b111,
+    };
+    let item = match tile.item {
+        ItemKind::Safe => 0b000,
+        ItemKind::Decoy => 0b001,
+        ItemKind::Mine => 0b010,
+        ItemKind::Flashbang => 0b011,
+    };
+    item << 4 | kind
+}
+
+fn decode_tile(b: u8, region: u8) -> Result<InitTile, InitDecodeError> {
+    let kind = match b & 0b111 {
+        0b000 => TileKind::Water,
+        0b001 => TileKind::Foundation,
+        0b010 => TileKind::Mountain,
+        0b011 => TileKind::Forest,
+        0b100 => TileKind::Destroyed,
+        0b110 => TileKind::Regular,
+        0b111 => TileKind::Fertile,
+        _ => return Err(InitDecodeError::BadTile(b)),
+    };
+    let item = match (b >> 4) & 0b111 {
+        0b000 => ItemKind::Safe,
+        0b001 => ItemKind::Decoy,
+        0b010 => ItemKind::Mine,
+        0b011 => ItemKind::Flashbang,
+        _ => return Err(InitDecodeError::BadTile(b)),
+    };
+    Ok(InitTile { kind, item, region })
+}
+
+#[cfg(test)]
+mod test {
+    use mw_common::grid::{Hex, MapShape, Sq};
+    use mw_common::prelude::*;
+
+    use super::*;
+
+    fn test_map<C: Coord>(size: u8) -> MapData<C, InitTile> {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1503);
+        MapData::new_with(size, |c: C| InitTile {
+            kind: if c.ring() > size - 2 {
+                TileKind::Water
+            } else {
+                *[TileKind::Regular, TileKind::Fertile, TileKind::Mountain, TileKind::Forest]
+                    .choose(&mut rng).unwrap()
+            },
+            item: *[ItemKind::Safe, ItemKind::Mine, ItemKind::Decoy]
+                .choose(&mut rng).unwrap(),
+            region: rng.gen_range(0..3),
+        })
+    }
+
+    fn roundtrip<C: Coord>(compress: bool) {
+        let map = test_map::<C>(12);
+        let cits = [C::from(Pos(0, 0)), C::from(Pos(3, -2)), C::from(Pos(-5, 1))];
+        let names = vec!["Iyes".to_owned(), "Ahmed".to_owned()];
+        let init = InitSequence::new(&map, &cits, 2, names, |d| *d);
+        let mut out = vec![0xAA];
+        init.encode(&mut out, compress).unwrap();
+        // extra trailing data, should be ignored
+        out.push(0x55);
+        let (init2, len) = InitSequence::decode(&out[1..]).unwrap();
+        assert_eq!(len, out.len() - 2);
+        assert_eq!(init, init2);
+        let map2 = init2.mapdata::<C>().unwrap();
+        for (c, d) in map.iter() {
+            assert_eq!(*d, map2[c]);
+        }
+    }
+
+    #[test]
+    fn roundtrip_hex() {
+        roundtrip::<Hex>(false);
+        roundtrip::<Hex>(true);
+    }
+
+    #[test]
+    fn roundtrip_sq() {
+        roundtrip::<Sq>(false);
+        roundtrip::<Sq>(true);
+    }
+
+    #[test]
+    fn rect_padded() {
+        let map = MapData::<Sq, _>::new_shape(MapShape::rect::<Sq>(6, 3), InitTile::default());
+        let init = InitSequence::new(&map, &[], 1, vec![], |d| *d);
+        let out = init.mapdata::<Sq>().unwrap();
+        assert_eq!(out.size(), 3);
+        for (c, tile) in out.iter() {
+            assert_eq!(tile.kind == TileKind::Water, !map.contains(c), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn header() {
+        let map = MapData::<Sq, _>::new(1, InitTile::default());
+        let init = InitSequence::new(&map, &[Sq(1, -1)], 3, vec![], |d| *d);
+        let mut out = vec![];
+        init.encode(&mut out, false).unwrap();
+        assert_eq!(&out[..], &[
+            0x01, 0x08, 0x01, 0x30,
+            0x00, 0x00, 0x00, 0x12, 0x00, 0x12,
+            0x81, 0x7F,
+            0x06, 0x06, 0x06, 0x06, 0x06, 0x06, 0x06, 0x06, 0x06,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        assert!(init.mapdata::<Hex>().is_none());
+    }
+
+    #[test]
+    fn wide_plids() {
+        let map = MapData::<Sq, _>::new(1, InitTile::default());
+        let mut init = InitSequence::new(&map, &[Sq(0, 0)], 9, vec![], |d| *d);
+        assert!(init.wide_plids);
+        assert_eq!(init.max_plid(), 15);
+        let mut out = vec![];
+        init.encode(&mut out, false).unwrap();
+        assert_eq!(out[1], 0x18);
+        assert_eq!(InitSequence::decode(&out).unwrap().0, init);
+
+        // more plids than players, for spectator-heavy sessions
+        init.n_players = 2;
+        let mut out = vec![];
+        init.encode(&mut out, false).unwrap();
+        assert_eq!(InitSequence::decode(&out).unwrap().0.max_plid(), 15);
+
+        init.n_players = 9;
+        init.wide_plids = false;
+        assert!(matches!(init.encode(&mut out, false), Err(InitEncodeError::PlidsTooNarrow(9))));
+    }
+
+    #[test]
+    fn compressed_is_smaller() {
+        let map = MapData::<Hex, _>::new(20, InitTile::default());
+        let init = InitSequence::new(&map, &[Hex(0, 0)], 1, vec![], |d| *d);
+        let mut raw = vec![];
+        init.encode(&mut raw, false).unwrap();
+        let mut compressed = vec![];
+        init.encode(&mut compressed, true).unwrap();
+        assert!(compressed.len() < raw.len());
+        assert_eq!(InitSequence::decode(&compressed).unwrap().0, init);
+    }
+
+    #[test]
+    fn errors() {
+        let map = MapData::<Hex, _>::new(3, InitTile::default());
+        let mut out = vec![];
+        let init = InitSequence::new(&map, &[], 1, vec![], |d| *d);
+        assert!(matches!(init.encode(&mut out, false), Err(InitEncodeError::BadCitCount(0))));
+        let init = InitSequence::new(&map, &[Hex(0, 0)], 2, vec!["a".into()], |d| *d);
+        assert!(matches!(init.encode(&mut out, false), Err(InitEncodeError::BadNames)));
+        let map = MapData::<Hex, _>::new(110, InitTile::default());
+        let init = InitSequence::new(&map, &[Hex(0, 0)], 1, vec![], |d| *d);
+        assert!(matches!(init.encode(&mut out, false), Err(InitEncodeError::MapTooBig)));
+
+        let init = InitSequence::new(&MapData::<Hex, _>::new(3, InitTile::default()), &[Hex(0, 0)], 1, vec![], |d| *d);
+        let mut out = vec![];
+        init.encode(&mut out, false).unwrap();
+        assert!(matches!(InitSequence::decode(&out[..out.len() - 1]), Err(InitDecodeError::Truncated)));
+        out[0] = 2;
+        assert!(matches!(InitSequence::decode(&out), Err(InitDecodeError::BadVersion(2))));
+    }
+}
@@ -1,3 +1,10 @@
 pub mod asm;
+pub mod codec;
+pub mod diff;
+pub mod event;
+pub mod frame;
+pub mod init;
 pub mod msg;
 pub mod opt;
+pub mod replay;
+pub mod state;
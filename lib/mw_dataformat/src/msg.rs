@@ -5,11 +5,14 @@ use mw_common::{grid::Pos, plid::PlayerId};
 /// This is the IR used as a final step before encoding raw bytes.
 ///
 /// The sorting order is important! Optimization passes rely on it! Do not reorder things in this enum!
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+///
+/// Digit values are encoded the same way as in the binary format:
+/// bits `-xxx` are the digit, bit `x---` is the asterisk.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Msg {
     Player {
         plid: PlayerId,
-        status: u8,
+        status: MsgPlayer,
     },
     Capture {
         pos: Pos,
@@ -29,6 +32,24 @@ pub enum Msg {
         money: u32,
         income: u16,
     },
+    CitMoney {
+        cit: u8,
+        money: u32,
+        income: u16,
+    },
+    CitRes {
+        cit: u8,
+        res: u16,
+    },
+    CitSpend {
+        cit: u8,
+        spent: u16,
+    },
+    CitTrade {
+        cit: u8,
+        export: u8,
+        import: u8,
+    },
     RevealStructure {
         pos: Pos,
         kind: MsgStructureKind,
@@ -50,6 +71,15 @@ pub enum Msg {
         current: u16,
         rate: u16,
     },
+    TileKind {
+        pos: Pos,
+        kind: MsgTileKind,
+    },
+    Flag {
+        pos: Pos,
+        plid: PlayerId,
+        question: bool,
+    },
     RevealItem {
         pos: Pos,
         item: MsgItem,
@@ -60,10 +90,85 @@ pub enum Msg {
     Smoke {
         pos: Pos,
     },
+    Unsmoke {
+        pos: Pos,
+    },
     Tremor,
     Nop,
 }
 
+/// The kinds of Player Update messages
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MsgPlayer {
+    Joined,
+    Ping {
+        millis: u16,
+    },
+    Stunned {
+        millis: u16,
+    },
+    Unstunned,
+    Blinded {
+        millis: u16,
+    },
+    Unblinded,
+    Protected,
+    Unprotected,
+    Eliminated,
+    Surrendered,
+    Disconnected,
+    Kicked,
+    FriendlyChat(String),
+    AllChat(String),
+    LivesRemain {
+        lives: u8,
+    },
+    MatchTimeRemain {
+        secs: u16,
+    },
+    Exploded {
+        pos: Pos,
+    },
+    Hint {
+        pos: Pos,
+        mine: bool,
+    },
+    GameResult {
+        rank: u8,
+        tiles: u16,
+        mines_hit: u8,
+        lives: u8,
+        eliminated_secs: Option<u16>,
+    },
+}
+
+impl MsgPlayer {
+    /// The value of the message kind byte in the binary format
+    pub fn kind(&self) -> u8 {
+        match self {
+            MsgPlayer::Joined => 0x00,
+            MsgPlayer::Ping { .. } => 0x01,
+            MsgPlayer::Stunned { .. } => 0x02,
+            MsgPlayer::Unstunned => 0x03,
+            MsgPlayer::Blinded { .. } => 0x04,
+            MsgPlayer::Unblinded => 0x05,
+            MsgPlayer::Protected => 0x06,
+            MsgPlayer::Unprotected => 0x07,
+            MsgPlayer::Eliminated => 0x08,
+            MsgPlayer::Surrendered => 0x09,
+            MsgPlayer::Disconnected => 0x0A,
+            MsgPlayer::Kicked => 0x0B,
+            MsgPlayer::FriendlyChat(_) => 0x0E,
+            MsgPlayer::AllChat(_) => 0x0F,
+            MsgPlayer::LivesRemain { .. } => 0x10,
+            MsgPlayer::MatchTimeRemain { .. } => 0x11,
+            MsgPlayer::Exploded { .. } => 0x12,
+            MsgPlayer::Hint { .. } => 0x13,
+            MsgPlayer::GameResult { .. } => 0x14,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum MsgItem {
     None = 0,
@@ -79,3 +184,14 @@ pub enum MsgStructureKind {
     Wall = 2,
     Tower = 3,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MsgTileKind {
+    Water = 0,
+    Foundation = 1,
+    Mountain = 2,
+    Forest = 3,
+    Destroyed = 4,
+    Regular = 6,
+    Fertile = 7,
+}
@@ -1,10 +1,27 @@
+use mw_common::grid::Pos;
 use mw_common::plid::PlayerId;
+use mw_common::prelude::{HashMap, HashSet};
 
 use crate::msg::Msg;
 
+/// What the optimizer knows about the state of each view
+///
+/// Used to suppress updates that would not change anything, because the
+/// view already has the same information from a previous sequence.
+///
+/// Every sequence must only be optimized once: the state assumes that
+/// everything that comes out of the optimizer is sent to the view.
 #[derive(Default)]
 pub struct OptimizerState {
-    // TODO
+    views: HashMap<PlayerId, ViewState>,
+}
+
+#[derive(Default)]
+struct ViewState {
+    owners: HashMap<Pos, PlayerId>,
+    digits: HashMap<Pos, u8>,
+    money: HashMap<u8, (u32, u16)>,
+    res: HashMap<u8, u16>,
 }
 
 /// Output from an optimization pass
@@ -30,13 +47,17 @@ pub enum OptimizerResult {
 ///
 /// If you notice any weird bugs in the game, try disabling this optimizer, to know if the issue is caused by it.
 /// They might be caused by bugs in the optimizations.
-pub fn optimize_mwseq_lossy(_state: &mut OptimizerState, _me: PlayerId, sequence: &mut Vec<Msg>) -> OptimizerResult {
+pub fn optimize_mwseq_lossy(state: &mut OptimizerState, me: PlayerId, sequence: &mut Vec<Msg>) -> OptimizerResult {
     let r = OptimizerResult::Good;
 
     if sequence.is_empty() {
         return r;
     }
 
+    // Before sorting, get rid of anything that is overwritten later in
+    // the sequence. Sorting loses the information about what came later.
+    remove_superseded(me, sequence);
+
     // First, sort. This will expose all further optimizations.
     sequence.sort_unstable();
 
@@ -49,12 +70,396 @@ pub fn optimize_mwseq_lossy(_state: &mut OptimizerState, _me: PlayerId, sequence
     // Remove any literal duplicates.
     sequence.dedup();
 
-    // TODO: implement more optimization passes
+    if me != PlayerId::Neutral {
+        fold_captures(me, sequence);
+    }
+    suppress_known(state.views.entry(me).or_default(), me, sequence);
+
+    // The passes above may have replaced things; restore the sorting order.
+    sequence.sort_unstable();
+    truncate_nops(sequence);
 
     r
 }
 
 fn truncate_nops(sorted_sequence: &mut Vec<Msg>) {
-    let newlen = sorted_sequence.iter().rposition(|msg| *msg != Msg::Nop).unwrap_or(0);
+    let newlen = sorted_sequence.iter().rposition(|msg| *msg != Msg::Nop).map(|i| i + 1).unwrap_or(0);
     sorted_sequence.truncate(newlen);
 }
+
+/// Replace messages that are made irrelevant by later messages with `Nop`s
+///
+///  - Tile ownership and digits: only the last update for each tile matters
+///    (in the spectator view, digits are captures, so they also count as ownership)
+///  - Tile kinds, flags, smoke: only the last update for each tile matters
+///  - Cities: only the last update of each kind for each city matters
+///  - Items: no point revealing an item that explodes later
+fn remove_superseded(me: PlayerId, sequence: &mut [Msg]) {
+    let mut seen_owner = HashSet::new();
+    let mut seen_digit = HashSet::new();
+    let mut seen_kind = HashSet::new();
+    let mut seen_flag = HashSet::new();
+    let mut seen_smoke = HashSet::new();
+    let mut seen_money = HashSet::new();
+    let mut seen_res = HashSet::new();
+    let mut seen_trade = HashSet::new();
+    let mut seen_explode = HashSet::new();
+    for msg in sequence.iter_mut().rev() {
+        let superseded = match *msg {
+            // the owner comes from the participation mask, so we cannot split
+            // it up; only the last update of the tile's owner matters
+            Msg::TileOwner { pos, .. } | Msg::Digit { pos, .. } | Msg::Capture { pos, .. }
+                if me == PlayerId::Neutral => !seen_owner.insert(pos),
+            Msg::TileOwner { pos, .. } => !seen_owner.insert(pos),
+            Msg::Digit { pos, .. } => !seen_digit.insert(pos),
+            Msg::Capture { pos, digit } => {
+                let new_owner = seen_owner.insert(pos);
+                let new_digit = seen_digit.insert(pos);
+                match (new_owner, new_digit) {
+                    (true, true) => false,
+                    (false, false) => true,
+                    (true, false) => {
+                        *msg = Msg::TileOwner { pos, plid: me };
+                        false
+                    }
+                    (false, true) => {
+                        *msg = Msg::Digit { pos, digit };
+                        false
+                    }
+                }
+            }
+            Msg::CitUpdate { cit, res, money, income } => {
+                let new_money = seen_money.insert(cit);
+                let new_res = seen_res.insert(cit);
+                match (new_money, new_res) {
+                    (true, true) => false,
+                    (false, false) => true,
+                    (true, false) => {
+                        *msg = Msg::CitMoney { cit, money, income };
+                        false
+                    }
+                    (false, true) => {
+                        *msg = Msg::CitRes { cit, res };
+                        false
+                    }
+                }
+            }
+            Msg::CitMoney { cit, .. } => !seen_money.insert(cit),
+            Msg::CitRes { cit, .. } => !seen_res.insert(cit),
+            Msg::CitTrade { cit, .. } => !seen_trade.insert(cit),
+            Msg::TileKind { pos, .. } => !seen_kind.insert(pos),
+            Msg::Flag { pos, .. } => !seen_flag.insert(pos),
+            Msg::Smoke { pos } | Msg::Unsmoke { pos } => !seen_smoke.insert(pos),
+            Msg::RevealItem { pos, .. } => seen_explode.contains(&pos),
+            Msg::Explode { pos } => {
+                seen_explode.insert(pos);
+                false
+            }
+            _ => false,
+        };
+        if superseded {
+            *msg = Msg::Nop;
+        }
+    }
+}
+
+/// Combine `TileOwner` (to ourselves) + `Digit` into `Capture`
+///
+/// Expects a sorted sequence with no superseded updates.
+fn fold_captures(me: PlayerId, sorted_sequence: &mut [Msg]) {
+    let mut digits = HashMap::new();
+    for (i, msg) in sorted_sequence.iter().enumerate() {
+        if let Msg::Digit { pos, digit } = *msg {
+            digits.insert(pos, (i, digit));
+        }
+    }
+    for i in 0..sorted_sequence.len() {
+        let Msg::TileOwner { pos, plid } = sorted_sequence[i] else {
+            continue;
+        };
+        if plid != me {
+            continue;
+        }
+        let Some((i_digit, digit)) = digits.remove(&pos) else {
+            continue;
+        };
+        sorted_sequence[i] = Msg::Capture { pos, digit };
+        sorted_sequence[i_digit] = Msg::Nop;
+    }
+}
+
+/// Drop updates that the view already knows about from previous sequences,
+/// and remember the new information
+fn suppress_known(view: &mut ViewState, me: PlayerId, sequence: &mut [Msg]) {
+    for msg in sequence.iter_mut() {
+        let known = match *msg {
+            Msg::TileOwner { pos, plid } => view.owners.insert(pos, plid) == Some(plid),
+            Msg::Digit { pos, .. } | Msg::Capture { pos, .. } if me == PlayerId::Neutral => {
+                // we don't know who the owner is
+                view.owners.remove(&pos);
+                false
+            }
+            Msg::Digit { pos, digit } => view.digits.insert(pos, digit) == Some(digit),
+            Msg::Capture { pos, digit } => {
+                let known_owner = view.owners.insert(pos, me) == Some(me);
+                let known_digit = view.digits.insert(pos, digit) == Some(digit);
+                match (known_owner, known_digit) {
+                    (false, false) => false,
+                    (true, true) => true,
+                    (false, true) => {
+                        *msg = Msg::TileOwner { pos, plid: me };
+                        false
+                    }
+                    (true, false) => {
+                        *msg = Msg::Digit { pos, digit };
+                        false
+                    }
+                }
+            }
+            Msg::CitUpdate { cit, res, money, income } => {
+                let known_money = view.money.insert(cit, (money, income)) == Some((money, income));
+                let known_res = view.res.insert(cit, res) == Some(res);
+                match (known_money, known_res) {
+                    (false, false) => false,
+                    (true, true) => true,
+                    (false, true) => {
+                        *msg = Msg::CitMoney { cit, money, income };
+                        false
+                    }
+                    (true, false) => {
+                        *msg = Msg::CitRes { cit, res };
+                        false
+                    }
+                }
+            }
+            Msg::CitMoney { cit, money, income } => {
+                view.money.insert(cit, (money, income)) == Some((money, income))
+            }
+            Msg::CitRes { cit, res } => view.res.insert(cit, res) == Some(res),
+            Msg::Explode { pos } => {
+                // the tile is destroyed and the digits around it may change;
+                // the (square) 3x3 area covers the neighbors in either topology
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        let pos = Pos(pos.0.wrapping_add(y), pos.1.wrapping_add(x));
+                        view.owners.remove(&pos);
+                        view.digits.remove(&pos);
+                    }
+                }
+                false
+            }
+            _ => false,
+        };
+        if known {
+            *msg = Msg::Nop;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mw_common::prelude::*;
+
+    use crate::codec::encode_msgs;
+    use crate::msg::MsgItem;
+    use crate::state;
+
+    use super::*;
+
+    /// Something resembling the messages produced during a game
+    fn recorded_sequences(seed: u64, me: PlayerId) -> Vec<Vec<Msg>> {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(seed);
+        let rpos = |rng: &mut rand_pcg::Pcg32| Pos(rng.gen_range(-6..=6), rng.gen_range(-6..=6));
+        let owner = if me == PlayerId::Neutral { 1.into() } else { me };
+        let mut seqs = vec![];
+        for _ in 0..200 {
+            let mut seq = vec![];
+            let mut exploded = HashSet::new();
+            for _ in 0..rng.gen_range(1..30) {
+                let pos = rpos(&mut rng);
+                match rng.gen_range(0..11) {
+                    0..=2 => {
+                        // a capture, as generated by the game
+                        seq.push(Msg::TileOwner { pos, plid: owner });
+                        seq.push(Msg::Digit { pos, digit: rng.gen_range(0..7) });
+                    }
+                    3 => seq.push(Msg::TileOwner { pos, plid: rng.gen_range(1..4).into() }),
+                    4..=5 => seq.push(Msg::Digit { pos, digit: rng.gen_range(0..4) }),
+                    6 => seq.push(Msg::CitUpdate {
+                        cit: rng.gen_range(0..3),
+                        res: rng.gen_range(0..3),
+                        money: 100,
+                        income: rng.gen_range(0..2),
+                    }),
+                    7 if rng.gen_bool(0.5) => seq.push(Msg::CitMoney {
+                        cit: rng.gen_range(0..3),
+                        money: rng.gen_range(100..102),
+                        income: 1,
+                    }),
+                    7 => if rng.gen_bool(0.5) {
+                        seq.push(Msg::Smoke { pos });
+                    } else {
+                        seq.push(Msg::Unsmoke { pos });
+                    },
+                    // nothing can appear on a tile after it explodes
+                    8 if !exploded.contains(&pos) => {
+                        seq.push(Msg::RevealItem { pos, item: MsgItem::Mine });
+                        if rng.gen_bool(0.5) {
+                            seq.push(Msg::Explode { pos });
+                            exploded.insert(pos);
+                        }
+                    }
+                    9 => seq.push(Msg::Capture { pos, digit: rng.gen_range(0..7) }),
+                    _ => seq.push(Msg::Tremor),
+                }
+            }
+            seqs.push(seq);
+        }
+        seqs
+    }
+
+    fn check_harness(seed: u64, me: PlayerId) {
+        let spect = me == PlayerId::Neutral;
+        let owner = if spect { 1.into() } else { me };
+        let mut state = OptimizerState::default();
+        let mut view_orig = state::ViewState::default();
+        let mut view_opt = state::ViewState::default();
+        let mut size_orig = 0;
+        let mut size_opt = 0;
+        for seq in recorded_sequences(seed, me) {
+            let mut opt = seq.clone();
+            optimize_mwseq_lossy(&mut state, me, &mut opt);
+            let mut buf = vec![];
+            encode_msgs(&seq, &mut buf).unwrap();
+            size_orig += buf.len();
+            buf.clear();
+            encode_msgs(&opt, &mut buf).unwrap();
+            size_opt += buf.len();
+            for msg in seq.iter() {
+                view_orig.apply(msg, spect, owner);
+            }
+            for msg in opt.iter() {
+                view_opt.apply(msg, spect, owner);
+            }
+            assert_eq!(view_orig.msgs(me), view_opt.msgs(me));
+            // transient effects are not part of the state
+            let transient = |msgs: &[Msg]| -> HashSet<Msg> {
+                msgs.iter().filter(|msg| matches!(msg, Msg::Explode { .. } | Msg::Tremor)).cloned().collect()
+            };
+            assert_eq!(transient(&seq), transient(&opt));
+        }
+        assert!(size_opt < size_orig * 3 / 4, "{} -> {}", size_orig, size_opt);
+    }
+
+    #[test]
+    fn harness_player() {
+        check_harness(1503, 1.into());
+        check_harness(1504, 3.into());
+    }
+
+    #[test]
+    fn harness_spect() {
+        check_harness(1505, PlayerId::Neutral);
+    }
+
+    #[test]
+    fn truncate() {
+        let mut seq = vec![Msg::Tremor, Msg::Nop, Msg::Nop];
+        truncate_nops(&mut seq);
+        assert_eq!(seq, vec![Msg::Tremor]);
+        let mut seq = vec![Msg::Nop];
+        truncate_nops(&mut seq);
+        assert!(seq.is_empty());
+    }
+
+    #[test]
+    fn fold() {
+        let me = PlayerId::from(2);
+        let pos = Pos(1, 1);
+        let mut seq = vec![
+            Msg::Digit { pos, digit: 3 },
+            Msg::TileOwner { pos, plid: me },
+            Msg::TileOwner { pos: Pos(0, 0), plid: 1.into() },
+            Msg::Digit { pos: Pos(0, 0), digit: 1 },
+        ];
+        optimize_mwseq_lossy(&mut OptimizerState::default(), me, &mut seq);
+        assert_eq!(seq, vec![
+            Msg::Capture { pos, digit: 3 },
+            Msg::TileOwner { pos: Pos(0, 0), plid: 1.into() },
+            Msg::Digit { pos: Pos(0, 0), digit: 1 },
+        ]);
+    }
+
+    #[test]
+    fn superseded() {
+        let me = PlayerId::from(1);
+        let pos = Pos(1, 1);
+        let mut seq = vec![
+            Msg::Digit { pos, digit: 3 },
+            Msg::CitUpdate { cit: 0, res: 1, money: 5, income: 1 },
+            Msg::RevealItem { pos, item: MsgItem::Mine },
+            Msg::Digit { pos, digit: 2 },
+            Msg::Explode { pos },
+            Msg::CitUpdate { cit: 0, res: 2, money: 7, income: 1 },
+        ];
+        optimize_mwseq_lossy(&mut OptimizerState::default(), me, &mut seq);
+        assert_eq!(seq, vec![
+            Msg::Digit { pos, digit: 2 },
+            Msg::CitUpdate { cit: 0, res: 2, money: 7, income: 1 },
+            Msg::Explode { pos },
+        ]);
+    }
+
+    #[test]
+    fn cit_split() {
+        let me = PlayerId::from(1);
+        let mut state = OptimizerState::default();
+        let mut seq = vec![
+            Msg::CitUpdate { cit: 0, res: 1, money: 5, income: 1 },
+            Msg::CitRes { cit: 0, res: 2 },
+        ];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        assert_eq!(seq, vec![
+            Msg::CitMoney { cit: 0, money: 5, income: 1 },
+            Msg::CitRes { cit: 0, res: 2 },
+        ]);
+        let mut seq = vec![Msg::CitUpdate { cit: 0, res: 3, money: 5, income: 1 }];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        assert_eq!(seq, vec![Msg::CitRes { cit: 0, res: 3 }]);
+    }
+
+    #[test]
+    fn known_state() {
+        let me = PlayerId::from(1);
+        let pos = Pos(1, 1);
+        let mut state = OptimizerState::default();
+        let mut seq = vec![Msg::Capture { pos, digit: 3 }];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        assert_eq!(seq, vec![Msg::Capture { pos, digit: 3 }]);
+        let mut seq = vec![Msg::Capture { pos, digit: 4 }];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        assert_eq!(seq, vec![Msg::Digit { pos, digit: 4 }]);
+        let mut seq = vec![Msg::Digit { pos, digit: 4 }, Msg::TileOwner { pos, plid: me }];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        assert!(seq.is_empty());
+        // other views are tracked separately
+        let mut seq = vec![Msg::Digit { pos, digit: 4 }];
+        optimize_mwseq_lossy(&mut state, 2.into(), &mut seq);
+        assert_eq!(seq, vec![Msg::Digit { pos, digit: 4 }]);
+    }
+
+    #[test]
+    fn explode_forgets() {
+        let me = PlayerId::from(1);
+        let pos = Pos(1, 1);
+        let mut state = OptimizerState::default();
+        let mut seq = vec![Msg::Capture { pos, digit: 3 }];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        let mut seq = vec![Msg::Explode { pos: Pos(1, 2) }];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        // the neighbor's state is no longer assumed to be known
+        let mut seq = vec![Msg::Capture { pos, digit: 3 }];
+        optimize_mwseq_lossy(&mut state, me, &mut seq);
+        assert_eq!(seq, vec![Msg::Capture { pos, digit: 3 }]);
+    }
+}
@@ -0,0 +1,352 @@
+//! Replay Files
+//!
+//! A spectator stream (Initialization Sequence + frames), with a file header
+//! containing checksums, and optionally LZ4-compressed frames data.
+//! See the "Spectator/Replay Stream Format" documentation.
+
+use std::hash::Hasher;
+
+use mw_common::game::TileKind;
+use mw_common::grid::{Hex, Pos, Sq, Topology};
+use thiserror::Error;
+
+use crate::frame::FrameReader;
+use crate::init::{split_lens, InitDecodeError, InitEncodeError, InitSequence, InitTile};
+
+/// Length of the file header
+const FILE_HEADER_LEN: usize = 32;
+
+/// A replay file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayFile {
+    pub init: InitSequence,
+    /// The encoded (uncompressed) frames data
+    pub frames: Vec<u8>,
+}
+
+/// The file header of a replay file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayHeader {
+    /// Checksum of the rest of the file header (incl. the other checksums)
+    /// + Initialization Sequence header
+    pub checksum_header: u64,
+    /// Checksum of the Initialization Sequence data payload
+    pub checksum_init: u64,
+    /// Checksum of the frames data (as stored in the file)
+    pub checksum_frames: u64,
+    /// Length of the frames data, as stored in the file
+    pub len_compressed: u32,
+    /// Length of the frames data, after decompression
+    pub len_raw: u32,
+}
+
+impl ReplayHeader {
+    /// Parse the file header from the start of a replay file
+    ///
+    /// No validation is done.
+    pub fn decode(data: &[u8]) -> Result<Self, ReplayDecodeError> {
+        let header = data.get(..FILE_HEADER_LEN).ok_or(ReplayDecodeError::Truncated)?;
+        let u64_at = |i: usize| u64::from_be_bytes(header[i..(i + 8)].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_be_bytes(header[i..(i + 4)].try_into().unwrap());
+        Ok(ReplayHeader {
+            checksum_header: u64_at(0),
+            checksum_init: u64_at(8),
+            checksum_frames: u64_at(16),
+            len_compressed: u32_at(24),
+            len_raw: u32_at(28),
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayEncodeError {
+    #[error("Initialization Sequence: {0}")]
+    Init(#[from] InitEncodeError),
+    #[error("Frames data too big to be encoded")]
+    FramesTooBig,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayDecodeError {
+    #[error("Unexpected end of data")]
+    Truncated,
+    #[error("Unexpected data after the end of the frames data")]
+    TrailingData,
+    #[error("Checksum mismatch in the file header / Initialization Sequence header")]
+    HeaderChecksum,
+    #[error("Checksum mismatch in the Initialization Sequence data payload")]
+    InitChecksum,
+    #[error("Checksum mismatch in the frames data")]
+    FramesChecksum,
+    #[error("Initialization Sequence: {0}")]
+    Init(#[from] InitDecodeError),
+    #[error("Frames data length is invalid")]
+    BadFramesLen,
+    #[error("LZ4: {0}")]
+    Lz4(#[from] lz4_flex::block::DecompressError),
+}
+
+impl ReplayFile {
+    /// Iterate over the frames
+    pub fn frames(&self) -> FrameReader<'_> {
+        FrameReader::new(&self.frames, self.init.max_plid())
+    }
+
+    /// Encode into the binary format, appending to `out`
+    ///
+    /// If `compress` is true, the map data and the frames data will be LZ4
+    /// compressed (but only if that makes them smaller).
+    pub fn encode(&self, out: &mut Vec<u8>, compress: bool) -> Result<(), ReplayEncodeError> {
+        let mut init = vec![];
+        self.init.encode(&mut init, compress)?;
+        let (init_header_len, _) = split_lens(&init).unwrap();
+
+        let Ok(len_raw) = u32::try_from(self.frames.len()) else {
+            return Err(ReplayEncodeError::FramesTooBig);
+        };
+        let mut compressed = None;
+        if compress {
+            let dict = compression_dict(&self.init);
+            let data = lz4_flex::block::compress_with_dict(&self.frames, &dict);
+            if data.len() < self.frames.len() {
+                compressed = Some(data);
+            }
+        }
+        let frames = compressed.as_deref().unwrap_or(&self.frames);
+        let len_compressed = frames.len() as u32;
+
+        let mut lens = [0; 8];
+        lens[0..4].copy_from_slice(&len_compressed.to_be_bytes());
+        lens[4..8].copy_from_slice(&len_raw.to_be_bytes());
+
+        let checksum_init = seahash::hash(&init[init_header_len..]);
+        let checksum_frames = seahash::hash(frames);
+        let mut hasher = seahash::SeaHasher::new();
+        hasher.write(&checksum_init.to_be_bytes());
+        hasher.write(&checksum_frames.to_be_bytes());
+        hasher.write(&lens);
+        hasher.write(&init[..init_header_len]);
+        let checksum_header = hasher.finish();
+
+        out.extend_from_slice(&checksum_header.to_be_bytes());
+        out.extend_from_slice(&checksum_init.to_be_bytes());
+        out.extend_from_slice(&checksum_frames.to_be_bytes());
+        out.extend_from_slice(&lens);
+        out.extend_from_slice(&init);
+        out.extend_from_slice(frames);
+
+        Ok(())
+    }
+
+    /// Decode from the binary format
+    ///
+    /// Every checksum is validated before the respective part of the file is decoded.
+    pub fn decode(data: &[u8]) -> Result<Self, ReplayDecodeError> {
+        let header = ReplayHeader::decode(data)?;
+        let len_compressed = header.len_compressed as usize;
+        let len_raw = header.len_raw as usize;
+
+        let data_init = &data[FILE_HEADER_LEN..];
+        let (init_header_len, init_payload_len) = split_lens(data_init)
+            .ok_or(ReplayDecodeError::Truncated)?;
+        let init_header = data_init.get(..init_header_len)
+            .ok_or(ReplayDecodeError::Truncated)?;
+        let mut hasher = seahash::SeaHasher::new();
+        hasher.write(&data[8..FILE_HEADER_LEN]);
+        hasher.write(init_header);
+        if hasher.finish() != header.checksum_header {
+            return Err(ReplayDecodeError::HeaderChecksum);
+        }
+        let init_len = init_header_len + init_payload_len;
+        let init_payload = data_init.get(init_header_len..init_len)
+            .ok_or(ReplayDecodeError::Truncated)?;
+        if seahash::hash(init_payload) != header.checksum_init {
+            return Err(ReplayDecodeError::InitChecksum);
+        }
+        let (init, _) = InitSequence::decode(data_init)?;
+
+        let data_frames = &data_init[init_len..];
+        if data_frames.len() < len_compressed {
+            return Err(ReplayDecodeError::Truncated);
+        }
+        if data_frames.len() > len_compressed {
+            return Err(ReplayDecodeError::TrailingData);
+        }
+        if seahash::hash(data_frames) != header.checksum_frames {
+            return Err(ReplayDecodeError::FramesChecksum);
+        }
+        let frames = if len_compressed < len_raw {
+            let dict = compression_dict(&init);
+            let frames = lz4_flex::block::decompress_with_dict(data_frames, len_raw, &dict)?;
+            if frames.len() != len_raw {
+                return Err(ReplayDecodeError::BadFramesLen);
+            }
+            frames
+        } else if len_compressed == len_raw {
+            data_frames.to_vec()
+        } else {
+            return Err(ReplayDecodeError::BadFramesLen);
+        };
+
+        Ok(ReplayFile {
+            init,
+            frames,
+        })
+    }
+}
+
+/// Construct the dictionary to be used for compressing the frames data
+///
+/// It consists of all the mountain coordinates followed by all the land
+/// coordinates of the map, each in sorted order, encoded the same way as
+/// in update messages.
+pub fn compression_dict(init: &InitSequence) -> Vec<u8> {
+    let coords: Vec<(Pos, InitTile)> = match init.topology {
+        Topology::Hex => init.mapdata::<Hex>().unwrap()
+            .iter().map(|(c, t)| (c.into(), *t)).collect(),
+        Topology::Sq => init.mapdata::<Sq>().unwrap()
+            .iter().map(|(c, t)| (c.into(), *t)).collect(),
+    };
+    let mut mountains: Vec<Pos> = coords.iter()
+        .filter(|(_, t)| t.kind == TileKind::Mountain)
+        .map(|(pos, _)| *pos)
+        .collect();
+    let mut land: Vec<Pos> = coords.iter()
+        .filter(|(_, t)| t.kind.is_land())
+        .map(|(pos, _)| *pos)
+        .collect();
+    mountains.sort_unstable();
+    land.sort_unstable();
+    let mut dict = Vec::with_capacity((mountains.len() + land.len()) * 2);
+    for pos in mountains.into_iter().chain(land) {
+        let (y, x): (u8, u8) = pos.into();
+        dict.extend_from_slice(&[y, x]);
+    }
+    dict
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use mw_common::game::ItemKind;
+    use mw_common::grid::{Coord, MapData};
+    use mw_common::plid::Plids;
+    use mw_common::prelude::*;
+
+    use crate::frame::FrameWriter;
+    use crate::msg::Msg;
+
+    use super::*;
+
+    fn test_replay<C: Coord>() -> ReplayFile {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1503);
+        let map = MapData::new_with(10, |c: C| InitTile {
+            kind: if c.ring() > 8 {
+                TileKind::Water
+            } else {
+                *[TileKind::Regular, TileKind::Regular, TileKind::Mountain, TileKind::Forest]
+                    .choose(&mut rng).unwrap()
+            },
+            item: *[ItemKind::Safe, ItemKind::Safe, ItemKind::Mine]
+                .choose(&mut rng).unwrap(),
+            region: 0,
+        });
+        let init = InitSequence::new(&map, &[C::origin()], 2, vec![], |d| *d);
+        let coords: Vec<C> = map.iter()
+            .filter(|(_, t)| t.kind.is_land())
+            .map(|(c, _)| c)
+            .collect();
+        let mut w = FrameWriter::new(2);
+        for i in 0..200 {
+            let msgs = (0..rng.gen_range(1..6)).map(|_| Msg::Digit {
+                pos: (*coords.choose(&mut rng).unwrap()).into(),
+                digit: rng.gen_range(0..7),
+            }).collect();
+            w.push(Duration::from_millis(i * 250), Plids(0b110), msgs).unwrap();
+        }
+        ReplayFile {
+            init,
+            frames: w.finish().unwrap(),
+        }
+    }
+
+    fn roundtrip<C: Coord>(compress: bool) {
+        let replay = test_replay::<C>();
+        let mut out = vec![];
+        replay.encode(&mut out, compress).unwrap();
+        let replay2 = ReplayFile::decode(&out).unwrap();
+        assert_eq!(replay, replay2);
+        assert_eq!(replay.frames().count(), replay2.frames().count());
+        let header = ReplayHeader::decode(&out).unwrap();
+        assert_eq!(header.len_raw as usize, replay.frames.len());
+        assert_eq!(compress, header.len_compressed < header.len_raw);
+    }
+
+    #[test]
+    fn roundtrip_hex() {
+        roundtrip::<Hex>(false);
+        roundtrip::<Hex>(true);
+    }
+
+    #[test]
+    fn roundtrip_sq() {
+        roundtrip::<Sq>(false);
+        roundtrip::<Sq>(true);
+    }
+
+    #[test]
+    fn dict() {
+        let map = MapData::new_with(1, |c: Hex| InitTile {
+            kind: match c {
+                Hex(0, 1) => TileKind::Mountain,
+                Hex(-1, 1) | Hex(1, -1) => TileKind::Water,
+                _ => TileKind::Regular,
+            },
+            ..Default::default()
+        });
+        let init = InitSequence::new(&map, &[Hex(0, 0)], 1, vec![], |d| *d);
+        assert_eq!(compression_dict(&init), vec![
+            0x80, 0x81,
+            0x7F, 0x80,
+            0x80, 0x7F,
+            0x80, 0x80,
+            0x81, 0x80,
+        ]);
+    }
+
+    #[test]
+    fn checksums() {
+        let replay = test_replay::<Hex>();
+        let mut out = vec![];
+        replay.encode(&mut out, true).unwrap();
+        let init_header_len = split_lens(&out[FILE_HEADER_LEN..]).unwrap().0;
+
+        let mut bad = out.clone();
+        bad[26] ^= 0x01;
+        assert!(matches!(ReplayFile::decode(&bad), Err(ReplayDecodeError::HeaderChecksum)));
+        let mut bad = out.clone();
+        bad[FILE_HEADER_LEN + 2] ^= 0x01;
+        assert!(matches!(ReplayFile::decode(&bad), Err(ReplayDecodeError::HeaderChecksum)));
+        let mut bad = out.clone();
+        bad[FILE_HEADER_LEN + init_header_len + 1] ^= 0x01;
+        assert!(matches!(ReplayFile::decode(&bad), Err(ReplayDecodeError::InitChecksum)));
+        let mut bad = out.clone();
+        *bad.last_mut().unwrap() ^= 0x01;
+        assert!(matches!(ReplayFile::decode(&bad), Err(ReplayDecodeError::FramesChecksum)));
+        let mut bad = out.clone();
+        bad[3] ^= 0x01;
+        assert!(matches!(ReplayFile::decode(&bad), Err(ReplayDecodeError::HeaderChecksum)));
+        // the other checksums are covered by the header checksum
+        for i in [10, 21] {
+            let mut bad = out.clone();
+            bad[i] ^= 0x01;
+            assert!(matches!(ReplayFile::decode(&bad), Err(ReplayDecodeError::HeaderChecksum)));
+        }
+
+        assert!(matches!(ReplayFile::decode(&out[..out.len() - 1]), Err(ReplayDecodeError::Truncated)));
+        let mut long = out.clone();
+        long.push(0);
+        assert!(matches!(ReplayFile::decode(&long), Err(ReplayDecodeError::TrailingData)));
+    }
+}
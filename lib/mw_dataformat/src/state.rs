@@ -0,0 +1,344 @@
+//! Tracking the state of each view in a stream
+//!
+//! Applying messages to a [`ViewState`] accumulates everything a client
+//! would know from them, ignoring transient effects (explosions, chat, ...).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use mw_common::grid::Pos;
+use mw_common::plid::{PlayerId, Plids};
+
+use crate::frame::Frame;
+use crate::msg::{Msg, MsgItem, MsgPlayer, MsgStructureKind, MsgTileKind};
+
+/// The state of every view in a spectator stream / replay
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StreamState {
+    views: BTreeMap<PlayerId, ViewState>,
+}
+
+/// Everything a view knows, that is not just a transient effect
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ViewState {
+    players: BTreeMap<(PlayerId, u8), MsgPlayer>,
+    kinds: BTreeMap<Pos, MsgTileKind>,
+    owners: BTreeMap<Pos, PlayerId>,
+    digits: BTreeMap<Pos, u8>,
+    flags: BTreeMap<Pos, (PlayerId, bool)>,
+    smoke: BTreeSet<Pos>,
+    items: BTreeMap<Pos, MsgItem>,
+    structures: BTreeMap<Pos, Structure>,
+    money: BTreeMap<u8, (u32, u16)>,
+    res: BTreeMap<u8, u16>,
+    trade: BTreeMap<u8, (u8, u8)>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Structure {
+    revealed: Option<MsgStructureKind>,
+    pending: Option<(MsgStructureKind, u16)>,
+    progress: Option<(u16, u16)>,
+    hp: Option<u8>,
+}
+
+/// Player statuses that replace each other
+///
+/// Returns `None` for transient things (pings, chat, stuns ...).
+fn player_slot(status: &MsgPlayer) -> Option<u8> {
+    match status {
+        MsgPlayer::Joined | MsgPlayer::Disconnected | MsgPlayer::Kicked => Some(0),
+        MsgPlayer::Protected | MsgPlayer::Unprotected => Some(1),
+        MsgPlayer::Eliminated | MsgPlayer::Surrendered => Some(2),
+        MsgPlayer::LivesRemain { .. } => Some(3),
+        MsgPlayer::MatchTimeRemain { .. } => Some(4),
+        MsgPlayer::GameResult { .. } => Some(5),
+        _ => None,
+    }
+}
+
+impl StreamState {
+    /// Update the state with the contents of a frame
+    pub fn apply(&mut self, frame: &Frame) {
+        // in the spectator view, captures are owned by the other plid in the mask
+        let spect_owner = frame.plids.iter(None)
+            .find(|plid| *plid != PlayerId::Neutral)
+            .unwrap_or(PlayerId::Neutral);
+        for (plid, msgs) in frame.views.iter() {
+            let owner = if *plid == PlayerId::Neutral { spect_owner } else { *plid };
+            let view = self.views.entry(*plid).or_default();
+            for msg in msgs {
+                view.apply(msg, *plid == PlayerId::Neutral, owner);
+            }
+        }
+    }
+
+    /// The state of the given view, if anything has been sent to it
+    pub fn view(&self, plid: PlayerId) -> Option<&ViewState> {
+        self.views.get(&plid)
+    }
+
+    /// Produce batches of messages that recreate the current state
+    ///
+    /// They are to be pushed to a `FrameWriter` in order, all at the same time.
+    pub fn snapshot(&self) -> Vec<(Plids, Vec<Msg>)> {
+        self.views.iter()
+            .map(|(plid, view)| (Plids::from(*plid), view.msgs(*plid)))
+            .filter(|(_, msgs)| !msgs.is_empty())
+            .collect()
+    }
+}
+
+impl ViewState {
+    /// Update the state with a message
+    ///
+    /// `owner` is the new owner of tiles in `Capture` messages (and `Digit`
+    /// messages, for the spectator view, if `spect` is true).
+    ///
+    /// The spectator view does not track digits; spectator clients calculate
+    /// them from the mine locations.
+    pub fn apply(&mut self, msg: &Msg, spect: bool, owner: PlayerId) {
+        match *msg {
+            Msg::Player { plid, ref status } => {
+                if let Some(slot) = player_slot(status) {
+                    self.players.insert((plid, slot), status.clone());
+                }
+            }
+            Msg::Capture { pos, digit } => {
+                self.owners.insert(pos, owner);
+                if !spect {
+                    self.digits.insert(pos, digit);
+                }
+            }
+            Msg::TileOwner { pos, plid } => {
+                self.owners.insert(pos, plid);
+            }
+            Msg::Digit { pos, digit } => {
+                if spect {
+                    self.owners.insert(pos, owner);
+                } else {
+                    self.digits.insert(pos, digit);
+                }
+            }
+            Msg::CitUpdate { cit, res, money, income } => {
+                self.money.insert(cit, (money, income));
+                self.res.insert(cit, res);
+            }
+            Msg::CitMoney { cit, money, income } => {
+                self.money.insert(cit, (money, income));
+            }
+            Msg::CitRes { cit, res } => {
+                self.res.insert(cit, res);
+            }
+            Msg::CitTrade { cit, export, import } => {
+                self.trade.insert(cit, (export, import));
+            }
+            Msg::RevealStructure { pos, kind } => {
+                self.structures.entry(pos).or_default().revealed = Some(kind);
+            }
+            Msg::StructureGone { pos } => {
+                self.structures.remove(&pos);
+            }
+            Msg::StructureHp { pos, hp } => {
+                self.structures.entry(pos).or_default().hp = Some(hp);
+            }
+            Msg::BuildNew { pos, kind, pts } => {
+                self.structures.entry(pos).or_default().pending = Some((kind, pts));
+            }
+            Msg::Construction { pos, current, rate } => {
+                self.structures.entry(pos).or_default().progress = Some((current, rate));
+            }
+            Msg::TileKind { pos, kind } => {
+                self.kinds.insert(pos, kind);
+            }
+            Msg::Flag { pos, plid, question } => {
+                if plid == PlayerId::Neutral {
+                    self.flags.remove(&pos);
+                } else {
+                    self.flags.insert(pos, (plid, question));
+                }
+            }
+            Msg::RevealItem { pos, item } => {
+                self.items.insert(pos, item);
+            }
+            Msg::Explode { pos } => {
+                // the tile converts to destroyed land
+                self.items.remove(&pos);
+                self.kinds.insert(pos, MsgTileKind::Destroyed);
+            }
+            Msg::Smoke { pos } => {
+                self.smoke.insert(pos);
+            }
+            Msg::Unsmoke { pos } => {
+                self.smoke.remove(&pos);
+            }
+            Msg::CitSpend { .. } | Msg::Tremor | Msg::Nop => {}
+        }
+    }
+
+    /// The kind of the tile, if it has changed since the start of the game
+    pub fn kind(&self, pos: Pos) -> Option<MsgTileKind> {
+        self.kinds.get(&pos).copied()
+    }
+
+    /// Who owns the tile
+    pub fn owner(&self, pos: Pos) -> PlayerId {
+        self.owners.get(&pos).copied().unwrap_or(PlayerId::Neutral)
+    }
+
+    /// The digit shown on the tile, if known
+    pub fn digit(&self, pos: Pos) -> Option<u8> {
+        self.digits.get(&pos).copied()
+    }
+
+    /// Who has placed a flag on the tile, if anyone, and if it is a question mark
+    pub fn flag(&self, pos: Pos) -> Option<(PlayerId, bool)> {
+        self.flags.get(&pos).copied()
+    }
+
+    /// The item on the tile, if it has been revealed
+    pub fn item(&self, pos: Pos) -> Option<MsgItem> {
+        self.items.get(&pos).copied()
+    }
+
+    /// Messages describing this view's state, in a canonical order
+    ///
+    /// `me` is the plid of the view, used to combine owner + digit into
+    /// `Capture` messages.
+    pub fn msgs(&self, me: PlayerId) -> Vec<Msg> {
+        let mut msgs = vec![];
+        for ((plid, _), status) in self.players.iter() {
+            msgs.push(Msg::Player { plid: *plid, status: status.clone() });
+        }
+        for (pos, kind) in self.kinds.iter() {
+            msgs.push(Msg::TileKind { pos: *pos, kind: *kind });
+        }
+        let captured = |pos: &Pos| self.owners.get(pos) == Some(&me);
+        for (pos, plid) in self.owners.iter() {
+            if *plid == PlayerId::Neutral {
+                continue;
+            }
+            match self.digits.get(pos) {
+                Some(digit) if captured(pos) => msgs.push(Msg::Capture { pos: *pos, digit: *digit }),
+                _ => msgs.push(Msg::TileOwner { pos: *pos, plid: *plid }),
+            }
+        }
+        for (pos, digit) in self.digits.iter() {
+            if !captured(pos) {
+                msgs.push(Msg::Digit { pos: *pos, digit: *digit });
+            }
+        }
+        for (pos, (plid, question)) in self.flags.iter() {
+            msgs.push(Msg::Flag { pos: *pos, plid: *plid, question: *question });
+        }
+        for pos in self.smoke.iter() {
+            msgs.push(Msg::Smoke { pos: *pos });
+        }
+        for (pos, item) in self.items.iter() {
+            msgs.push(Msg::RevealItem { pos: *pos, item: *item });
+        }
+        for (pos, s) in self.structures.iter() {
+            let pos = *pos;
+            if let Some(kind) = s.revealed {
+                msgs.push(Msg::RevealStructure { pos, kind });
+            }
+            if let Some((kind, pts)) = s.pending {
+                msgs.push(Msg::BuildNew { pos, kind, pts });
+            }
+            if let Some((current, rate)) = s.progress {
+                msgs.push(Msg::Construction { pos, current, rate });
+            }
+            if let Some(hp) = s.hp {
+                msgs.push(Msg::StructureHp { pos, hp });
+            }
+        }
+        for (cit, (money, income)) in self.money.iter() {
+            msgs.push(Msg::CitMoney { cit: *cit, money: *money, income: *income });
+        }
+        for (cit, res) in self.res.iter() {
+            msgs.push(Msg::CitRes { cit: *cit, res: *res });
+        }
+        for (cit, (export, import)) in self.trade.iter() {
+            msgs.push(Msg::CitTrade { cit: *cit, export: *export, import: *import });
+        }
+        msgs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mw_common::prelude::*;
+
+    use crate::frame::{FrameReader, FrameWriter};
+
+    use super::*;
+
+    fn apply_all(data: &[u8]) -> StreamState {
+        let mut state = StreamState::default();
+        for frame in FrameReader::new(data, 3) {
+            state.apply(&frame.unwrap());
+        }
+        state
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1503);
+        let all = Plids(0b1111);
+        let mut w = FrameWriter::new(3);
+        for i in 0..300 {
+            let pos = Pos(rng.gen_range(-4..4), rng.gen_range(-4..4));
+            let plid = PlayerId::from(rng.gen_range(1..4));
+            let (plids, msg) = match rng.gen_range(0..8) {
+                0 => (Plids::with_spect(plid), Msg::Capture { pos, digit: rng.gen_range(0..8) }),
+                1 => (all, Msg::TileOwner { pos, plid: PlayerId::Neutral }),
+                2 => (Plids::from(plid), Msg::Digit { pos, digit: rng.gen_range(0..8) }),
+                3 => (all, Msg::Explode { pos }),
+                4 => (Plids::from(plid), Msg::RevealItem { pos, item: MsgItem::Mine }),
+                5 => (Plids::from(plid), Msg::CitMoney { cit: 0, money: rng.gen_range(0..100), income: 1 }),
+                6 => (all, Msg::Player { plid, status: MsgPlayer::Eliminated }),
+                _ => (all, Msg::BuildNew { pos, kind: MsgStructureKind::Road, pts: 10 }),
+            };
+            w.push(Duration::from_millis(i * 10), plids, vec![msg]).unwrap();
+        }
+        let state = apply_all(&w.finish().unwrap());
+
+        let mut w = FrameWriter::new(3);
+        for (plids, msgs) in state.snapshot() {
+            w.push(Duration::ZERO, plids, msgs).unwrap();
+        }
+        let state2 = apply_all(&w.finish().unwrap());
+        for plid in 0..4 {
+            let plid = PlayerId::from(plid);
+            assert_eq!(state.view(plid).unwrap().msgs(plid), state2.view(plid).unwrap().msgs(plid));
+        }
+    }
+
+    #[test]
+    fn game_result_kept() {
+        let plid = PlayerId::from(2);
+        let result = MsgPlayer::GameResult {
+            rank: 1,
+            tiles: 30,
+            mines_hit: 0,
+            lives: 1,
+            eliminated_secs: None,
+        };
+        let mut view = ViewState::default();
+        view.apply(&Msg::Player { plid, status: MsgPlayer::Eliminated }, true, PlayerId::Neutral);
+        view.apply(&Msg::Player { plid, status: result.clone() }, true, PlayerId::Neutral);
+        assert_eq!(view.msgs(PlayerId::Neutral), vec![
+            Msg::Player { plid, status: MsgPlayer::Eliminated },
+            Msg::Player { plid, status: result },
+        ]);
+    }
+
+    #[test]
+    fn explode_destroys() {
+        let pos = Pos(1, 2);
+        let mut view = ViewState::default();
+        view.apply(&Msg::RevealItem { pos, item: MsgItem::Mine }, false, 1.into());
+        view.apply(&Msg::Explode { pos }, false, 1.into());
+        assert_eq!(view.kind(pos), Some(MsgTileKind::Destroyed));
+        assert_eq!(view.msgs(1.into()), vec![Msg::TileKind { pos, kind: MsgTileKind::Destroyed }]);
+    }
+}
@@ -8,19 +8,89 @@ use mw_common::driver::*;
 
 use modular_bitfield::prelude::*;
 
+use crate::solver::{play, Deduction, Solver, SolverTile};
+
+pub mod solver;
+
+/// How many times to re-roll a board that needs guessing, before repairing it
+const NO_GUESS_REROLLS: u32 = 8;
+
 /// Settings that can be configured for a session of the Minesweeper game mode
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MinesweeperSettings {
     /// Each plid will get eliminated from the game when they step on a mine this many times.
     pub n_lives: u8,
-    /// 1 for singleplayer or co-op. >1 for PvP, Duos, etc.
+    /// 1 for singleplayer. >1 for co-op, PvP, Duos, etc.
     pub n_plids: u8,
+    /// Which plids play together as one, for co-op
+    ///
+    /// Entry `i` is the team of plid `i+1`. A team is identified by the plid
+    /// whose color it plays as. Teammates share territory, lives, and flags.
+    /// Plids that are not listed (or have an invalid team) play on their own.
+    /// A team is only valid if its own plid is listed as playing for it.
+    #[serde(default)]
+    pub teams: Vec<u8>,
     /// If nonzero, limit the maximum time allowed for the game.
     pub time_limit_secs: u16,
     /// Probability of a mine appearing on a tile.
     pub mine_density: u8,
     /// Probability a mine being replaced by a decoy instead.
     pub prob_decoy: u8,
+    /// Seed for placing the mines and decoys. Random if not set.
+    ///
+    /// The same seed, settings, and map always give the same board.
+    pub seed: Option<u64>,
+    /// Generate a board that can be solved without guessing
+    ///
+    /// The board is (re)generated when the first tile of the game is explored,
+    /// so that it can be solved starting from there. Dense boards may end up
+    /// with fewer mines than `mine_density` would give.
+    #[serde(default)]
+    pub no_guess: bool,
+    /// Lives taken away for every hint
+    ///
+    /// A hint is refused if it would take away the last life.
+    #[serde(default)]
+    pub hint_penalty_lives: u8,
+    /// Seconds taken off the time limit for every hint (if there is a time limit)
+    #[serde(default)]
+    pub hint_penalty_secs: u16,
+    /// What happens when exploring a digit that has as many flags around it
+    #[serde(default)]
+    pub chording: Chording,
+    /// Toggling a flag turns it into a question mark, before removing it
+    #[serde(default)]
+    pub question_marks: bool,
+    /// Who can see the flags (and question marks) that a player places
+    #[serde(default)]
+    pub flag_visibility: FlagVisibility,
+}
+
+/// Exploring one of your digits, when you have flagged as many of its neighbors
+///
+/// (regardless of the setting, `0` and `1*` digits always explore their neighbors)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Chording {
+    /// Does nothing
+    #[default]
+    Off,
+    /// Explores the unflagged neighbors, but only if the flags are correct
+    ///
+    /// Wrong flags make it silently do nothing, so this is more forgiving
+    /// (though it also hints that some flag is wrong).
+    Safe,
+    /// Explores the unflagged neighbors, stepping on any mines that were not flagged
+    Explode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FlagVisibility {
+    /// Everyone sees everyone's flags. Only one player can mark any given tile.
+    #[default]
+    All,
+    /// Only the player (and their teammates) see their flags, and the
+    /// spectator sees them all. Every player can mark any tile.
+    Own,
 }
 
 impl Default for MinesweeperSettings {
@@ -28,9 +98,17 @@ impl Default for MinesweeperSettings {
         Self {
             n_lives: 1,
             n_plids: 1,
+            teams: vec![],
             time_limit_secs: 0,
             mine_density: 96,
             prob_decoy: 48,
+            seed: None,
+            no_guess: false,
+            hint_penalty_lives: 0,
+            hint_penalty_secs: 0,
+            chording: Chording::Off,
+            question_marks: false,
+            flag_visibility: FlagVisibility::All,
         }
     }
 }
@@ -44,12 +122,19 @@ pub enum MinesweeperInputAction {
     ToggleFlag {
         pos: Pos,
     },
+    /// Ask for a tile that can be figured out from what the player knows
+    ///
+    /// Only available in singleplayer. The answer is a `PlayerEv::Hint`,
+    /// or nothing if there is no tile that can be figured out without guessing.
+    RequestHint,
 }
 
 #[derive(Clone, Copy)]
 pub struct PlayerData {
     n_owned: u16,
     n_lives: u8,
+    n_mines_hit: u8,
+    eliminated: Option<Instant>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -63,57 +148,111 @@ pub struct GameMinesweeper<C: Coord> {
     playerdata: Vec<PlayerData>,
     n_unexplored_tiles: u16,
     floodq: FloodQ,
+    rng: rand_pcg::Pcg64,
+    /// Has any tile been explored yet?
+    started: bool,
+    /// When the time limit runs out
+    deadline: Option<Instant>,
+    /// When the game started
+    start_time: Instant,
+    /// The team of each plid
+    teams: Vec<PlayerId>,
+    /// The flags of each plid (teams use the team's entry)
+    marks: Vec<HashMap<C, Mark>>,
+}
+
+/// Something a player placed on an unexplored tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Flag,
+    Question,
 }
 
 impl<C: Coord> GameMinesweeper<C> {
     pub fn new<D>(mut settings: MinesweeperSettings, map_src: &MapData<C, D>, f_tilekind: impl Fn(&D) -> TileKind) -> Self {
-        let mut n_unexplored_tiles = 0;
         settings.n_lives = settings.n_lives.max(1);
         settings.n_plids = settings.n_plids.max(1);
         let playerdata = vec![PlayerData {
             n_owned: 0,
             n_lives: settings.n_lives,
+            n_mines_hit: 0,
+            eliminated: None,
         }; settings.n_plids as usize];
-        let mut rng = thread_rng();
+        // a team must play as itself, so that no plid is both a team and a member of another
+        let valid_team = |team: u8| {
+            team != 0 && team <= settings.n_plids
+                && settings.teams.get(team as usize - 1) == Some(&team)
+        };
+        let teams = (1..=settings.n_plids).map(|i| {
+            match settings.teams.get(i as usize - 1) {
+                Some(&team) if valid_team(team) => PlayerId::from(team),
+                _ => PlayerId::from(i),
+            }
+        }).collect();
+        let marks = vec![HashMap::new(); settings.n_plids as usize];
+        let seed = *settings.seed.get_or_insert_with(|| thread_rng().gen());
         let mapdata = map_src.convert(|_, d| {
             let mut tile = TileData::default();
             tile.set_owner(0);
-            tile.set_flag(0);
-            let tilekind = f_tilekind(&d);
-            tile.set_kind(tilekind);
-            if tilekind.is_land() {
-                let item = if rng.gen_bool(settings.mine_density as f64 / 255.0) {
-                    if rng.gen_bool(settings.prob_decoy as f64 / 255.0) {
-                        n_unexplored_tiles += 1;
-                        ItemKind::Decoy
-                    } else {
-                        ItemKind::Mine
-                    }
-                } else {
-                    n_unexplored_tiles += 1;
-                    ItemKind::Safe
-                };
-                tile.set_item(item);
-            } else {
-                tile.set_item(ItemKind::Safe);
-            }
+            tile.set_kind(f_tilekind(d));
+            tile.set_item(ItemKind::Safe);
             tile
         });
-        Self {
+        let mut game = Self {
             settings,
             mapdata,
             playerdata,
-            n_unexplored_tiles,
+            n_unexplored_tiles: 0,
             floodq: Default::default(),
-        }
+            rng: rand_pcg::Pcg64::seed_from_u64(seed),
+            started: false,
+            deadline: None,
+            start_time: Instant::now(),
+            teams,
+            marks,
+        };
+        game.place_items(&[]);
+        game
+    }
+
+    /// The settings of this session, with the effective seed filled in
+    ///
+    /// Can be used to play the same board again.
+    pub fn settings(&self) -> &MinesweeperSettings {
+        &self.settings
+    }
+
+    /// The seed that was used to generate the board
+    pub fn seed(&self) -> u64 {
+        self.settings.seed.unwrap_or_default()
+    }
+
+    /// The plid that `plid` plays as (see `MinesweeperSettings::teams`)
+    pub fn team(&self, plid: PlayerId) -> PlayerId {
+        self.teams.get(plid.i().wrapping_sub(1)).copied().unwrap_or(plid)
+    }
+
+    /// All the plids that play as `team`
+    pub fn members(&self, team: PlayerId) -> PlidsBig {
+        self.teams.iter()
+            .enumerate()
+            .filter(|(_, t)| **t == team)
+            .fold(PlidsBig::from(team), |plids, (i, _)| plids + PlayerId::from(i as u8 + 1))
+    }
+
+    /// The data of every team
+    fn team_data(&self) -> impl Iterator<Item = (PlayerId, &PlayerData)> {
+        self.playerdata.iter()
+            .enumerate()
+            .map(|(i, p)| (PlayerId::from(i as u8 + 1), p))
+            .filter(|(plid, _)| self.teams.contains(plid))
     }
 }
 
 #[bitfield]
 #[derive(Clone, Copy, Default)]
 struct TileData {
-    owner: B4,
-    flag: B4,
+    owner: B8,
     item: ItemKind,
     kind: TileKind,
     #[skip] __: B3,
@@ -126,24 +265,26 @@ impl<C: Coord> Game for GameMinesweeper<C> {
     type SchedEvent = MinesweeperSchedEvent;
 
     fn init<H: Host<Self>>(&mut self, host: &mut H, _initdata: Self::InitData) {
+        self.start_time = Instant::now();
         // schedule an event for "game over by running out of time"
         if self.settings.time_limit_secs != 0 {
-            host.msg(Plids::all(true), MwEv::Player {
+            let deadline = Instant::now() + Duration::from_secs(self.settings.time_limit_secs as u64);
+            self.deadline = Some(deadline);
+            host.msg(PlidsBig::all(true), MwEv::Player {
                 plid: PlayerId::Neutral,
                 ev: PlayerEv::MatchTimeRemain {
                     secs: self.settings.time_limit_secs,
                 },
             });
-            host.sched(
-                Instant::now() + Duration::from_secs(self.settings.time_limit_secs as u64),
-                MinesweeperSchedEvent::GameOverOutOfTime
-            );
+            host.sched(deadline, MinesweeperSchedEvent::GameOverOutOfTime);
         }
     }
     fn input<H: Host<Self>>(&mut self, host: &mut H, plid: PlayerId, action: Self::InputAction) {
         if u8::from(plid) > self.settings.n_plids || plid == PlayerId::Neutral {
             return;
         }
+        // teammates all play as the same plid
+        let plid = self.team(plid);
         if let Some(playerdata) = self.playerdata.get(plid.i()-1) {
             if playerdata.n_lives == 0 {
                 return;
@@ -158,19 +299,23 @@ impl<C: Coord> Game for GameMinesweeper<C> {
             MinesweeperInputAction::ToggleFlag { pos } => {
                 self.flag(host, plid, pos.into());
             }
+            MinesweeperInputAction::RequestHint => {
+                self.hint(host, plid);
+            }
         }
     }
     fn unsched<H: Host<Self>>(&mut self, host: &mut H, event: Self::SchedEvent) {
         match event {
             MinesweeperSchedEvent::GameOverOutOfTime => {
-                for (i, playerdata) in self.playerdata.iter().enumerate() {
+                for (plid, playerdata) in self.team_data() {
                     if playerdata.n_lives > 0 {
-                        host.msg(Plids::all(true), MwEv::Player {
-                            plid: PlayerId::from(i as u8 + 1),
+                        host.msg(PlidsBig::all(true), MwEv::Player {
+                            plid,
                             ev: PlayerEv::Eliminated,
                         });
                     }
                 }
+                self.send_results(host);
                 host.game_over();
             }
         }
@@ -178,35 +323,305 @@ impl<C: Coord> Game for GameMinesweeper<C> {
 }
 
 impl<C: Coord> GameMinesweeper<C> {
+    /// Randomly place mines and decoys on all land tiles, except `clear`
+    fn place_items(&mut self, clear: &[C]) {
+        let mine_density = self.settings.mine_density as f64 / 255.0;
+        let prob_decoy = self.settings.prob_decoy as f64 / 255.0;
+        for (c, tile) in self.mapdata.iter_mut() {
+            let item = if !tile.kind().is_land() {
+                ItemKind::Safe
+            } else if self.rng.gen_bool(mine_density) {
+                if self.rng.gen_bool(prob_decoy) {
+                    ItemKind::Decoy
+                } else {
+                    ItemKind::Mine
+                }
+            } else {
+                ItemKind::Safe
+            };
+            tile.set_item(if clear.contains(&c) { ItemKind::Safe } else { item });
+        }
+        self.count_unexplored_tiles();
+    }
+    fn count_unexplored_tiles(&mut self) {
+        self.n_unexplored_tiles = self.mapdata.iter()
+            .filter(|(_, tile)| tile.kind().is_land() && tile.item() != ItemKind::Mine)
+            .count() as u16;
+    }
+    /// Make sure the board can be solved without guessing, starting at `start`
+    ///
+    /// First tries new boards, then moves away (or removes) the items that
+    /// get in the way. Items are only moved to places where they are not next
+    /// to any other item, so dense boards lose some of their items.
+    ///
+    /// Always succeeds: every step that does not move an item removes one,
+    /// and a board without items can always be solved.
+    fn make_no_guess(&mut self, start: C) {
+        let clear: Vec<C> = std::iter::once(start)
+            .chain(start.iter_n1_in(&self.mapdata))
+            .collect();
+        for _ in 0..NO_GUESS_REROLLS {
+            self.place_items(&clear);
+            if play(&self.mapdata, start, |t| (t.kind(), t.item())).is_ok() {
+                return;
+            }
+        }
+        // items are moved elsewhere at most this many times, and removed after that
+        let mut moves_left = self.mapdata.iter().filter(|(_, t)| t.kind().is_land()).count();
+        loop {
+            let Err(mut stuck) = play(&self.mapdata, start, |t| (t.kind(), t.item())) else {
+                break;
+            };
+            // fix every place where the player is stuck, as long as they are
+            // far enough apart not to affect each other
+            let mut moved: Vec<C> = vec![];
+            stuck.frontier.shuffle(&mut self.rng);
+            for f in stuck.frontier.iter() {
+                if moved.iter().any(|c| c.distance(*f) <= 2) {
+                    continue;
+                }
+                // the items that the digits next to it are counting
+                let mut hazards: Vec<C> = f.iter_n1_in(&self.mapdata)
+                    .filter(|c| stuck.explored.contains(c))
+                    .flat_map(|c| c.iter_n1_in(&self.mapdata))
+                    .filter(|c| !stuck.explored.contains(c) && self.mapdata[*c].item() != ItemKind::Safe)
+                    .collect();
+                hazards.sort();
+                hazards.dedup();
+                let Some(&from) = hazards.choose(&mut self.rng) else {
+                    continue;
+                };
+                moved.push(*f);
+                let item = self.mapdata[from].item();
+                self.mapdata[from].set_item(ItemKind::Safe);
+                if moves_left > 0 {
+                    moves_left -= 1;
+                    // somewhere the player has not seen yet
+                    let to = self.mapdata.iter()
+                        .filter(|(c, tile)| {
+                            tile.kind().is_land() && tile.item() == ItemKind::Safe && *c != from
+                                && !stuck.explored.contains(c)
+                                && c.iter_n1_in(&self.mapdata).all(|c2| {
+                                    !stuck.explored.contains(&c2) && self.mapdata[c2].item() == ItemKind::Safe
+                                })
+                        })
+                        .map(|(c, _)| c)
+                        .choose(&mut self.rng);
+                    if let Some(to) = to {
+                        self.mapdata[to].set_item(item);
+                    }
+                }
+            }
+            if moved.is_empty() {
+                // nothing to move out of the way; remove the items on the
+                // frontier, or every unexplored item if there are none there
+                let mut blocking: Vec<C> = stuck.frontier.iter()
+                    .copied()
+                    .filter(|c| self.mapdata[*c].item() != ItemKind::Safe)
+                    .collect();
+                if blocking.is_empty() {
+                    blocking = self.mapdata.iter()
+                        .filter(|(c, tile)| tile.item() != ItemKind::Safe && !stuck.explored.contains(c))
+                        .map(|(c, _)| c)
+                        .collect();
+                }
+                for c in blocking {
+                    self.mapdata[c].set_item(ItemKind::Safe);
+                }
+            }
+        }
+        self.count_unexplored_tiles();
+    }
+    /// Find a tile that `plid` can figure out from the digits they can see
+    ///
+    /// Prefers tiles that are safe to explore. Returns whether the tile has a mine.
+    fn find_hint(&self, plid: PlayerId) -> Option<(C, bool)> {
+        let tiles = self.mapdata.convert(|c, tile| {
+            if !tile.kind().is_land() || (tile.owner() != 0 && tile.owner() != u8::from(plid)) {
+                SolverTile::Clear
+            } else if tile.owner() == u8::from(plid) {
+                let (digit, asterisk) = self.compute_digit(plid, c);
+                SolverTile::Digit { digit, asterisk }
+            } else {
+                SolverTile::Unknown
+            }
+        });
+        let mut solver = Solver::new(tiles);
+        solver.solve();
+        if let Some(c) = solver.safe_tiles().next() {
+            return Some((c, false));
+        }
+        // no point pointing out mines that the player has already flagged
+        self.mapdata.iter()
+            .find(|(c, _)| {
+                solver.deduction(*c) == Some(Deduction::Mine) && self.mark(plid, *c) != Some(Mark::Flag)
+            })
+            .map(|(c, _)| (c, true))
+    }
+    fn hint<H: Host<Self>>(&mut self, host: &mut H, plid: PlayerId) {
+        if self.team_data().count() != 1 {
+            return;
+        }
+        let penalty_lives = self.settings.hint_penalty_lives;
+        if penalty_lives > 0 && self.playerdata[plid.i()-1].n_lives <= penalty_lives {
+            return;
+        }
+        let Some((c, mine)) = self.find_hint(plid) else {
+            return;
+        };
+        if penalty_lives > 0 {
+            let playerdata = &mut self.playerdata[plid.i()-1];
+            playerdata.n_lives -= penalty_lives;
+            host.msg(PlidsBig::all(true), MwEv::Player {
+                plid,
+                ev: PlayerEv::LivesRemain {
+                    lives: playerdata.n_lives,
+                },
+            });
+        }
+        if let Some(deadline) = &mut self.deadline {
+            if self.settings.hint_penalty_secs > 0 {
+                *deadline -= Duration::from_secs(self.settings.hint_penalty_secs as u64);
+                host.desched_all(MinesweeperSchedEvent::GameOverOutOfTime);
+                host.sched(*deadline, MinesweeperSchedEvent::GameOverOutOfTime);
+                host.msg(PlidsBig::all(true), MwEv::Player {
+                    plid: PlayerId::Neutral,
+                    ev: PlayerEv::MatchTimeRemain {
+                        secs: deadline.saturating_duration_since(Instant::now()).as_secs() as u16,
+                    },
+                });
+            }
+        }
+        host.msg(self.members(plid), MwEv::Player {
+            plid,
+            ev: PlayerEv::Hint {
+                pos: c.into(),
+                mine,
+            },
+        });
+    }
     fn flag<H: Host<Self>>(&mut self, host: &mut H, plid: PlayerId, c: C) {
-        if c.ring() > self.mapdata.size() {
+        if !self.mapdata.contains(c) {
             return;
         }
         if !self.mapdata[c].kind().is_land() || self.mapdata[c].owner() != 0 {
             return;
         }
-        if self.mapdata[c].flag() == 0 {
-            if c.iter_n1().any(|c2| self.mapdata[c2].owner() == u8::from(plid)) {
-                self.mapdata[c].set_flag(u8::from(plid));
-                host.msg(Plids::all(true), MwEv::Map {
-                    pos: c.into(),
-                    ev: MapEv::Flag {
-                        plid,
+        let mark = match self.mark(plid, c) {
+            None => {
+                if self.settings.flag_visibility == FlagVisibility::All
+                    && self.marks.iter().any(|marks| marks.contains_key(&c))
+                {
+                    return;
+                }
+                if !c.iter_n1_in(&self.mapdata).any(|c2| self.mapdata[c2].owner() == u8::from(plid)) {
+                    return;
+                }
+                Some(Mark::Flag)
+            }
+            Some(Mark::Flag) if self.settings.question_marks => Some(Mark::Question),
+            Some(_) => None,
+        };
+        if let Some(mark) = mark {
+            self.marks[plid.i()-1].insert(c, mark);
+        } else {
+            self.marks[plid.i()-1].remove(&c);
+        }
+        self.send_mark(host, plid, c, mark);
+    }
+    /// What `plid` has placed on a tile
+    fn mark(&self, plid: PlayerId, c: C) -> Option<Mark> {
+        self.marks.get(plid.i().wrapping_sub(1))
+            .and_then(|marks| marks.get(&c))
+            .copied()
+    }
+    /// Tell everyone who can see it that `plid`'s mark on a tile changed
+    ///
+    /// With `FlagVisibility::Own`, the spectator can only show one mark per
+    /// tile, so when a mark is removed it gets whichever mark remains.
+    fn send_mark<H: Host<Self>>(&self, host: &mut H, plid: PlayerId, c: C, mark: Option<Mark>) {
+        let ev = |plid, mark: Option<Mark>| MwEv::Map {
+            pos: c.into(),
+            ev: MapEv::Flag {
+                plid: if mark.is_some() { plid } else { PlayerId::Neutral },
+                question: mark == Some(Mark::Question),
+            }
+        };
+        match self.settings.flag_visibility {
+            FlagVisibility::All => host.msg(PlidsBig::all(true), ev(plid, mark)),
+            FlagVisibility::Own => {
+                let spect = if mark.is_some() {
+                    ev(plid, mark)
+                } else {
+                    let remaining = self.marks.iter().enumerate()
+                        .find_map(|(i, marks)| Some((PlayerId::from(i as u8 + 1), *marks.get(&c)?)));
+                    match remaining {
+                        Some((plid2, mark2)) => ev(plid2, Some(mark2)),
+                        None => ev(plid, None),
                     }
-                });
+                };
+                if spect == ev(plid, mark) {
+                    host.msg(self.members(plid) + PlayerId::Neutral, spect);
+                } else {
+                    host.msg(self.members(plid), ev(plid, mark));
+                    host.msg(PlidsBig::from(PlayerId::Neutral), spect);
+                }
             }
-        } else if self.mapdata[c].flag() == u8::from(plid) {
-            self.mapdata[c].set_flag(0);
-            host.msg(Plids::all(true), MwEv::Map {
-                pos: c.into(),
-                ev: MapEv::Flag {
-                    plid: PlayerId::Neutral,
+        }
+    }
+    /// Remove everything that any player has placed on a tile
+    fn clear_marks<H: Host<Self>>(&mut self, host: &mut H, c: C) {
+        let removed: Vec<_> = (0..self.marks.len())
+            .filter(|&i| self.marks[i].remove(&c).is_some())
+            .collect();
+        for i in removed {
+            self.send_mark(host, PlayerId::from(i as u8 + 1), c, None);
+        }
+    }
+    /// Explore the neighbors of a digit that the player has not flagged
+    ///
+    /// Only if there are as many flags as the digit (see `Chording`).
+    fn chord<H: Host<Self>>(&mut self, host: &mut H, plid: PlayerId, c: C, digit: u8) {
+        let mut n_flags = 0;
+        let mut targets = vec![];
+        for c2 in c.iter_n1_in(&self.mapdata) {
+            if self.mapdata[c2].owner() != 0 || !self.mapdata[c2].kind().is_land() {
+                continue;
+            }
+            if self.mark(plid, c2) == Some(Mark::Flag) {
+                n_flags += 1;
+            } else {
+                targets.push(c2);
+            }
+        }
+        if n_flags != digit {
+            return;
+        }
+        if self.settings.chording == Chording::Safe
+            && targets.iter().any(|c2| self.mapdata[*c2].item() != ItemKind::Safe)
+        {
+            return;
+        }
+        for c2 in targets {
+            if self.playerdata[plid.i()-1].n_lives == 0 {
+                break;
+            }
+            // might have been captured by a flood from an earlier neighbor
+            if self.mapdata[c2].owner() != 0 {
+                continue;
+            }
+            match self.mapdata[c2].item() {
+                ItemKind::Safe => {
+                    self.capture_tile(host, plid, c2, true);
                 }
-            });
+                _ => {
+                    self.explode_player(host, plid, c2);
+                }
+            }
         }
     }
     fn explore_tile<H: Host<Self>>(&mut self, host: &mut H, plid: PlayerId, c: C) {
-        if c.ring() > self.mapdata.size() {
+        if !self.mapdata.contains(c) {
             return;
         }
 
@@ -214,12 +629,19 @@ impl<C: Coord> GameMinesweeper<C> {
             return;
         }
 
+        if !self.started {
+            self.started = true;
+            if self.settings.no_guess {
+                self.make_no_guess(c);
+            }
+        }
+
         // if this is the player's first tile, and it is not adjacent to another player's
         // territory, guarantee it to be safe (forgive any mine)
         if let Some(playerdata) = self.playerdata.get(plid.i()-1) {
             if playerdata.n_owned == 0 {
                 if self.mapdata[c].item() == ItemKind::Mine {
-                    if c.iter_n1().all(|c2| self.mapdata[c2].owner() == 0) {
+                    if c.iter_n1_in(&self.mapdata).all(|c2| self.mapdata[c2].owner() == 0) {
                         self.mapdata[c].set_item(ItemKind::Safe);
                     }
                 }
@@ -231,14 +653,13 @@ impl<C: Coord> GameMinesweeper<C> {
         if owner == u8::from(plid) {
             let (digit, asterisk) = self.compute_digit(plid, c);
             if digit == 0 {
-                for c2 in c.iter_n1() {
+                for c2 in c.iter_n1_in(&self.mapdata) {
                     if self.mapdata[c2].owner() == 0 {
                         self.capture_tile(host, plid, c2, true);
                     }
                 }
-            }
-            if digit == 1 && asterisk {
-                for c2 in c.iter_n1() {
+            } else if digit == 1 && asterisk {
+                for c2 in c.iter_n1_in(&self.mapdata) {
                     if self.mapdata[c2].owner() == 0 {
                         match self.mapdata[c2].item() {
                             ItemKind::Safe => {
@@ -250,6 +671,8 @@ impl<C: Coord> GameMinesweeper<C> {
                         }
                     }
                 }
+            } else if self.settings.chording != Chording::Off {
+                self.chord(host, plid, c, digit);
             }
         } else if owner == 0 {
             match self.mapdata[c].item() {
@@ -273,27 +696,19 @@ impl<C: Coord> GameMinesweeper<C> {
             }
             self.n_unexplored_tiles -= 1;
             self.mapdata[c].set_owner(u8::from(plid));
-            if self.mapdata[c].flag() != 0 {
-                self.mapdata[c].set_flag(0);
-                host.msg(Plids::all(true), MwEv::Map {
-                    pos: c.into(),
-                    ev: MapEv::Flag {
-                        plid: PlayerId::Neutral,
-                    }
-                });
-            }
-            host.msg(Plids::all(true), MwEv::Map {
+            self.clear_marks(host, c);
+            host.msg(PlidsBig::all(true), MwEv::Map {
                 pos: c.into(),
                 ev: MapEv::Owner {
                     plid,
                 },
             });
             let digit = self.compute_send_digit(host, plid, c);
-            for c2 in c.iter_n1() {
+            for c2 in c.iter_n1_in(&self.mapdata) {
                 let kind = self.mapdata[c2].kind();
                 if kind.is_rescluster() {
                     self.mapdata[c2].set_owner(u8::from(plid));
-                    host.msg(Plids::all(true), MwEv::Map {
+                    host.msg(PlidsBig::all(true), MwEv::Map {
                         pos: c2.into(),
                         ev: MapEv::Owner {
                             plid,
@@ -302,9 +717,12 @@ impl<C: Coord> GameMinesweeper<C> {
                     self.floodq.clear();
                     self.floodq.push_back(c2.into());
                     flood(&mut self.floodq, |c3, _| {
-                        if self.mapdata[c3].kind() == kind && self.mapdata[c3].owner() != u8::from(plid) {
+                        let Some(tile) = self.mapdata.get(c3) else {
+                            return FloodSelect::No;
+                        };
+                        if tile.kind() == kind && tile.owner() != u8::from(plid) {
                             self.mapdata[c3].set_owner(u8::from(plid));
-                            host.msg(Plids::all(true), MwEv::Map {
+                            host.msg(PlidsBig::all(true), MwEv::Map {
                                 pos: c3.into(),
                                 ev: MapEv::Owner {
                                     plid,
@@ -320,16 +738,42 @@ impl<C: Coord> GameMinesweeper<C> {
                     q.push(c2);
                 }
             }
-            if let Some(next_c) = q.pop() {
+            // a tile can be queued more than once, by different neighbors
+            let next = std::iter::from_fn(|| q.pop())
+                .find(|c2| self.mapdata[*c2].owner() == 0);
+            if let Some(next_c) = next {
                 c = next_c;
             } else {
                 break;
             }
         }
         if self.n_unexplored_tiles == 0 {
+            self.send_results(host);
             host.game_over();
         }
     }
+    /// Tell everyone how every team did, ranked by the number of tiles they explored
+    fn send_results<H: Host<Self>>(&self, host: &mut H) {
+        for (plid, playerdata) in self.team_data() {
+            let rank = self.team_data()
+                .filter(|(_, other)| other.n_owned > playerdata.n_owned)
+                .count() + 1;
+            let eliminated_secs = playerdata.eliminated.map(|t| {
+                let secs = t.saturating_duration_since(self.start_time).as_secs();
+                secs.min(u16::MAX as u64 - 1) as u16
+            });
+            host.msg(PlidsBig::all(true), MwEv::Player {
+                plid,
+                ev: PlayerEv::GameResult {
+                    rank: rank as u8,
+                    tiles: playerdata.n_owned,
+                    mines_hit: playerdata.n_mines_hit,
+                    lives: playerdata.n_lives,
+                    eliminated_secs,
+                },
+            });
+        }
+    }
     fn explode_player<H: Host<Self>>(&mut self, host: &mut H, plid: PlayerId, c: C) {
         let mut capture = true;
         match self.mapdata[c].item() {
@@ -338,22 +782,14 @@ impl<C: Coord> GameMinesweeper<C> {
             },
             // minesweeper mode has no flashes, treat them as decoys
             ItemKind::Decoy | ItemKind::Flashbang => {
-                if self.mapdata[c].flag() != 0 {
-                    self.mapdata[c].set_flag(0);
-                    host.msg(Plids::all(true), MwEv::Map {
-                        pos: c.into(),
-                        ev: MapEv::Flag {
-                            plid: PlayerId::Neutral,
-                        }
-                    });
-                }
-                host.msg(Plids::all(true), MwEv::Map {
+                self.clear_marks(host, c);
+                host.msg(PlidsBig::all(true), MwEv::Map {
                     pos: c.into(),
                     ev: MapEv::Item {
                         kind: ItemKind::Decoy,
                     },
                 });
-                host.msg(Plids::all(true), MwEv::Map {
+                host.msg(PlidsBig::all(true), MwEv::Map {
                     pos: c.into(),
                     ev: MapEv::Explode,
                 });
@@ -361,49 +797,44 @@ impl<C: Coord> GameMinesweeper<C> {
             ItemKind::Mine => {
                 // we now have an extra safe/explorable tile
                 self.n_unexplored_tiles += 1;
-                if self.mapdata[c].flag() != 0 {
-                    self.mapdata[c].set_flag(0);
-                    host.msg(Plids::all(true), MwEv::Map {
-                        pos: c.into(),
-                        ev: MapEv::Flag {
-                            plid: PlayerId::Neutral,
-                        }
-                    });
-                }
-                host.msg(Plids::all(true), MwEv::Map {
+                self.clear_marks(host, c);
+                host.msg(PlidsBig::all(true), MwEv::Map {
                     pos: c.into(),
                     ev: MapEv::Item {
                         kind: ItemKind::Mine,
                     },
                 });
-                host.msg(Plids::all(true), MwEv::Map {
+                host.msg(PlidsBig::all(true), MwEv::Map {
                     pos: c.into(),
                     ev: MapEv::Explode,
                 });
-                host.msg(Plids::all(true), MwEv::Map {
+                host.msg(PlidsBig::all(true), MwEv::Map {
                     pos: c.into(),
                     ev: MapEv::Tile {
                         kind: TileKind::Destroyed,
                     },
                 });
                 if let Some(playerdata) = self.playerdata.get_mut(plid.i()-1) {
+                    playerdata.n_mines_hit = playerdata.n_mines_hit.saturating_add(1);
                     if playerdata.n_lives > 0 {
                         playerdata.n_lives -= 1;
                     }
                     if playerdata.n_lives == 0 {
-                        host.msg(Plids::all(true), MwEv::Player {
+                        playerdata.eliminated = Some(Instant::now());
+                        host.msg(PlidsBig::all(true), MwEv::Player {
                             plid,
                             ev: PlayerEv::Eliminated,
                         });
                         capture = false;
                     }
-                    host.msg(Plids::all(true), MwEv::Player {
+                    host.msg(PlidsBig::all(true), MwEv::Player {
                         plid,
                         ev: PlayerEv::LivesRemain {
                             lives: playerdata.n_lives,
                         },
                     });
-                    if self.playerdata.iter().all(|p| p.n_lives == 0) {
+                    if self.team_data().all(|(_, p)| p.n_lives == 0) {
+                        self.send_results(host);
                         host.game_over();
                     }
                 }
@@ -421,7 +852,7 @@ impl<C: Coord> GameMinesweeper<C> {
             self.capture_tile(host, plid, c, false);
         }
     }
-    fn compute_digit(&mut self, plid: PlayerId, c: C) -> (u8, bool) {
+    fn compute_digit(&self, plid: PlayerId, c: C) -> (u8, bool) {
         let mut digit = 0;
         let mut asterisk = false;
         for c2 in c.iter_n1() {
@@ -443,7 +874,7 @@ impl<C: Coord> GameMinesweeper<C> {
     }
     fn compute_send_digit<H: Host<Self>>(&mut self, host: &mut H, plid: PlayerId, c: C) -> (u8, bool) {
         let (digit, asterisk) = self.compute_digit(plid, c);
-        host.msg(Plids::from(plid), MwEv::Map {
+        host.msg(self.members(plid), MwEv::Map {
             pos: c.into(),
             ev: MapEv::Digit {
                 digit, asterisk,
@@ -452,3 +883,587 @@ impl<C: Coord> GameMinesweeper<C> {
         (digit, asterisk)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn board<C: Coord>(seed: u64) -> String {
+        let map = MapData::<C, _>::new(4, TileKind::Regular);
+        let settings = MinesweeperSettings {
+            seed: Some(seed),
+            ..Default::default()
+        };
+        let game = GameMinesweeper::new(settings, &map, |k| *k);
+        assert_eq!(game.seed(), seed);
+        // in storage order
+        game.mapdata.iter().map(|(_, tile)| match tile.item() {
+            ItemKind::Safe => '.',
+            ItemKind::Mine => '*',
+            ItemKind::Decoy | ItemKind::Flashbang => 'd',
+        }).collect()
+    }
+
+    #[test]
+    fn seed_hex() {
+        assert_eq!(board::<Hex>(1503), board::<Hex>(1503));
+        assert_ne!(board::<Hex>(1503), board::<Hex>(1504));
+        assert_eq!(board::<Hex>(1503), GOLDEN_HEX);
+    }
+
+    #[test]
+    fn seed_sq() {
+        assert_eq!(board::<Sq>(42), board::<Sq>(42));
+        assert_eq!(board::<Sq>(42), GOLDEN_SQ);
+    }
+
+    #[test]
+    fn random_seed() {
+        let map = MapData::<Hex, _>::new(4, TileKind::Regular);
+        let game = GameMinesweeper::new(MinesweeperSettings::default(), &map, |k| *k);
+        let settings = game.settings().clone();
+        assert_eq!(settings.seed, Some(game.seed()));
+        let again = GameMinesweeper::new(settings, &map, |k| *k);
+        assert!(game.mapdata.data().iter().zip(again.mapdata.data())
+            .all(|(a, b)| a.item() == b.item()));
+    }
+
+    fn check_no_guess<C: Coord>(seed: u64, mine_density: u8) {
+        // a lake in the middle
+        let map = MapData::<C, _>::new_with(8, |c: C| {
+            if c.ring() == 2 { TileKind::Water } else { TileKind::Regular }
+        });
+        let settings = MinesweeperSettings {
+            seed: Some(seed),
+            mine_density,
+            no_guess: true,
+            ..Default::default()
+        };
+        let start = C::origin().iter_ring(5).next().unwrap();
+        let mut game = GameMinesweeper::new(settings.clone(), &map, |k| *k);
+        game.make_no_guess(start);
+        assert!(solver::play(&game.mapdata, start, |t| (t.kind(), t.item())).is_ok());
+        for c in start.iter_n0_in(&game.mapdata).chain(Some(start)) {
+            assert_eq!(game.mapdata[c].item(), ItemKind::Safe);
+        }
+        let n = game.mapdata.iter()
+            .filter(|(_, t)| t.kind().is_land() && t.item() != ItemKind::Mine)
+            .count();
+        assert_eq!(game.n_unexplored_tiles as usize, n);
+
+        let mut again = GameMinesweeper::new(settings, &map, |k| *k);
+        again.make_no_guess(start);
+        assert!(game.mapdata.data().iter().zip(again.mapdata.data())
+            .all(|(a, b)| a.item() == b.item()));
+    }
+
+    #[test]
+    fn no_guess() {
+        for seed in 0..4 {
+            check_no_guess::<Hex>(seed, 48);
+            check_no_guess::<Sq>(seed, 48);
+        }
+        // too many mines for re-rolling to work
+        check_no_guess::<Hex>(1503, 112);
+        check_no_guess::<Sq>(1503, 112);
+    }
+
+    #[test]
+    fn no_guess_dense() {
+        for (seed, mine_density) in [(0, 48), (1, 112), (2, 160), (3, 255)] {
+            let map = MapData::<Hex, _>::new(6, TileKind::Regular);
+            let settings = MinesweeperSettings {
+                seed: Some(seed),
+                mine_density,
+                no_guess: true,
+                ..Default::default()
+            };
+            let mut game = GameMinesweeper::new(settings, &map, |k| *k);
+            let start = Hex::from(Pos(2, -1));
+            game.make_no_guess(start);
+            assert!(solver::play(&game.mapdata, start, |t| (t.kind(), t.item())).is_ok());
+        }
+    }
+
+    #[derive(Default)]
+    struct TestHost {
+        events: Vec<MwEv>,
+        /// Who each event was sent to
+        to: Vec<PlidsBig>,
+        game_over: bool,
+    }
+
+    impl<C: Coord> Host<GameMinesweeper<C>> for TestHost {
+        fn msg<P: PlidMask>(&mut self, plids: P, event: MwEv) {
+            self.events.push(event);
+            self.to.push(plids.into());
+        }
+        fn sched(&mut self, _time: Instant, _event: MinesweeperSchedEvent) {}
+        fn desched_all(&mut self, _event: MinesweeperSchedEvent) {}
+        fn game_over(&mut self) {
+            self.game_over = true;
+        }
+    }
+
+    #[test]
+    fn flood_captures_once() {
+        // no mines, so the first click floods the whole map and many
+        // tiles get queued by more than one neighbor
+        let map = MapData::<Hex, _>::new(6, TileKind::Regular);
+        let settings = MinesweeperSettings {
+            seed: Some(1),
+            mine_density: 0,
+            ..Default::default()
+        };
+        let mut game = GameMinesweeper::new(settings, &map, |k| *k);
+        let mut host = TestHost::default();
+        let plid = PlayerId::from(1);
+        game.input(&mut host, plid, MinesweeperInputAction::ExploreTile { pos: Pos(0, 0) });
+        assert_eq!(game.n_unexplored_tiles, 0);
+        assert_eq!(game.playerdata[0].n_owned as usize, map.data().len());
+        let owned: HashSet<Pos> = host.events.iter().filter_map(|ev| match ev {
+            MwEv::Map { pos, ev: MapEv::Owner { .. } } => Some(*pos),
+            _ => None,
+        }).collect();
+        assert_eq!(owned.len(), map.data().len());
+    }
+
+    /// Who the digit events were sent to
+    fn digits_to(host: &TestHost) -> Vec<PlidsBig> {
+        host.events.iter().zip(host.to.iter())
+            .filter(|(ev, _)| matches!(ev, MwEv::Map { ev: MapEv::Digit { .. }, .. }))
+            .map(|(_, to)| *to)
+            .collect()
+    }
+
+    fn coop_game(n_plids: u8, teams: Vec<u8>) -> GameMinesweeper<Sq> {
+        let map = MapData::<Sq, _>::new(6, TileKind::Regular);
+        let settings = MinesweeperSettings {
+            seed: Some(3),
+            n_plids,
+            teams,
+            n_lives: 2,
+            ..Default::default()
+        };
+        GameMinesweeper::new(settings, &map, |k| *k)
+    }
+
+    /// Unexplored tiles with the given item
+    fn unexplored(game: &GameMinesweeper<Sq>, item: ItemKind) -> Vec<Pos> {
+        game.mapdata.iter()
+            .filter(|(_, t)| t.owner() == 0 && t.item() == item)
+            .map(|(c, _)| c.into())
+            .collect()
+    }
+
+    #[test]
+    fn coop() {
+        for n in 2..=4 {
+            let mut game = coop_game(n, vec![1; n as usize]);
+            let mut host = TestHost::default();
+            let team = PlayerId::from(1);
+            let members = game.members(team);
+            for i in 1..=n {
+                assert_eq!(game.team(PlayerId::from(i)), team);
+                assert!(members.contains(PlayerId::from(i)));
+            }
+            assert!(!members.contains(PlayerId::from(n + 1)));
+
+            // territory belongs to the team, digits go to everyone on it
+            game.input(&mut host, PlayerId::from(n), MinesweeperInputAction::ExploreTile { pos: Pos(0, 0) });
+            assert_eq!(game.mapdata[Sq(0, 0)].owner(), 1);
+            let digits = digits_to(&host);
+            assert!(!digits.is_empty());
+            assert!(digits.iter().all(|to| *to == members));
+
+            // flags are shared too
+            let flag = game.mapdata.iter()
+                .find(|(c, t)| {
+                    t.owner() == 0 && c.iter_n1_in(&game.mapdata).any(|c2| game.mapdata[c2].owner() == 1)
+                })
+                .map(|(c, _)| c)
+                .unwrap();
+            game.input(&mut host, PlayerId::from(2), MinesweeperInputAction::ToggleFlag { pos: flag.into() });
+            assert_eq!(game.mark(team, flag), Some(Mark::Flag));
+            game.input(&mut host, PlayerId::from(1), MinesweeperInputAction::ToggleFlag { pos: flag.into() });
+            assert_eq!(game.mark(team, flag), None);
+
+            // and so are lives
+            let mines = unexplored(&game, ItemKind::Mine);
+            game.input(&mut host, PlayerId::from(1), MinesweeperInputAction::ExploreTile { pos: mines[0] });
+            assert_eq!(game.playerdata[0].n_lives, 1);
+            assert!(!host.game_over);
+            game.input(&mut host, PlayerId::from(n), MinesweeperInputAction::ExploreTile { pos: mines[1] });
+            assert_eq!(game.playerdata[0].n_lives, 0);
+            assert!(host.events.contains(&MwEv::Player { plid: team, ev: PlayerEv::Eliminated }));
+            assert!(host.game_over);
+
+            // nobody on the team can play any more
+            host.events.clear();
+            for i in 1..=n {
+                game.input(&mut host, PlayerId::from(i), MinesweeperInputAction::ExploreTile { pos: mines[2] });
+            }
+            assert!(host.events.is_empty());
+        }
+    }
+
+    #[test]
+    fn two_teams() {
+        let mut game = coop_game(4, vec![1, 1, 3, 3]);
+        let mut host = TestHost::default();
+        let (team1, team3) = (PlayerId::from(1), PlayerId::from(3));
+        assert_eq!(game.team(PlayerId::from(2)), team1);
+        assert_eq!(game.team(PlayerId::from(4)), team3);
+        assert!(!game.members(team3).contains(team1));
+        assert!(!game.members(team3).contains(PlayerId::from(2)));
+
+        game.input(&mut host, PlayerId::from(4), MinesweeperInputAction::ExploreTile { pos: Pos(0, 0) });
+        assert_eq!(game.mapdata[Sq(0, 0)].owner(), 3);
+        assert!(digits_to(&host).iter().all(|to| *to == game.members(team3)));
+
+        // no hints with more than one team
+        host.events.clear();
+        game.input(&mut host, PlayerId::from(3), MinesweeperInputAction::RequestHint);
+        assert!(host.events.is_empty());
+
+        // one team out, the other one keeps playing
+        // (the first mine is forgiven, as it is the team's first tile)
+        let mines = unexplored(&game, ItemKind::Mine);
+        let mines: Vec<_> = mines.into_iter()
+            .filter(|pos| Sq::from(*pos).iter_n1_in(&game.mapdata).all(|c2| game.mapdata[c2].owner() == 0))
+            .collect();
+        for pos in &mines[..3] {
+            game.input(&mut host, PlayerId::from(2), MinesweeperInputAction::ExploreTile { pos: *pos });
+        }
+        assert!(host.events.contains(&MwEv::Player { plid: team1, ev: PlayerEv::Eliminated }));
+        assert!(!host.game_over);
+        assert_eq!(game.playerdata[2].n_lives, 2);
+    }
+
+    #[test]
+    fn pvp_results() {
+        let mut game = coop_game(2, vec![]);
+        let mut host = TestHost::default();
+        game.input(&mut host, PlayerId::from(1), MinesweeperInputAction::ExploreTile { pos: Pos(0, 0) });
+
+        // the first mine is forgiven, as it is the player's first tile
+        let mines: Vec<_> = unexplored(&game, ItemKind::Mine).into_iter()
+            .filter(|pos| Sq::from(*pos).iter_n1_in(&game.mapdata).all(|c2| game.mapdata[c2].owner() == 0))
+            .collect();
+        for pos in &mines[..3] {
+            game.input(&mut host, PlayerId::from(2), MinesweeperInputAction::ExploreTile { pos: *pos });
+        }
+        assert!(!host.game_over);
+        assert!(!host.events.iter().any(|ev| matches!(ev, MwEv::Player { ev: PlayerEv::GameResult { .. }, .. })));
+        for pos in &mines[3..5] {
+            game.input(&mut host, PlayerId::from(1), MinesweeperInputAction::ExploreTile { pos: *pos });
+        }
+        assert!(host.game_over);
+
+        let results: Vec<_> = host.events.iter()
+            .filter_map(|ev| match ev {
+                MwEv::Player { plid, ev: PlayerEv::GameResult { rank, tiles, mines_hit, lives, eliminated_secs } } => {
+                    Some((*plid, *rank, *tiles, *mines_hit, *lives, *eliminated_secs))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results.len(), 2);
+        let (n1, n2) = (game.playerdata[0].n_owned, game.playerdata[1].n_owned);
+        assert!(n1 > 0 && n2 > 0);
+        let rank = |a: u16, b: u16| if a >= b { 1 } else { 2 };
+        assert_eq!(results[0], (PlayerId::from(1), rank(n1, n2), n1, 2, 0, Some(0)));
+        assert_eq!(results[1], (PlayerId::from(2), rank(n2, n1), n2, 2, 0, Some(0)));
+    }
+
+    /// A board with one mine, next to the only tile that plid 1 owns
+    fn chord_game(settings: MinesweeperSettings) -> (GameMinesweeper<Sq>, TestHost) {
+        let map = MapData::<Sq, _>::new(4, TileKind::Regular);
+        let settings = MinesweeperSettings {
+            mine_density: 0,
+            n_lives: 2,
+            ..settings
+        };
+        let mut game = GameMinesweeper::new(settings, &map, |k| *k);
+        let mut host = TestHost::default();
+        game.mapdata[Sq(1, 0)].set_item(ItemKind::Mine);
+        game.capture_tile(&mut host, PlayerId::from(1), Sq(0, 0), false);
+        host.events.clear();
+        host.to.clear();
+        (game, host)
+    }
+
+    fn toggle_flag(game: &mut GameMinesweeper<Sq>, host: &mut TestHost, plid: u8, c: Sq) {
+        game.input(host, PlayerId::from(plid), MinesweeperInputAction::ToggleFlag { pos: c.into() });
+    }
+
+    fn explore(game: &mut GameMinesweeper<Sq>, host: &mut TestHost, plid: u8, c: Sq) {
+        game.input(host, PlayerId::from(plid), MinesweeperInputAction::ExploreTile { pos: c.into() });
+    }
+
+    #[test]
+    fn chord() {
+        for chording in [Chording::Safe, Chording::Explode] {
+            let (mut game, mut host) = chord_game(MinesweeperSettings {
+                chording,
+                ..Default::default()
+            });
+            // not enough flags
+            explore(&mut game, &mut host, 1, Sq(0, 0));
+            assert!(host.events.is_empty());
+
+            toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+            explore(&mut game, &mut host, 1, Sq(0, 0));
+            for c in Sq(0, 0).iter_n1() {
+                assert_eq!(game.mapdata[c].owner(), if c == Sq(1, 0) { 0 } else { 1 });
+            }
+            assert_eq!(game.mark(PlayerId::from(1), Sq(1, 0)), Some(Mark::Flag));
+            assert_eq!(game.playerdata[0].n_lives, 2);
+        }
+
+        let (mut game, mut host) = chord_game(MinesweeperSettings {
+            chording: Chording::Off,
+            ..Default::default()
+        });
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        host.events.clear();
+        explore(&mut game, &mut host, 1, Sq(0, 0));
+        assert!(host.events.is_empty());
+    }
+
+    #[test]
+    fn chord_wrong_flag() {
+        let (mut game, mut host) = chord_game(MinesweeperSettings {
+            chording: Chording::Safe,
+            ..Default::default()
+        });
+        toggle_flag(&mut game, &mut host, 1, Sq(0, 1));
+        host.events.clear();
+        explore(&mut game, &mut host, 1, Sq(0, 0));
+        assert!(host.events.is_empty());
+
+        let (mut game, mut host) = chord_game(MinesweeperSettings {
+            chording: Chording::Explode,
+            ..Default::default()
+        });
+        toggle_flag(&mut game, &mut host, 1, Sq(0, 1));
+        explore(&mut game, &mut host, 1, Sq(0, 0));
+        assert_eq!(game.playerdata[0].n_lives, 1);
+        assert!(host.events.contains(&MwEv::Map { pos: Pos(1, 0), ev: MapEv::Explode }));
+    }
+
+    #[test]
+    fn question_marks() {
+        let flag_ev = |plid: u8, question| MwEv::Map {
+            pos: Pos(1, 0),
+            ev: MapEv::Flag { plid: PlayerId::from(plid), question },
+        };
+
+        let (mut game, mut host) = chord_game(MinesweeperSettings::default());
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        assert_eq!(host.events, vec![flag_ev(1, false), flag_ev(0, false)]);
+
+        let (mut game, mut host) = chord_game(MinesweeperSettings {
+            question_marks: true,
+            ..Default::default()
+        });
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        assert_eq!(game.mark(PlayerId::from(1), Sq(1, 0)), Some(Mark::Question));
+        // question marks do not count for chording
+        explore(&mut game, &mut host, 1, Sq(0, 0));
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        assert_eq!(host.events, vec![flag_ev(1, false), flag_ev(1, true), flag_ev(0, false)]);
+        assert_eq!(game.mark(PlayerId::from(1), Sq(1, 0)), None);
+
+        // exploring a marked tile clears the mark
+        toggle_flag(&mut game, &mut host, 1, Sq(0, 1));
+        toggle_flag(&mut game, &mut host, 1, Sq(0, 1));
+        host.events.clear();
+        explore(&mut game, &mut host, 1, Sq(0, 1));
+        assert_eq!(game.mark(PlayerId::from(1), Sq(0, 1)), None);
+        assert!(host.events.contains(&MwEv::Map {
+            pos: Pos(0, 1),
+            ev: MapEv::Flag { plid: PlayerId::Neutral, question: false },
+        }));
+    }
+
+    #[test]
+    fn flag_visibility() {
+        for visibility in [FlagVisibility::All, FlagVisibility::Own] {
+            let (mut game, mut host) = chord_game(MinesweeperSettings {
+                n_plids: 2,
+                flag_visibility: visibility,
+                ..Default::default()
+            });
+            game.capture_tile(&mut host, PlayerId::from(2), Sq(2, 0), false);
+            host.events.clear();
+            host.to.clear();
+
+            toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+            toggle_flag(&mut game, &mut host, 2, Sq(1, 0));
+            let p1 = PlidsBig::from(PlayerId::from(1));
+            let p2 = PlidsBig::from(PlayerId::from(2));
+            match visibility {
+                FlagVisibility::All => {
+                    // the tile is already taken
+                    assert_eq!(host.events.len(), 1);
+                    assert!(host.to[0].contains(PlayerId::from(2)));
+                    assert_eq!(game.mark(PlayerId::from(2), Sq(1, 0)), None);
+                }
+                FlagVisibility::Own => {
+                    assert_eq!(host.to, vec![p1 + PlayerId::Neutral, p2 + PlayerId::Neutral]);
+                    assert_eq!(game.mark(PlayerId::from(1), Sq(1, 0)), Some(Mark::Flag));
+                    assert_eq!(game.mark(PlayerId::from(2), Sq(1, 0)), Some(Mark::Flag));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flag_visibility_spectator() {
+        let (mut game, mut host) = chord_game(MinesweeperSettings {
+            n_plids: 2,
+            flag_visibility: FlagVisibility::Own,
+            ..Default::default()
+        });
+        game.capture_tile(&mut host, PlayerId::from(2), Sq(2, 0), false);
+        let flag_ev = |pos, plid: u8| MwEv::Map {
+            pos,
+            ev: MapEv::Flag { plid: PlayerId::from(plid), question: false },
+        };
+        let p1 = PlidsBig::from(PlayerId::from(1));
+        let p2 = PlidsBig::from(PlayerId::from(2));
+        let spect = PlidsBig::from(PlayerId::Neutral);
+
+        // the spectator keeps seeing the flag that remains
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        toggle_flag(&mut game, &mut host, 2, Sq(1, 0));
+        host.events.clear();
+        host.to.clear();
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 0));
+        assert_eq!(host.events, vec![flag_ev(Pos(1, 0), 0), flag_ev(Pos(1, 0), 2)]);
+        assert_eq!(host.to, vec![p1, spect]);
+        host.events.clear();
+        host.to.clear();
+        toggle_flag(&mut game, &mut host, 2, Sq(1, 0));
+        assert_eq!(host.events, vec![flag_ev(Pos(1, 0), 0)]);
+        assert_eq!(host.to, vec![p2 + PlayerId::Neutral]);
+
+        // capturing the tile clears every flag for the spectator
+        toggle_flag(&mut game, &mut host, 1, Sq(1, 1));
+        toggle_flag(&mut game, &mut host, 2, Sq(1, 1));
+        host.events.clear();
+        host.to.clear();
+        explore(&mut game, &mut host, 1, Sq(1, 1));
+        let flags: Vec<_> = host.events.iter().zip(host.to.iter())
+            .filter(|(ev, _)| matches!(ev, MwEv::Map { ev: MapEv::Flag { .. }, .. }))
+            .map(|(ev, to)| (ev.clone(), *to))
+            .collect();
+        assert_eq!(flags, vec![
+            (flag_ev(Pos(1, 1), 0), p1 + PlayerId::Neutral),
+            (flag_ev(Pos(1, 1), 0), p2 + PlayerId::Neutral),
+        ]);
+    }
+
+    #[test]
+    fn bad_teams() {
+        // invalid entries and missing plids play on their own
+        let game = coop_game(3, vec![0, 9]);
+        for i in 1..=3 {
+            assert_eq!(game.team(PlayerId::from(i)), PlayerId::from(i));
+        }
+        assert_eq!(game.team_data().count(), 3);
+
+        // 2 cannot join 3 while also being the team that 1 plays for
+        let game = coop_game(3, vec![2, 3, 3]);
+        assert_eq!(game.team(PlayerId::from(1)), PlayerId::from(1));
+        assert_eq!(game.team(PlayerId::from(2)), PlayerId::from(3));
+        assert_eq!(game.team(PlayerId::from(3)), PlayerId::from(3));
+        let teams: Vec<_> = game.team_data().map(|(plid, _)| plid).collect();
+        assert_eq!(teams, vec![PlayerId::from(1), PlayerId::from(3)]);
+        assert_eq!(game.members(PlayerId::from(3)), PlidsBig::from(PlayerId::from(2)) + PlayerId::from(3));
+        assert_eq!(game.members(PlayerId::from(1)), PlidsBig::from(PlayerId::from(1)));
+        // a team that does not play as itself is not a team
+        let game = coop_game(3, vec![2, 1, 0]);
+        for i in 1..=3 {
+            assert_eq!(game.team(PlayerId::from(i)), PlayerId::from(i));
+        }
+    }
+
+    fn hints(host: &TestHost) -> Vec<(Pos, bool)> {
+        host.events.iter().filter_map(|ev| match ev {
+            MwEv::Player { ev: PlayerEv::Hint { pos, mine }, .. } => Some((*pos, *mine)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn hint() {
+        let map = MapData::<Hex, _>::new(8, TileKind::Regular);
+        let settings = MinesweeperSettings {
+            seed: Some(7),
+            no_guess: true,
+            ..Default::default()
+        };
+        let mut game = GameMinesweeper::new(settings, &map, |k| *k);
+        let mut host = TestHost::default();
+        let plid = PlayerId::from(1);
+
+        // nothing is known before the first tile is explored
+        game.input(&mut host, plid, MinesweeperInputAction::RequestHint);
+        assert!(hints(&host).is_empty());
+
+        game.input(&mut host, plid, MinesweeperInputAction::ExploreTile { pos: Pos(0, 0) });
+        // follow the hints, they should solve the whole board
+        for _ in 0..map.data().len() {
+            if game.n_unexplored_tiles == 0 {
+                break;
+            }
+            host.events.clear();
+            game.input(&mut host, plid, MinesweeperInputAction::RequestHint);
+            let [(pos, mine)] = hints(&host)[..] else {
+                panic!("no hint");
+            };
+            let c = Hex::from(pos);
+            assert_eq!(game.mapdata[c].item() == ItemKind::Mine, mine);
+            let action = if mine {
+                MinesweeperInputAction::ToggleFlag { pos }
+            } else {
+                MinesweeperInputAction::ExploreTile { pos }
+            };
+            game.input(&mut host, plid, action);
+        }
+        assert_eq!(game.n_unexplored_tiles, 0);
+        assert_eq!(game.playerdata[0].n_lives, 1);
+    }
+
+    #[test]
+    fn hint_penalty() {
+        let map = MapData::<Sq, _>::new(6, TileKind::Regular);
+        let settings = MinesweeperSettings {
+            seed: Some(7),
+            no_guess: true,
+            n_lives: 2,
+            hint_penalty_lives: 1,
+            ..Default::default()
+        };
+        let mut game = GameMinesweeper::new(settings, &map, |k| *k);
+        let mut host = TestHost::default();
+        let plid = PlayerId::from(1);
+        game.input(&mut host, plid, MinesweeperInputAction::ExploreTile { pos: Pos(0, 0) });
+        host.events.clear();
+        game.input(&mut host, plid, MinesweeperInputAction::RequestHint);
+        assert_eq!(hints(&host).len(), 1);
+        assert!(host.events.contains(&MwEv::Player { plid, ev: PlayerEv::LivesRemain { lives: 1 } }));
+        // would take the last life
+        host.events.clear();
+        game.input(&mut host, plid, MinesweeperInputAction::RequestHint);
+        assert!(host.events.is_empty());
+        assert_eq!(game.playerdata[0].n_lives, 1);
+    }
+
+    const GOLDEN_HEX: &str = "..d....**..*.....*.*...*...**...*.d****.**.*.....**.**....*..";
+    const GOLDEN_SQ: &str = "*...d..*...d.*..**..*...*....*.*..**.......*.**.dd*....*....*.....d.*.**.*.*..*..";
+}
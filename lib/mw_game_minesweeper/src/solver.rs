@@ -0,0 +1,492 @@
+//! Minesweeper solver
+//!
+//! Figures out what can be known about unexplored tiles from the digits a
+//! player can see, without guessing. Used for generating boards that never
+//! need a guess, and useful for hints and bots.
+//!
+//! Digits count both mines and decoys (see [`Deduction`]), and an asterisk
+//! means that at least one of them is a decoy. Exploring a decoy is harmless,
+//! so the solver also tries to find tiles that are "not a mine", even if it
+//! cannot tell whether they are empty.
+
+use mw_common::game::{ItemKind, TileKind};
+use mw_common::grid::*;
+use mw_common::prelude::*;
+
+/// What a player can see on a tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolverTile {
+    /// Not explored yet
+    Unknown,
+    /// Cannot have an item, but has no digit either
+    /// (water, mountains, forests, another player's territory, ...)
+    Clear,
+    /// Explored, showing a digit
+    Digit {
+        digit: u8,
+        asterisk: bool,
+    },
+}
+
+/// Something the solver has figured out about an unexplored tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deduction {
+    /// Has no item
+    Safe,
+    /// Has a decoy
+    Decoy,
+    /// Could have a decoy, but not a mine
+    NotMine,
+    /// Has a mine
+    Mine,
+    /// Has a mine or a decoy (is counted by digits)
+    Hazard,
+}
+
+impl Deduction {
+    /// Can the tile be explored without losing a life?
+    pub fn is_safe_to_explore(self) -> bool {
+        matches!(self, Deduction::Safe | Deduction::Decoy | Deduction::NotMine)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Knowledge {
+    /// Is there a mine or decoy?
+    hazard: Option<bool>,
+    /// Is there a mine?
+    mine: Option<bool>,
+    /// Next to a digit without an asterisk
+    no_decoy: bool,
+}
+
+impl Knowledge {
+    /// Fill in everything that follows from what we know
+    fn normalize(&mut self) {
+        if self.hazard == Some(false) {
+            self.mine = Some(false);
+        }
+        if self.mine == Some(true) {
+            self.hazard = Some(true);
+        }
+        if self.no_decoy {
+            if self.hazard.is_some() {
+                self.mine = self.hazard;
+            } else if self.mine.is_some() {
+                self.hazard = self.mine;
+            }
+        }
+    }
+
+    fn deduction(&self) -> Option<Deduction> {
+        match (self.hazard, self.mine) {
+            (Some(false), _) => Some(Deduction::Safe),
+            (_, Some(true)) => Some(Deduction::Mine),
+            (Some(true), Some(false)) => Some(Deduction::Decoy),
+            (None, Some(false)) => Some(Deduction::NotMine),
+            (Some(true), None) => Some(Deduction::Hazard),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Constraint-propagation solver
+///
+/// Feed it what a player can see, with [`Solver::new`] and [`Solver::set`],
+/// call [`Solver::solve`], and query the results.
+pub struct Solver<C: Coord> {
+    tiles: MapData<C, SolverTile>,
+    known: MapData<C, Knowledge>,
+}
+
+impl<C: Coord> Solver<C> {
+    pub fn new(tiles: MapData<C, SolverTile>) -> Self {
+        let known = tiles.convert(|_, _| Knowledge::default());
+        Solver { tiles, known }
+    }
+
+    /// Update what is visible on a tile (for example, after it has been explored)
+    pub fn set(&mut self, c: C, tile: SolverTile) {
+        if let Some(t) = self.tiles.get_mut(c) {
+            *t = tile;
+        }
+    }
+
+    pub fn tile(&self, c: C) -> Option<SolverTile> {
+        self.tiles.get(c).copied()
+    }
+
+    /// What is known about an unexplored tile
+    ///
+    /// `None` if nothing is known (or the tile is not unexplored).
+    pub fn deduction(&self, c: C) -> Option<Deduction> {
+        if self.tiles.get(c) != Some(&SolverTile::Unknown) {
+            return None;
+        }
+        self.known[c].deduction()
+    }
+
+    /// All unexplored tiles that can be explored without losing a life
+    pub fn safe_tiles(&self) -> impl Iterator<Item = C> + '_ {
+        self.tiles.iter_coords(None)
+            .filter(|c| self.deduction(*c).map(|d| d.is_safe_to_explore()).unwrap_or(false))
+    }
+
+    /// Make all the deductions possible from the current state
+    ///
+    /// Returns `true` if anything new was learned.
+    pub fn solve(&mut self) -> bool {
+        let mut any = false;
+        loop {
+            let mut changed = false;
+            let digits: Vec<(C, u8, bool)> = self.tiles.iter()
+                .filter_map(|(c, t)| match *t {
+                    SolverTile::Digit { digit, asterisk } => Some((c, digit, asterisk)),
+                    _ => None,
+                })
+                // skip digits that have nothing more to tell
+                .filter(|(c, _, _)| {
+                    self.unknown_neighbors(*c).any(|c2| {
+                        let k = self.known[c2];
+                        k.hazard.is_none() || k.mine.is_none()
+                    })
+                })
+                .collect();
+            for &(c, digit, asterisk) in digits.iter() {
+                changed |= self.apply_digit(c, digit, asterisk);
+            }
+            if !changed {
+                changed |= self.apply_subsets(&digits);
+            }
+            if !changed {
+                break;
+            }
+            any = true;
+        }
+        any
+    }
+
+    fn unknown_neighbors(&self, c: C) -> impl Iterator<Item = C> + '_ {
+        c.iter_n1_in(&self.tiles).filter(|c2| self.tiles[*c2] == SolverTile::Unknown)
+    }
+
+    fn update(&mut self, c: C, f: impl FnOnce(&mut Knowledge)) -> bool {
+        let old = self.known[c];
+        let k = &mut self.known[c];
+        f(k);
+        k.normalize();
+        *k != old
+    }
+
+    /// Undecided neighbors, and how many of them must be hazards
+    fn remaining(&self, c: C, digit: u8) -> (Vec<C>, i32) {
+        let mut undecided = vec![];
+        let mut hazards = 0;
+        for c2 in self.unknown_neighbors(c) {
+            match self.known[c2].hazard {
+                None => undecided.push(c2),
+                Some(true) => hazards += 1,
+                Some(false) => {}
+            }
+        }
+        (undecided, digit as i32 - hazards)
+    }
+
+    fn apply_digit(&mut self, c: C, digit: u8, asterisk: bool) -> bool {
+        let mut changed = false;
+        let neighbors: Vec<C> = self.unknown_neighbors(c).collect();
+
+        let (undecided, rem) = self.remaining(c, digit);
+        if rem == 0 || rem == undecided.len() as i32 {
+            for c2 in undecided {
+                changed |= self.update(c2, |k| k.hazard = Some(rem != 0));
+            }
+        }
+
+        if !asterisk {
+            for c2 in neighbors.iter() {
+                changed |= self.update(*c2, |k| k.no_decoy = true);
+            }
+            return changed;
+        }
+
+        // at least one of the hazards is a decoy
+        let mines = neighbors.iter().filter(|c2| self.known[**c2].mine == Some(true)).count();
+        if digit as i32 - mines as i32 == 1 {
+            // ... and it is the only one that is not a known mine
+            for c2 in neighbors.iter() {
+                if self.known[*c2].mine.is_none() {
+                    changed |= self.update(*c2, |k| k.mine = Some(false));
+                }
+            }
+        }
+        let could_be_decoy: Vec<C> = neighbors.iter()
+            .copied()
+            .filter(|c2| {
+                let k = self.known[*c2];
+                k.hazard != Some(false) && k.mine != Some(true) && !k.no_decoy
+            })
+            .collect();
+        if let [decoy] = could_be_decoy[..] {
+            changed |= self.update(decoy, |k| {
+                k.hazard = Some(true);
+                k.mine = Some(false);
+            });
+        }
+        changed
+    }
+
+    /// If the undecided tiles of one digit are a subset of another's, the
+    /// difference must contain the difference in hazards
+    fn apply_subsets(&mut self, digits: &[(C, u8, bool)]) -> bool {
+        let mut changed = false;
+        let remaining: HashMap<C, (Vec<C>, i32)> = digits.iter()
+            .map(|&(c, digit, _)| (c, self.remaining(c, digit)))
+            .filter(|(_, (undecided, _))| !undecided.is_empty())
+            .collect();
+        for &(a, _, _) in digits {
+            let Some((ua, ra)) = remaining.get(&a) else {
+                continue;
+            };
+            // digits sharing a neighbor are at most 2 tiles apart
+            for b in a.iter_n1().chain(a.iter_ring(2)) {
+                let Some((ub, rb)) = remaining.get(&b) else {
+                    continue;
+                };
+                if ub.len() <= ua.len() || !ua.iter().all(|c| ub.contains(c)) {
+                    continue;
+                }
+                let diff: Vec<C> = ub.iter().copied().filter(|c| !ua.contains(c)).collect();
+                let rd = rb - ra;
+                if rd == 0 || rd == diff.len() as i32 {
+                    for c in diff {
+                        changed |= self.update(c, |k| k.hazard = Some(rd != 0));
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Where the solver got stuck, when playing a board
+#[derive(Debug, Clone)]
+pub struct Stuck<C: Coord> {
+    /// Unexplored tiles next to explored ones that the solver could not prove safe
+    pub frontier: Vec<C>,
+    /// All the tiles that were explored
+    pub explored: HashSet<C>,
+}
+
+/// Play a board like a player would, starting from `start`, never guessing
+///
+/// `f` gives the kind and item of each tile. `start` is explored first, as
+/// if it was safe (the first tile a player explores is always forgiven).
+///
+/// Succeeds if every tile without a mine could be explored. Tiles that cannot
+/// be reached from `start` over land (islands) are never revealed by any digit,
+/// so they are not counted.
+pub fn play<C: Coord, D>(
+    map: &MapData<C, D>,
+    start: C,
+    f: impl Fn(&D) -> (TileKind, ItemKind),
+) -> Result<(), Stuck<C>> {
+    let kinds = map.convert(|_, d| f(d).0);
+    let mut items = map.convert(|_, d| f(d).1);
+    let mut solver = Solver::new(kinds.convert(|_, k| {
+        if k.is_land() { SolverTile::Unknown } else { SolverTile::Clear }
+    }));
+    let mut explored = HashSet::new();
+    if let Some(item) = items.get_mut(start) {
+        *item = ItemKind::Safe;
+    }
+
+    let digit = |items: &MapData<C, ItemKind>, c: C| {
+        let mut digit = 0;
+        let mut asterisk = false;
+        for c2 in c.iter_n1_in(items) {
+            match items[c2] {
+                ItemKind::Safe => {}
+                ItemKind::Mine => digit += 1,
+                ItemKind::Decoy | ItemKind::Flashbang => {
+                    digit += 1;
+                    asterisk = true;
+                }
+            }
+        }
+        SolverTile::Digit { digit, asterisk }
+    };
+
+    let mut to_explore = vec![start];
+    loop {
+        while let Some(c) = to_explore.pop() {
+            if !kinds.get(c).map(|k| k.is_land()).unwrap_or(false) || explored.contains(&c) {
+                continue;
+            }
+            debug_assert_ne!(items[c], ItemKind::Mine, "the solver explored a mine");
+            explored.insert(c);
+            if items[c] != ItemKind::Safe {
+                // exploding a decoy changes the digits around it
+                items[c] = ItemKind::Safe;
+                for c2 in c.iter_n1_in(&items) {
+                    if explored.contains(&c2) {
+                        solver.set(c2, digit(&items, c2));
+                    }
+                }
+            }
+            let tile = digit(&items, c);
+            solver.set(c, tile);
+            if tile == (SolverTile::Digit { digit: 0, asterisk: false }) {
+                to_explore.extend(c.iter_n1_in(&items));
+            }
+        }
+        solver.solve();
+        to_explore.extend(solver.safe_tiles());
+        if to_explore.is_empty() {
+            break;
+        }
+    }
+
+    let mut reachable: HashSet<C> = HashSet::from_iter([start]);
+    let mut q = vec![start];
+    while let Some(c) = q.pop() {
+        for c2 in c.iter_n1_in(&kinds) {
+            if kinds[c2].is_land() && reachable.insert(c2) {
+                q.push(c2);
+            }
+        }
+    }
+    let done = reachable.iter()
+        .all(|c| items[*c] == ItemKind::Mine || explored.contains(c));
+    if done {
+        return Ok(());
+    }
+    let mut frontier: Vec<C> = kinds.iter_coords(None)
+        .filter(|c| solver.tile(*c) == Some(SolverTile::Unknown))
+        .filter(|c| c.iter_n1_in(&kinds).any(|c2| explored.contains(&c2)))
+        .filter(|c| !solver.deduction(*c).map(|d| d.is_safe_to_explore()).unwrap_or(false))
+        .collect();
+    frontier.sort();
+    Err(Stuck { frontier, explored })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn digit(digit: u8, asterisk: bool) -> SolverTile {
+        SolverTile::Digit { digit, asterisk }
+    }
+
+    /// Only the given tiles are unknown, everything else is clear
+    fn solver_with<C: Coord>(unknown: &[C], digits: &[(C, SolverTile)]) -> Solver<C> {
+        let mut solver = Solver::new(MapData::new_with(3, |c| {
+            if unknown.contains(&c) { SolverTile::Unknown } else { SolverTile::Clear }
+        }));
+        for (c, t) in digits {
+            solver.set(*c, *t);
+        }
+        solver
+    }
+
+    #[test]
+    fn zero_and_one() {
+        let mut solver = Solver::new(MapData::new(3, SolverTile::Unknown));
+        solver.set(Sq(0, 0), digit(0, false));
+        assert!(solver.solve());
+        for c in Sq(0, 0).iter_n1() {
+            assert_eq!(solver.deduction(c), Some(Deduction::Safe));
+        }
+        assert_eq!(solver.deduction(Sq(2, 2)), None);
+        assert_eq!(solver.safe_tiles().count(), 8);
+        assert!(!solver.solve());
+
+        let mut solver = solver_with(&[Sq(0, 1)], &[(Sq(0, 0), digit(1, false))]);
+        solver.solve();
+        assert_eq!(solver.deduction(Sq(0, 1)), Some(Deduction::Mine));
+        assert_eq!(solver.safe_tiles().count(), 0);
+    }
+
+    #[test]
+    fn all_hazards() {
+        let mut solver = Solver::new(MapData::new(3, SolverTile::Unknown));
+        solver.set(Hex(0, 0), digit(6, false));
+        solver.solve();
+        for c in Hex(0, 0).iter_n1() {
+            assert_eq!(solver.deduction(c), Some(Deduction::Mine));
+        }
+
+        let mut solver = Solver::new(MapData::new(3, SolverTile::Unknown));
+        solver.set(Hex(0, 0), digit(6, true));
+        solver.solve();
+        for c in Hex(0, 0).iter_n1() {
+            assert_eq!(solver.deduction(c), Some(Deduction::Hazard));
+        }
+        assert_eq!(solver.safe_tiles().count(), 0);
+    }
+
+    #[test]
+    fn asterisk() {
+        // a 1 with an asterisk: the only hazard is a decoy
+        let mut solver = Solver::new(MapData::new(3, SolverTile::Unknown));
+        solver.set(Hex(0, 0), digit(1, true));
+        solver.solve();
+        for c in Hex(0, 0).iter_n1() {
+            assert_eq!(solver.deduction(c), Some(Deduction::NotMine));
+        }
+        assert_eq!(solver.safe_tiles().count(), 6);
+
+        // a 2 with an asterisk and two unknown neighbors: a mine and a decoy
+        let a = Hex(0, 0).iter_n1().next().unwrap();
+        let b = Hex(0, 0).iter_n1().nth(1).unwrap();
+        let mut solver = solver_with(&[a, b], &[(Hex(0, 0), digit(2, true))]);
+        solver.solve();
+        assert_eq!(solver.deduction(a), Some(Deduction::Hazard));
+        assert_eq!(solver.deduction(b), Some(Deduction::Hazard));
+
+        // another digit without an asterisk tells which is which
+        let other = a.iter_n1().find(|c| c.ring() == 2 && c.distance(b) > 1).unwrap();
+        solver.set(other, digit(1, false));
+        assert!(solver.solve());
+        assert_eq!(solver.deduction(a), Some(Deduction::Mine));
+        assert_eq!(solver.deduction(b), Some(Deduction::Decoy));
+        assert_eq!(solver.safe_tiles().collect::<Vec<_>>(), vec![b]);
+    }
+
+    #[test]
+    fn subset() {
+        // the 1 above sees two of the tiles the 1 below sees,
+        // so the third one must be safe
+        let unknown = [Sq(0, -1), Sq(0, 0), Sq(0, 1)];
+        let mut solver = solver_with(&unknown, &[
+            (Sq(-1, -1), digit(1, false)),
+            (Sq(1, 0), digit(1, false)),
+        ]);
+        solver.solve();
+        assert_eq!(solver.deduction(Sq(0, 1)), Some(Deduction::Safe));
+        assert_eq!(solver.deduction(Sq(0, 0)), None);
+        assert_eq!(solver.deduction(Sq(0, -1)), None);
+    }
+
+    #[test]
+    fn play_boards() {
+        // no items: solved by flood fill alone
+        let map = MapData::<Hex, _>::new(5, (TileKind::Regular, ItemKind::Safe));
+        assert!(play(&map, Hex(0, 0), |d| *d).is_ok());
+
+        // a 50/50: a row of 3 land tiles, starting in the middle
+        let row = |item| MapData::<Sq, _>::new_with(2, |c: Sq| {
+            match (c.0, c.1) {
+                (0, 1) => (TileKind::Regular, item),
+                (0, -1 | 0) => (TileKind::Regular, ItemKind::Safe),
+                _ => (TileKind::Water, ItemKind::Safe),
+            }
+        });
+        let stuck = play(&row(ItemKind::Mine), Sq(0, 0), |d| *d).unwrap_err();
+        assert_eq!(stuck.frontier, vec![Sq(0, -1), Sq(0, 1)]);
+        assert_eq!(stuck.explored, HashSet::from_iter([Sq(0, 0)]));
+
+        // ... but not if it is a decoy
+        assert!(play(&row(ItemKind::Decoy), Sq(0, 0), |d| *d).is_ok());
+    }
+}
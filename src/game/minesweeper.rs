@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use mw_app::input::InputAction;
 use mw_app::settings::MapGenStyle;
 use mw_app::tool::*;
 use mw_app::view::*;
@@ -9,6 +10,7 @@ use mw_game_minesweeper::*;
 use mw_common::grid::*;
 use mw_common::plid::*;
 use mw_common::game::*;
+use mw_common::mapgen::{generate, MapGenParams};
 
 pub struct MinesweeperGameplayPlugin;
 
@@ -33,6 +35,8 @@ impl Plugin for MinesweeperGameplayPlugin {
             minesweeper_input
                 .in_set(InGameSet(Some(GameMode::Minesweeper)))
                 .in_set(ToolEventHandlerSet),
+            minesweeper_hint
+                .in_set(InGameSet(Some(GameMode::Minesweeper))),
         ));
     }
 }
@@ -44,14 +48,44 @@ fn cli_minesweeper_singleplayer(world: &mut World) {
 fn cli_minesweeper_playground(world: &mut World) {
     let minesweeper_settings = world.resource::<AllSettings>().game_minesweeper.clone();
     let mapgen_settings = world.resource::<AllSettings>().mapgen.clone();
+    let n_cits = world.resource::<AllSettings>().game.n_cits;
     match (PROPRIETARY, mapgen_settings.style) {
-        (false, _) | (_, MapGenStyle::Flat) => {
+        (_, MapGenStyle::Flat) => {
+            let shape = MapShape::Radial(mapgen_settings.size);
             match mapgen_settings.topology {
                 Topology::Hex => {
-                    setup_minesweeper_playground_flatmap::<Hex>(world, minesweeper_settings, mapgen_settings.size);
+                    setup_minesweeper_playground_flatmap::<Hex>(world, minesweeper_settings, shape);
                 }
                 Topology::Sq => {
-                    setup_minesweeper_playground_flatmap::<Sq>(world, minesweeper_settings, mapgen_settings.size);
+                    setup_minesweeper_playground_flatmap::<Sq>(world, minesweeper_settings, shape);
+                }
+            }
+        }
+        (_, MapGenStyle::FlatRect { width, height }) => {
+            let shape = MapShape::Rect { w: width, h: height };
+            match mapgen_settings.topology {
+                Topology::Hex => {
+                    setup_minesweeper_playground_flatmap::<Hex>(world, minesweeper_settings, shape);
+                }
+                Topology::Sq => {
+                    setup_minesweeper_playground_flatmap::<Sq>(world, minesweeper_settings, shape);
+                }
+            }
+        }
+        (false, MapGenStyle::MineWars) => {
+            let params = MapGenParams {
+                size: mapgen_settings.size,
+                seed: mapgen_settings.seed.unwrap_or_else(|| thread_rng().gen()),
+                land_bias: mapgen_settings.land_bias,
+                n_cits,
+                ..Default::default()
+            };
+            match mapgen_settings.topology {
+                Topology::Hex => {
+                    setup_minesweeper_playground_genmap::<Hex>(world, minesweeper_settings, &params);
+                }
+                Topology::Sq => {
+                    setup_minesweeper_playground_genmap::<Sq>(world, minesweeper_settings, &params);
                 }
             }
         }
@@ -67,12 +101,46 @@ fn cli_minesweeper_playground(world: &mut World) {
 fn setup_minesweeper_playground_flatmap<C: Coord>(
     world: &mut World,
     minesweeper_settings: MinesweeperSettings,
-    map_size: u8
+    shape: MapShape,
+) {
+    if !shape.is_valid::<C>() {
+        error!("Invalid map shape: {:?}", shape);
+        return;
+    }
+    let tiles = MapData::<C, _>::new_shape(shape, TileKind::Regular);
+    let regions = tiles.convert(|_, _| 0);
+    setup_minesweeper_playground(world, minesweeper_settings, &tiles, &regions, &[]);
+}
+
+fn setup_minesweeper_playground_genmap<C: Coord>(
+    world: &mut World,
+    minesweeper_settings: MinesweeperSettings,
+    params: &MapGenParams,
+) {
+    match generate::<C>(params) {
+        Ok(map) => {
+            info!("Generated map with seed {}", params.seed);
+            setup_minesweeper_playground(world, minesweeper_settings, &map.tiles, &map.regions, &map.cits);
+        }
+        Err(e) => {
+            error!("Could not generate map (seed {}): {}", params.seed, e);
+            setup_minesweeper_playground_flatmap::<C>(world, minesweeper_settings, MapShape::Radial(params.size));
+        }
+    }
+}
+
+fn setup_minesweeper_playground<C: Coord>(
+    world: &mut World,
+    minesweeper_settings: MinesweeperSettings,
+    tiles: &MapData<C, TileKind>,
+    regions: &MapData<C, u8>,
+    cits: &[C],
 ) {
     let n_plids = minesweeper_settings.n_plids;
-    let dummy_map = MapData::<C, ()>::new(map_size, ());
-    mw_app::map::setup_map(world, &dummy_map, &[], |_| TileKind::Regular, |_| 0);
-    let game = GameMinesweeper::<C>::new(minesweeper_settings, &dummy_map, |_| TileKind::Regular);
+    let mapdata = tiles.convert(|c, kind| (*kind, regions[c]));
+    mw_app::map::setup_map(world, &mapdata, cits, |d| d.0, |d| d.1);
+    let game = GameMinesweeper::<C>::new(minesweeper_settings, tiles, |k| *k);
+    info!("Minesweeper board seed: {}", game.seed());
     world.insert_resource(BevyHost::new(game, ()));
 
     let mut viewtile: ViewTileData = ViewTileData::default();
@@ -81,6 +149,14 @@ fn setup_minesweeper_playground_flatmap<C: Coord>(
     viewtile.set_kind(TileKind::Regular);
     viewtile.set_item(ItemKind::Safe);
     viewtile.set_has_structure(false);
+    // the view covers the whole bounding radius, so that it can be indexed
+    // with any on-screen coordinate
+    let viewmap = MapData::<C, _>::new_with(tiles.size(), |c| {
+        let kind = tiles.get(c).copied().unwrap_or(TileKind::Water);
+        let mut viewtile = viewtile;
+        viewtile.set_kind(kind);
+        viewtile
+    });
 
     let e_plid0 = world.spawn(SpectatorPlidBundle::default()).id();
     let mut players_index = PlayersIndex(vec![e_plid0]);
@@ -92,7 +168,7 @@ fn setup_minesweeper_playground_flatmap<C: Coord>(
                 state: PlayerState::Alive,
             },
             ViewBundle {
-                mapdata: ViewMapData(MapData::<C, _>::new(map_size, viewtile)),
+                mapdata: ViewMapData(viewmap.clone()),
             },
             PlidPlayable,
         )).id();
@@ -127,3 +203,14 @@ fn minesweeper_input(
         }
     }
 }
+
+fn minesweeper_hint(
+    q_action: Query<&ActionState<InputAction>>,
+    mut evw: EventWriter<MinesweeperInputAction>,
+) {
+    for action_state in &q_action {
+        if action_state.just_pressed(InputAction::RequestHint) {
+            evw.send(MinesweeperInputAction::RequestHint);
+        }
+    }
+}
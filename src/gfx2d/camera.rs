@@ -54,7 +54,7 @@ fn grid_cursor(
             ).inverse();
             let grid = conv * crs_in.pos;
             let new = Hex::from_f32_clamped(grid.into());
-            if new.ring() <= mapdesc.size {
+            if new.in_bounds(mapdesc.size) {
                 let new_pos = Pos::from(new);
                 if crs_out.0 != new_pos {
                     crs_out.0 = new_pos;
@@ -65,7 +65,7 @@ fn grid_cursor(
             let tdim = Vec2::new(super::sprite::WIDTH4, super::sprite::HEIGHT4);
             let adj = crs_in.pos / tdim;
             let new = Sq::from_f32_clamped(adj.into());
-            if new.ring() <= mapdesc.size {
+            if new.in_bounds(mapdesc.size) {
                 let new_pos = Pos::from(new);
                 if crs_out.0 != new_pos {
                     crs_out.0 = new_pos;
@@ -52,6 +52,21 @@ struct RegHighlightSprite;
 struct ExplosionSprite {
     timer: Timer,
 }
+#[derive(Component)]
+struct HintSprite {
+    timer: Timer,
+}
+
+/// How long to highlight a tile after a hint
+const HINT_MILLIS: u64 = 3000;
+
+fn hint_color(mine: bool) -> Color {
+    if mine {
+        Color::rgb(1.0, 0.25, 0.25)
+    } else {
+        Color::rgb(0.25, 1.0, 0.25)
+    }
+}
 
 fn rc_gfx2d_any(
     settings: Option<Res<AllSettings>>,
@@ -78,7 +93,7 @@ fn fancytint<C: Coord>(map_size: u8, c: C, f_kind: impl Fn(C) -> TileKind) -> f3
 
     'outer: for r in 1..=map_size {
         for c2 in c.iter_ring(r) {
-            if c2.ring() > map_size {
+            if !c2.in_bounds(map_size) {
                 if d_edge == 0 {
                     d_edge = r;
                 }
@@ -35,6 +35,7 @@ impl Plugin for Gfx2dSpritesPlugin {
                 tile_digit_sprite_mgr.after(MapUpdateSet::TileDigit),
                 tile_gent_sprite_mgr.after(MapUpdateSet::TileGent),
                 explosion_sprite_mgr,
+                hint_sprite_mgr,
                 sprites_reghighlight,
             )
                 .run_if(resource_exists::<TilemapInitted>()),
@@ -288,6 +289,13 @@ fn tile_gent_sprite_mgr(
                     )
                 }
             }
+            // no dedicated sprite yet, draw a faded flag
+            TileGent::QuestionMark(plid) => {
+                (
+                    super::sprite::FLAGS,
+                    Color::from(settings.player_colors.visible[plid.i()]).with_a(0.5),
+                )
+            }
             TileGent::Item(ItemKind::Mine) => (super::sprite::GENT_MINE, Color::WHITE),
             TileGent::Item(ItemKind::Decoy) =>(super::sprite::GENT_DECOY, Color::WHITE),
             TileGent::Item(ItemKind::Flashbang) => (super::sprite::GENT_FLASH, Color::WHITE),
@@ -373,6 +381,45 @@ fn explosion_sprite_mgr(
     }
 }
 
+fn hint_sprite_mgr(
+    mut commands: Commands,
+    time: Res<Time>,
+    assets: Res<GameAssets>,
+    mut q_hint: Query<(
+        Entity, &TileHint, Option<&mut HintSprite>, Option<&mut TextureAtlasSprite>,
+    )>,
+    q_tile: Query<&Transform, With<BaseSprite>>,
+) {
+    for (e, hint, spr_hint, sprite) in &mut q_hint {
+        if let (Some(mut spr_hint), Some(mut sprite)) = (spr_hint, sprite) {
+            spr_hint.timer.tick(time.delta());
+            if spr_hint.timer.finished() {
+                commands.entity(e).despawn_recursive();
+            }
+            sprite.color.set_a(spr_hint.timer.percent_left());
+        } else {
+            let xf = q_tile.get(hint.0).unwrap();
+            let mut trans = xf.translation;
+            trans.z = zpos::OVERLAYS;
+            commands.entity(e).insert((
+                HintSprite {
+                    timer: Timer::new(Duration::from_millis(HINT_MILLIS), TimerMode::Once),
+                },
+                SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        index: super::sprite::REVEAL,
+                        color: hint_color(hint.1),
+                        ..Default::default()
+                    },
+                    texture_atlas: assets.sprites.clone(),
+                    transform: Transform::from_translation(trans),
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+}
+
 fn sprites_reghighlight(
     mut commands: Commands,
     settings: Res<AllSettings>,
@@ -36,6 +36,7 @@ impl Plugin for Gfx2dTilemapPlugin {
                 digit_tilemap_mgr.after(MapUpdateSet::TileDigit),
                 gent_tilemap_mgr.after(MapUpdateSet::TileGent),
                 overlay_tilemap_mgr,
+                hint_tilemap_mgr.after(overlay_tilemap_mgr),
                 tilemap_reghighlight.run_if(resource_changed::<GridCursorTileEntity>()),
             )
                 .run_if(resource_exists::<TilemapInitted>()),
@@ -406,6 +407,13 @@ fn gent_tilemap_mgr(
                     )
                 }
             }
+            // no dedicated sprite yet, draw a faded flag
+            TileGent::QuestionMark(plid) => {
+                (
+                    super::sprite::FLAGS,
+                    Color::from(settings.player_colors.visible[plid.i()]).with_a(0.5),
+                )
+            }
             TileGent::Item(ItemKind::Mine) => (super::sprite::GENT_MINE, Color::WHITE),
             TileGent::Item(ItemKind::Decoy) =>(super::sprite::GENT_DECOY, Color::WHITE),
             TileGent::Item(ItemKind::Flashbang) => (super::sprite::GENT_FLASH, Color::WHITE),
@@ -498,6 +506,51 @@ fn overlay_tilemap_mgr(
     }
 }
 
+fn hint_tilemap_mgr(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut q_hint: Query<(
+        Entity, &TileHint, Option<&mut HintSprite>, Option<&mut TileColor>,
+    )>,
+    q_tile: Query<&TilePos, With<BaseSprite>>,
+    mut q_tm_overlay: Query<(Entity, &mut TileStorage), With<OverlayTilemap>>,
+) {
+    for (e, hint, spr_hint, color) in &mut q_hint {
+        if let (Some(mut spr_hint), opt_color) = (spr_hint, color) {
+            spr_hint.timer.tick(time.delta());
+            if spr_hint.timer.finished() {
+                commands.entity(e).despawn_recursive();
+                let tilepos = q_tile.get(hint.0).unwrap();
+                let (_, mut ts_overlay) = q_tm_overlay.single_mut();
+                // an explosion could have taken over the tile since
+                if ts_overlay.get(tilepos) == Some(e) {
+                    ts_overlay.remove(tilepos);
+                }
+            }
+            if let Some(mut color) = opt_color {
+                color.0.set_a(spr_hint.timer.percent_left());
+            }
+        } else {
+            let (e_tm, mut ts_overlay) = q_tm_overlay.single_mut();
+            let tilepos = q_tile.get(hint.0).unwrap();
+            commands.entity(e).insert((
+                HintSprite {
+                    timer: Timer::new(Duration::from_millis(HINT_MILLIS), TimerMode::Once),
+                },
+                TileBundle {
+                    position: *tilepos,
+                    texture_index: TileTextureIndex(super::sprite::REVEAL as u32),
+                    tilemap_id: TilemapId(e_tm),
+                    visible: TileVisible(true),
+                    color: TileColor(hint_color(hint.1)),
+                    ..Default::default()
+                },
+            ));
+            ts_overlay.set(tilepos, e);
+        }
+    }
+}
+
 fn tilemap_reghighlight(
     mut commands: Commands,
     settings: Res<AllSettings>,
@@ -5,6 +5,7 @@ mod hud;
 mod menu;
 mod notify;
 mod perf;
+mod results;
 mod tooltip;
 mod widget;
 
@@ -18,6 +19,7 @@ impl Plugin for UiPlugin {
             self::menu::MenuPlugin,
             self::notify::NotifyPlugin,
             self::perf::PerfUiPlugin,
+            self::results::ResultsPlugin,
             self::tooltip::TooltipPlugin,
             self::widget::WidgetsPlugin,
         ));
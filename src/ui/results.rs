@@ -0,0 +1,268 @@
+use mw_app::GameEventSet;
+use mw_common::game::event::{GameEvent, MwEv, PlayerEv};
+use mw_common::plid::PlayerId;
+
+use crate::{prelude::*, assets::UiAssets, locale::L10nKey};
+
+use super::UiRoot;
+
+pub(super) struct ResultsPlugin;
+
+impl Plugin for ResultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_clicommand_noargs("results_close", cli_results_close);
+        app.add_systems(OnExit(AppState::InGame), remove_resource::<GameResults>);
+        app.add_systems(Update, (
+            collect_results.after(GameEventSet),
+            spawn_results.run_if(resource_exists_and_changed::<GameResults>()),
+        ).chain().in_set(InStateSet(AppState::InGame)));
+    }
+}
+
+/// How a player did in the game that just ended
+#[derive(Debug, Clone)]
+pub struct PlayerResult {
+    pub plid: PlayerId,
+    pub rank: u8,
+    pub tiles: u16,
+    pub mines_hit: u8,
+    pub lives: u8,
+    pub eliminated_secs: Option<u16>,
+}
+
+/// The final results, sorted by rank
+///
+/// Only exists after the game is over.
+#[derive(Resource, Default)]
+pub struct GameResults(pub Vec<PlayerResult>);
+
+/// Marker for the results screen
+#[derive(Component)]
+struct ResultsPanel;
+
+fn collect_results(
+    mut commands: Commands,
+    mut evr: EventReader<GameEvent>,
+    results: Option<Res<GameResults>>,
+) {
+    let mut changed = false;
+    let mut entries = results.map(|r| r.0.clone()).unwrap_or_default();
+    for ev in evr.iter() {
+        let MwEv::Player {
+            plid,
+            ev: PlayerEv::GameResult { rank, tiles, mines_hit, lives, eliminated_secs },
+        } = ev.ev else {
+            continue;
+        };
+        // every plid we control gets its own copy of the event
+        entries.retain(|r| r.plid != plid);
+        entries.push(PlayerResult {
+            plid,
+            rank,
+            tiles,
+            mines_hit,
+            lives,
+            eliminated_secs,
+        });
+        changed = true;
+    }
+    if changed {
+        entries.sort_by_key(|r| (r.rank, r.plid));
+        commands.insert_resource(GameResults(entries));
+    }
+}
+
+fn spawn_results(
+    mut commands: Commands,
+    settings: Res<AllSettings>,
+    uiassets: Res<UiAssets>,
+    results: Res<GameResults>,
+    q_root: Query<Entity, With<UiRoot>>,
+    q_panel: Query<Entity, With<ResultsPanel>>,
+) {
+    let Ok(root) = q_root.get_single() else {
+        return;
+    };
+    for e in &q_panel {
+        commands.entity(e).despawn_recursive();
+    }
+
+    let text_style = TextStyle {
+        font: uiassets.font.clone(),
+        font_size: 24.0 * settings.ui.text_scale,
+        color: settings.ui.color_text.into(),
+    };
+    let text_style_heading = TextStyle {
+        font: uiassets.font_bold.clone(),
+        ..text_style.clone()
+    };
+
+    let panel = commands.spawn((
+        ResultsPanel,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(20.0),
+                right: Val::Percent(20.0),
+                top: Val::Percent(20.0),
+                bottom: Val::Auto,
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Stretch,
+                justify_content: JustifyContent::Start,
+                padding: UiRect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.8)),
+            z_index: ZIndex::Global(10),
+            ..Default::default()
+        },
+    )).id();
+
+    let title = commands.spawn((
+        L10nKey("results-title".into()),
+        TextBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                margin: UiRect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            text: Text::from_section(
+                "results-title",
+                TextStyle {
+                    font_size: 48.0 * settings.ui.text_scale,
+                    ..text_style_heading.clone()
+                },
+            ),
+            ..Default::default()
+        },
+    )).id();
+    commands.entity(panel).push_children(&[title]);
+
+    let headings = [
+        "results-rank", "results-player", "results-tiles",
+        "results-mines", "results-lives", "results-eliminated",
+    ];
+    let cells: Vec<_> = headings.iter().map(|key| {
+        commands.spawn((
+            L10nKey((*key).into()),
+            TextBundle {
+                text: Text::from_section(*key, text_style_heading.clone()),
+                ..Default::default()
+            },
+        )).id()
+    }).collect();
+    let row = spawn_results_row(&mut commands, &cells);
+    commands.entity(panel).push_children(&[row]);
+
+    for result in results.0.iter() {
+        let plid_color = settings.player_colors.visible
+            .get(result.plid.i())
+            .map(|lcha| (*lcha).into())
+            .unwrap_or(text_style.color);
+        let values = [
+            format!("{}", result.rank),
+            format!("{}", u8::from(result.plid)),
+            format!("{}", result.tiles),
+            format!("{}", result.mines_hit),
+            format!("{}", result.lives),
+            match result.eliminated_secs {
+                Some(secs) => format!("{}:{:02}", secs / 60, secs % 60),
+                None => "-".into(),
+            },
+        ];
+        let cells: Vec<_> = values.into_iter().enumerate().map(|(i, value)| {
+            let style = if i == 1 {
+                TextStyle {
+                    color: plid_color,
+                    ..text_style_heading.clone()
+                }
+            } else {
+                text_style.clone()
+            };
+            commands.spawn((
+                TextBundle {
+                    text: Text::from_section(value, style),
+                    ..Default::default()
+                },
+            )).id()
+        }).collect();
+        let row = spawn_results_row(&mut commands, &cells);
+        commands.entity(panel).push_children(&[row]);
+    }
+
+    let butt = commands.spawn((
+        OnClick::new().cli("results_close"),
+        ButtonBundle {
+            background_color: BackgroundColor(settings.ui.color_menu_button.into()),
+            style: Style {
+                align_self: AlignSelf::Center,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                padding: UiRect::all(Val::Px(4.0)),
+                margin: UiRect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )).id();
+    let butt_text = commands.spawn((
+        L10nKey("results-button-close".into()),
+        TextBundle {
+            text: Text::from_section(
+                "results-button-close",
+                TextStyle {
+                    font_size: 32.0 * settings.ui.text_scale,
+                    ..text_style.clone()
+                },
+            ),
+            ..Default::default()
+        },
+    )).id();
+    commands.entity(butt).push_children(&[butt_text]);
+    commands.entity(panel).push_children(&[butt]);
+
+    commands.entity(root).push_children(&[panel]);
+}
+
+fn spawn_results_row(
+    commands: &mut Commands,
+    cells: &[Entity],
+) -> Entity {
+    let row = commands.spawn((
+        NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::SpaceBetween,
+                padding: UiRect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )).id();
+    for cell in cells {
+        let wrapper = commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0 / 6.0),
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )).id();
+        commands.entity(wrapper).push_children(&[*cell]);
+        commands.entity(row).push_children(&[wrapper]);
+    }
+    row
+}
+
+fn cli_results_close(
+    mut appstate: ResMut<NextState<AppState>>,
+    mut gamemode: ResMut<NextState<GameMode>>,
+    mut session: ResMut<NextState<SessionKind>>,
+) {
+    appstate.set(AppState::MainMenu);
+    gamemode.set(GameMode::NoGame);
+    session.set(SessionKind::Disconnected);
+}